@@ -0,0 +1,167 @@
+/// Audits how well the dictionary covers the corpus
+use std::collections::{HashMap, BTreeMap};
+use std::sync::Arc;
+use core::fmt::{self, Display, Formatter};
+use serde::Serialize;
+use super::read::{Dict, strip};
+use super::frequency::tokens;
+
+/// Likely reason a token was not counted.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Cause {
+	/// Contains characters outside of ASCII, which `strip` removes.
+	NonAscii,
+	/// Mixes letters and digits.
+	Alphanumeric,
+	/// Part of a hyphenated compound, which the tokenizer splits.
+	Hyphenated,
+	/// Followed by an apostrophe the tokenizer does not strip.
+	Possessive,
+	/// A plain word missing from the dictionary.
+	Unknown,
+}
+
+/// Collects dictionary hits and misses over a set of articles.
+pub struct Audit {
+	dict: Arc<Dict>,
+	articles: usize,
+	hits: HashMap<u32, u64>,
+	misses: HashMap<(Cause, String), u64>,
+}
+
+/// Result of an audit.
+#[derive(Serialize, Debug)]
+pub struct AuditReport {
+	pub articles: usize,
+	pub hits: u64,
+	pub misses: u64,
+	/// Dictionary words that never occurred.
+	pub unused: Vec<String>,
+	/// Number of missed tokens per cause.
+	pub causes: BTreeMap<Cause, u64>,
+	/// Most frequent missed tokens per cause, with their counts.
+	pub missed: BTreeMap<Cause, Vec<(String, u64)>>,
+}
+
+impl Audit {
+	/// Create an empty audit of a dictionary.
+	pub fn new (dict: Arc<Dict>) -> Audit {
+		Audit {
+			dict,
+			articles: 0,
+			hits: HashMap::new(),
+			misses: HashMap::new(),
+		}
+	}
+
+	/// Tokenize an article the same way `Frequency::insert` does and record every token.
+	///
+	/// Tokens without any letters (numbers, punctuation) are ignored.
+	pub fn insert (&mut self, article: &str) {
+		self.articles += 1;
+
+		for (s, e) in tokens(article) {
+			let token = &article[s..e];
+
+			if !token.chars().any(|c| c.is_alphabetic()) {
+				continue;
+			}
+
+			let stripped = strip(token);
+
+			if let Some(i) = stripped.as_ref().and_then(|w| self.dict.get(w)) {
+				*self.hits.entry(*i).or_insert(0) += 1;
+				continue;
+			}
+
+			// whether the token is joined to another word by a hyphen
+			fn joined<I: Iterator<Item = char>> (mut it: I) -> bool {
+				matches!((it.next(), it.next()), (Some('-'), Some(c)) if c.is_alphanumeric())
+			}
+
+			let cause = if !token.is_ascii() {
+				Cause::NonAscii
+			} else if token.chars().any(|c| c.is_ascii_digit()) {
+				Cause::Alphanumeric
+			} else if joined(article[..s].chars().rev()) || joined(article[e..].chars()) {
+				Cause::Hyphenated
+			} else if matches!(article[e..].chars().next(), Some('\'' | '\u{2019}')) {
+				Cause::Possessive
+			} else {
+				Cause::Unknown
+			};
+
+			let key = match (cause, stripped) {
+				(Cause::NonAscii, _) | (_, None) => token.to_lowercase(),
+				(_, Some(w)) => w
+			};
+
+			*self.misses.entry((cause, key)).or_insert(0) += 1;
+		}
+	}
+
+	/// Summarize the audit, keeping the `top` most frequent misses per cause.
+	pub fn report (&self, top: usize) -> AuditReport {
+		let mut unused: Vec<String> = self.dict.iter()
+			.filter(|(_, v)| !self.hits.contains_key(v))
+			.map(|(k, _)| k.clone())
+			.collect();
+
+		unused.sort_unstable();
+
+		let mut causes: BTreeMap<Cause, u64> = BTreeMap::new();
+		let mut missed: BTreeMap<Cause, Vec<(String, u64)>> = BTreeMap::new();
+
+		for ((cause, word), count) in self.misses.iter() {
+			*causes.entry(*cause).or_insert(0) += count;
+			missed.entry(*cause)
+				.or_insert_with(Vec::new)
+				.push((word.clone(), *count));
+		}
+
+		for v in missed.values_mut() {
+			v.sort_unstable_by(|(a, x), (b, y)| y.cmp(x).then_with(|| a.cmp(b)));
+			v.truncate(top);
+		}
+
+		AuditReport {
+			articles: self.articles,
+			hits: self.hits.values().sum(),
+			misses: causes.values().sum(),
+			unused,
+			causes,
+			missed,
+		}
+	}
+}
+
+impl Display for AuditReport {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		let total = (self.hits + self.misses).max(1) as f64;
+
+		writeln!(f, "Articles:   {}", self.articles)?;
+		writeln!(f, "Counted:    {} tokens ({:.2}%)", self.hits, self.hits as f64 / total * 100.)?;
+		writeln!(f, "Missed:     {} tokens ({:.2}%)", self.misses, self.misses as f64 / total * 100.)?;
+
+		for (cause, c) in self.causes.iter() {
+			writeln!(f, "  {:<14} {:>12}", format!("{:?}", cause), c)?;
+		}
+		writeln!(f)?;
+
+		writeln!(f, "Unused dictionary words: {}", self.unused.len())?;
+		for w in self.unused.iter() {
+			writeln!(f, "  {}", w)?;
+		}
+
+		for (cause, words) in self.missed.iter() {
+			writeln!(f)?;
+			writeln!(f, "Most frequent misses ({:?}):", cause)?;
+
+			for (w, c) in words.iter() {
+				writeln!(f, "  {:<24} {:>12}", w, c)?;
+			}
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,167 @@
+/// Module scoring similarity data against human judgments of word pairs
+use core::str::FromStr;
+use core::fmt::{self, Display, Formatter};
+use std::collections::BTreeSet;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use super::similarity::Similarity;
+use super::drift::spearman;
+use super::error::*;
+
+/// Layout of a benchmark file.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+	/// WordSim-353, `word,word,score` or tab separated, with or without a header.
+	WordSim,
+	/// SimLex-999, tab separated with a header, the score in the fourth column.
+	SimLex,
+	/// MEN, space separated, words optionally tagged with their part of speech as in `sun-n`.
+	Men,
+}
+
+impl FromStr for Format {
+	type Err = Error;
+
+	fn from_str (s: &str) -> Result<Format> {
+		Ok(match s.to_lowercase().as_str() {
+			"wordsim" | "ws353" => Format::WordSim,
+			"simlex" => Format::SimLex,
+			"men" => Format::Men,
+			_ => return Err(ErrorKind::Format.into())
+		})
+	}
+}
+
+impl Format {
+	/// Format named by a file, WordSim-353 unless the name mentions SimLex or MEN.
+	pub fn guess <P: AsRef<Path>> (fname: P) -> Format {
+		let name = fname.as_ref()
+			.file_name()
+			.map(|n| n.to_string_lossy().to_lowercase())
+			.unwrap_or_default();
+
+		match name {
+			n if n.contains("simlex") => Format::SimLex,
+			n if n.starts_with("men") => Format::Men,
+			_ => Format::WordSim,
+		}
+	}
+}
+
+/// Word pair judged by people.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Judgment {
+	pub a: String,
+	pub b: String,
+	pub score: f64,
+}
+
+/// Read the pairs of a benchmark file, skipping headers and lines without a score.
+pub fn read <P: AsRef<Path>> (fname: P, format: Format) -> Result<Vec<Judgment>> {
+	let text = std::fs::read_to_string(fname).map_err(|_| ErrorKind::Io)?;
+
+	let column = match format {
+		Format::SimLex => 3,
+		_ => 2
+	};
+
+	let word = |w: &str| -> String {
+		let w = w.trim().trim_matches('"');
+
+		match (format, w.rsplit_once('-')) {
+			(Format::Men, Some((w, pos))) if pos.len() == 1 => w.to_lowercase(),
+			_ => w.to_lowercase()
+		}
+	};
+
+	Ok(text.lines()
+		.filter_map(|l| {
+			let cells: Vec<&str> = match format {
+				Format::WordSim if l.contains(',') => l.split(',').collect(),
+				_ => l.split_whitespace().collect()
+			};
+
+			Some(Judgment {
+				a: word(cells.first()?),
+				b: word(cells.get(1)?),
+				score: cells.get(column)?.trim().parse().ok()?,
+			})
+		})
+		.collect())
+}
+
+/// Agreement of similarity data with a benchmark.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Evaluation {
+	pub benchmark: String,
+	pub format: Format,
+	pub pairs: usize,
+	/// Pairs with both words scored.
+	pub covered: usize,
+	pub coverage: f64,
+	/// Spearman correlation of the scores and judgments of covered pairs.
+	pub spearman: f64,
+	/// Words of the benchmark without scores.
+	pub missing: Vec<String>,
+}
+
+/// Score the pairs of a benchmark with `Similarity::corr`.
+///
+/// Pairs with a word out of the vocabulary, or pruned, are left out of the correlation
+/// and counted against the coverage.
+pub fn evaluate (sim: &dyn Similarity, benchmark: &str, format: Format, pairs: &[Judgment]) -> Evaluation {
+	let mut missing: BTreeSet<String> = BTreeSet::new();
+	let mut human: Vec<f64> = Vec::new();
+	let mut scores: Vec<f64> = Vec::new();
+
+	for p in pairs {
+		for w in [&p.a, &p.b] {
+			if sim.index(w).is_none() {
+				missing.insert(w.clone());
+			}
+		}
+
+		if let Some(s) = sim.corr(&p.a, &p.b) {
+			human.push(p.score);
+			scores.push(s);
+		}
+	}
+
+	Evaluation {
+		benchmark: benchmark.to_owned(),
+		format,
+		pairs: pairs.len(),
+		covered: scores.len(),
+		coverage: scores.len() as f64 / pairs.len().max(1) as f64,
+		spearman: spearman(&human, &scores),
+		missing: missing.into_iter().collect(),
+	}
+}
+
+/// Evaluations of some data on several benchmarks.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Report {
+	/// Data evaluated, such as `frequency/corr`.
+	pub source: String,
+	pub benchmarks: Vec<Evaluation>,
+}
+
+impl Display for Report {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		writeln!(f, "Source: {}", self.source)?;
+		writeln!(f, "{:<32} {:>7} {:>9} {:>9}", "Benchmark", "Pairs", "Coverage", "Spearman")?;
+
+		for e in self.benchmarks.iter() {
+			writeln!(f, "{:<32} {:>7} {:>8.1}% {:>9.4}", e.benchmark, e.pairs, e.coverage * 100., e.spearman)?;
+		}
+
+		for e in self.benchmarks.iter().filter(|e| !e.missing.is_empty()) {
+			writeln!(f)?;
+			writeln!(f, "Missing from {} ({}):", e.benchmark, e.missing.len())?;
+			writeln!(f, "  {}", e.missing.join(" "))?;
+		}
+
+		Ok(())
+	}
+}
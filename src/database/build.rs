@@ -0,0 +1,530 @@
+/// Module computing correlation matrices from sparse word by article counts
+use std::collections::HashMap;
+use std::io::{BufWriter, Write};
+use std::fs::File;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use std::thread;
+use serde::{Serialize, Deserialize};
+use log::{debug, info};
+use super::error::*;
+use super::read::Dict;
+use super::correlation::Pruned;
+use super::matrix::{Precision, Layout};
+use super::measure::{Measure, Pair};
+
+/// Number of cells computed before they are written out.
+const BLOCK_CELLS: usize = 1 << 24;
+
+/// Sparse matrix in compressed sparse row form.
+pub struct Csr {
+	/// Start of every row in `cols` and `vals`, followed by the end of the last row.
+	rows: Vec<usize>,
+	cols: Vec<u32>,
+	vals: Vec<f64>,
+	/// Number of columns.
+	width: usize,
+}
+
+impl Csr {
+	/// Word by article count matrix, row `i` holding the counts of dictionary word `words[i]`.
+	///
+	/// Arguments
+	/// * `dat`: word id to `(article, count)` pairs, as exported by `Frequency::load`.
+	/// * `words`: dictionary ids of the rows.
+	/// * `len`: number of articles.
+	pub fn new (dat: &HashMap<u32, Vec<(u32, u16)>>, words: &[u32], len: usize) -> Csr {
+		let mut rows: Vec<usize> = Vec::with_capacity(words.len() + 1);
+		let mut cols: Vec<u32> = Vec::new();
+		let mut vals: Vec<f64> = Vec::new();
+
+		rows.push(0);
+
+		for w in words {
+			for (a, c) in dat.get(w).map(|e| e.as_slice()).unwrap_or(&[]) {
+				cols.push(*a);
+				vals.push(*c as f64);
+			}
+
+			rows.push(cols.len());
+		}
+
+		Csr { rows, cols, vals, width: len }
+	}
+
+	/// Dictionary ids of the rows of a pruned dictionary, ordered by its indices.
+	pub fn words (pruned: &Dict, dict: &Dict) -> Vec<u32> {
+		let mut words: Vec<(&String, &u32)> = pruned.iter().collect();
+
+		words.sort_unstable_by_key(|(_, i)| **i);
+
+		words.into_iter()
+			.map(|(k, _)| dict[k])
+			.collect()
+	}
+
+	/// Number of rows.
+	pub fn len (&self) -> usize {
+		self.rows.len() - 1
+	}
+
+	/// Number of columns.
+	pub fn width (&self) -> usize {
+		self.width
+	}
+
+	/// Product with the dense column `v`.
+	pub fn mul (&self, v: &[f64]) -> Vec<f64> {
+		(0..self.len())
+			.map(|i| {
+				let (c, x) = self.row(i);
+				c.iter().zip(x).map(|(c, x)| x * v[*c as usize]).sum()
+			})
+			.collect()
+	}
+
+	/// Column indices and values of the stored cells of row `i`.
+	pub fn row (&self, i: usize) -> (&[u32], &[f64]) {
+		let (s, e) = (self.rows[i], self.rows[i + 1]);
+
+		(&self.cols[s..e], &self.vals[s..e])
+	}
+
+	/// Transposed matrix, its rows listing columns in ascending order.
+	pub fn transpose (&self) -> Csr {
+		let mut rows: Vec<usize> = vec![0; self.width + 1];
+
+		for c in self.cols.iter() {
+			rows[*c as usize + 1] += 1;
+		}
+
+		for i in 0..self.width {
+			rows[i + 1] += rows[i];
+		}
+
+		let mut next = rows.clone();
+		let mut cols: Vec<u32> = vec![0; self.cols.len()];
+		let mut vals: Vec<f64> = vec![0.; self.vals.len()];
+
+		for i in 0..self.len() {
+			let (c, v) = self.row(i);
+
+			for (c, v) in c.iter().zip(v) {
+				let k = &mut next[*c as usize];
+
+				cols[*k] = i as u32;
+				vals[*k] = *v;
+				*k += 1;
+			}
+		}
+
+		Csr { rows, cols, vals, width: self.len() }
+	}
+}
+
+/// Per word sums over all articles, including those the word is absent from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Marginals {
+	/// Number of articles.
+	pub len: usize,
+	/// Number of articles containing the word.
+	pub nnz: Vec<usize>,
+	pub mean: Vec<f64>,
+	/// Sum of deviations from the mean over the articles containing the word.
+	pub sum: Vec<f64>,
+	/// Sum of squared deviations from the mean over all articles.
+	pub sum2: Vec<f64>,
+	/// Sum of squared counts.
+	pub sq: Vec<f64>,
+}
+
+impl Marginals {
+	pub fn new (x: &Csr) -> Marginals {
+		let len = x.width;
+		let rows = (0..x.len()).map(|i| x.row(i).1);
+
+		let mean: Vec<f64> = rows.clone()
+			.map(|v| v.iter().sum::<f64>() / len as f64)
+			.collect();
+
+		let sum: Vec<f64> = rows.clone()
+			.zip(mean.iter())
+			.map(|(v, m)| v.iter().map(|c| c - m).sum())
+			.collect();
+
+		let sum2: Vec<f64> = rows.clone()
+			.zip(mean.iter())
+			.map(|(v, m)| v.iter().map(|c| (c - m) * (c - m)).sum::<f64>() + (len - v.len()) as f64 * m * m)
+			.collect();
+
+		Marginals {
+			len,
+			nnz: rows.clone().map(|v| v.len()).collect(),
+			mean,
+			sum,
+			sum2,
+			sq: rows.map(|v| v.iter().map(|c| c * c).sum()).collect(),
+		}
+	}
+
+	/// Statistics of the pair of a word with itself.
+	pub fn pair (&self, i: usize) -> Pair {
+		Pair {
+			dot: self.sq[i],
+			co: self.nnz[i] as u32,
+		}
+	}
+}
+
+/// State of an interrupted build, saved after every block of rows.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Checkpoint {
+	/// Number of articles.
+	pub len: usize,
+	pub precision: Precision,
+	pub measure: Measure,
+	pub shrink: Option<f64>,
+	/// Pruned dictionary giving the row of every word.
+	pub dict: Dict,
+	pub pruned: HashMap<String, Pruned>,
+	pub marginals: Marginals,
+	/// Number of complete rows in the matrix file.
+	pub rows: usize,
+}
+
+impl Checkpoint {
+	/// Read a checkpoint, `None` if there is no readable one.
+	pub fn load <P: AsRef<Path>> (fname: P) -> Option<Checkpoint> {
+		bincode::deserialize_from(File::open(fname).ok()?).ok()
+	}
+
+	/// Write the checkpoint, replacing the previous one only once it is complete.
+	pub fn save <P: AsRef<Path>> (&self, fname: P) -> Result<()> {
+		let tmp = fname.as_ref().with_extension("ckpt.tmp");
+
+		let mut w = BufWriter::new(File::create(&tmp).map_err(|_| ErrorKind::Io)?);
+		bincode::serialize_into(&mut w, self).map_err(|_| ErrorKind::Io)?;
+		w.flush().map_err(|_| ErrorKind::Io)?;
+		drop(w);
+
+		std::fs::rename(&tmp, fname).map_err(|_| ErrorKind::Io)?;
+
+		Ok(())
+	}
+}
+
+/// Format a duration in seconds as `h:mm:ss`.
+pub fn clock (secs: f64) -> String {
+	let s = secs.max(0.).round() as u64;
+
+	format!("{}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60)
+}
+
+/// Write the cells left of the diagonal of `f(x · xᵀ)`, row by row.
+///
+/// Rows are computed in blocks of about `BLOCK_CELLS` cells, which `threads` workers
+/// take one row at a time. Each worker accumulates the dot products of a row against
+/// every earlier row in a dense buffer, by walking the articles of the row through
+/// the transposed matrix.
+///
+/// After every block `w` is flushed and `done` is called with the number of complete rows,
+/// the rate and remaining time are logged.
+///
+/// Arguments
+/// * `x`: word by article matrix.
+/// * `start`: first row to compute, earlier rows having been written before.
+/// * `threads`: number of workers.
+/// * `precision`: encoding of the written cells.
+/// * `w`: output, receiving the cells of rows `start..x.len()`.
+/// * `f`: cell value of rows `i > j` given their pair statistics.
+/// * `done`: called after every block.
+pub fn lower <W, F, D> (x: &Csr, start: usize, threads: usize, precision: Precision, w: &mut W, f: F, mut done: D) -> Result<()>
+	where W: Write, F: Fn(usize, usize, Pair) -> f64 + Sync, D: FnMut(usize) -> Result<()>,
+{
+	let t = x.transpose();
+	let n = x.len();
+
+	let tri = |r: usize| Layout::Triangular.cells(r as u64) as f64;
+	let started = Instant::now();
+
+	let mut r0 = start;
+
+	while r0 < n {
+		let mut r1 = r0 + 1;
+		let mut cells = r0;
+
+		while r1 < n && cells + r1 <= BLOCK_CELLS {
+			cells += r1;
+			r1 += 1;
+		}
+
+		let next = AtomicUsize::new(r0);
+
+		let work = || {
+			let mut acc: Vec<f64> = vec![0.; r1];
+			let mut co: Vec<u32> = vec![0; r1];
+			let mut out: Vec<(usize, Vec<u8>)> = Vec::new();
+
+			loop {
+				let i = next.fetch_add(1, Ordering::Relaxed);
+
+				if i >= r1 {
+					return out;
+				}
+
+				let (cols, vals) = x.row(i);
+
+				for (a, v) in cols.iter().zip(vals) {
+					let (ws, cs) = t.row(*a as usize);
+					let end = ws.partition_point(|j| (*j as usize) < i);
+
+					for (j, c) in ws[..end].iter().zip(&cs[..end]) {
+						acc[*j as usize] += v * c;
+						co[*j as usize] += 1;
+					}
+				}
+
+				let mut row: Vec<u8> = Vec::with_capacity(i * precision.width());
+
+				for (j, (dot, co)) in acc[..i].iter().zip(&co[..i]).enumerate() {
+					precision.encode(f(i, j, Pair { dot: *dot, co: *co }), &mut row);
+				}
+
+				acc[..i].fill(0.);
+				co[..i].fill(0);
+				out.push((i, row));
+			}
+		};
+
+		let mut rows: Vec<(usize, Vec<u8>)> = thread::scope(|s| {
+			let workers: Vec<_> = (0..threads.max(1))
+				.map(|_| s.spawn(&work))
+				.collect();
+
+			workers.into_iter()
+				.flat_map(|h| h.join().unwrap())
+				.collect()
+		});
+
+		rows.sort_unstable_by_key(|e| e.0);
+
+		for (_, row) in rows.iter() {
+			w.write_all(row).map_err(|_| ErrorKind::Io)?;
+		}
+
+		w.flush().map_err(|_| ErrorKind::Io)?;
+		done(r1)?;
+
+		// later rows are longer, so the remaining time follows the cell rate
+		let secs = started.elapsed().as_secs_f64();
+		let rate = (tri(r1) - tri(start)) / secs;
+
+		info!("Computed {} of {} rows, {:.1} rows/s, ETA {}.",
+			r1, n, (r1 - start) as f64 / secs, clock((tri(n) - tri(r1)) / rate));
+		debug!(target: "app::dump", "Computed rows {} to {} of {}.", r0, r1, n);
+
+		r0 = r1;
+	}
+
+	Ok(())
+}
+
+/// Compute `f(x · xᵀ)` as a sparse matrix, leaving out the diagonal and cells where `f` is 0.
+///
+/// Rows are computed like in `lower`, by `threads` workers taking one row at a time,
+/// but against every other row.
+pub fn sparse <F> (x: &Csr, threads: usize, f: F) -> Csr
+	where F: Fn(usize, usize, Pair) -> f64 + Sync,
+{
+	let t = x.transpose();
+	let n = x.len();
+
+	let next = AtomicUsize::new(0);
+
+	let work = || {
+		let mut acc: Vec<f64> = vec![0.; n];
+		let mut co: Vec<u32> = vec![0; n];
+		let mut touched: Vec<u32> = Vec::new();
+		let mut out: Vec<(usize, Vec<u32>, Vec<f64>)> = Vec::new();
+
+		loop {
+			let i = next.fetch_add(1, Ordering::Relaxed);
+
+			if i >= n {
+				return out;
+			}
+
+			let (cols, vals) = x.row(i);
+
+			for (a, v) in cols.iter().zip(vals) {
+				let (ws, cs) = t.row(*a as usize);
+
+				for (j, c) in ws.iter().zip(cs) {
+					if co[*j as usize] == 0 {
+						touched.push(*j);
+					}
+
+					acc[*j as usize] += v * c;
+					co[*j as usize] += 1;
+				}
+			}
+
+			touched.sort_unstable();
+
+			let (mut rc, mut rv): (Vec<u32>, Vec<f64>) = (Vec::new(), Vec::new());
+
+			for j in touched.drain(..) {
+				let j = j as usize;
+				let v = f(i, j, Pair { dot: acc[j], co: co[j] });
+
+				if j != i && v != 0. {
+					rc.push(j as u32);
+					rv.push(v);
+				}
+
+				acc[j] = 0.;
+				co[j] = 0;
+			}
+
+			out.push((i, rc, rv));
+		}
+	};
+
+	let mut rows: Vec<(usize, Vec<u32>, Vec<f64>)> = thread::scope(|s| {
+		let workers: Vec<_> = (0..threads.max(1))
+			.map(|_| s.spawn(&work))
+			.collect();
+
+		workers.into_iter()
+			.flat_map(|h| h.join().unwrap())
+			.collect()
+	});
+
+	rows.sort_unstable_by_key(|e| e.0);
+
+	let mut m = Csr { rows: vec![0], cols: Vec::new(), vals: Vec::new(), width: n };
+
+	for (_, c, v) in rows {
+		m.cols.extend(c);
+		m.vals.extend(v);
+		m.rows.push(m.cols.len());
+	}
+
+	debug!(target: "app::dump", "Computed sparse product with {} cells.", m.cols.len());
+
+	m
+}
+
+/// Value a score is ordered by, not a number scores such as those of words without
+/// variance ranking below all others.
+pub fn key (v: f64) -> f64 {
+	if v.is_nan() { f64::NEG_INFINITY } else { v }
+}
+
+/// Order of scored cells, highest score first, ties by ascending column.
+///
+/// Not a number scores come last.
+pub fn by_score (a: &(u32, f64), b: &(u32, f64)) -> std::cmp::Ordering {
+	key(b.1).total_cmp(&key(a.1)).then(a.0.cmp(&b.0))
+}
+
+/// The `k` highest scoring cells of a row, in `by_score` order.
+pub fn select (mut row: Vec<(u32, f64)>, k: usize) -> Vec<(u32, f64)> {
+	if k < row.len() {
+		row.select_nth_unstable_by(k, by_score);
+		row.truncate(k);
+	}
+
+	row.sort_unstable_by(by_score);
+	row
+}
+
+/// Write the `k` highest cells of every row of `f(x · xᵀ)` in `by_score` order, diagonal included.
+///
+/// Rows are computed like in `sparse`, but every cell is scored, as pairs without shared
+/// articles may still rank among the highest for some measures. Each cell is written as
+/// its column and value, both big endian `u32` and `f32`.
+///
+/// Blocks of rows holding about `BLOCK_CELLS` scores are computed before they are written
+/// to `w`, the rate and remaining time are logged after each.
+pub fn top <W, F> (x: &Csr, k: usize, threads: usize, w: &mut W, f: F) -> Result<()>
+	where W: Write, F: Fn(usize, usize, Pair) -> f64 + Sync,
+{
+	let t = x.transpose();
+	let n = x.len();
+	let step = (BLOCK_CELLS / n.max(1)).max(1);
+
+	let started = Instant::now();
+
+	for r0 in (0..n).step_by(step) {
+		let r1 = (r0 + step).min(n);
+		let next = AtomicUsize::new(r0);
+
+		let work = || {
+			let mut acc: Vec<f64> = vec![0.; n];
+			let mut co: Vec<u32> = vec![0; n];
+			let mut out: Vec<(usize, Vec<u8>)> = Vec::new();
+
+			loop {
+				let i = next.fetch_add(1, Ordering::Relaxed);
+
+				if i >= r1 {
+					return out;
+				}
+
+				let (cols, vals) = x.row(i);
+
+				for (a, v) in cols.iter().zip(vals) {
+					let (ws, cs) = t.row(*a as usize);
+
+					for (j, c) in ws.iter().zip(cs) {
+						acc[*j as usize] += v * c;
+						co[*j as usize] += 1;
+					}
+				}
+
+				let row: Vec<(u32, f64)> = (0..n)
+					.map(|j| (j as u32, f(i, j, Pair { dot: acc[j], co: co[j] })))
+					.collect();
+
+				acc.fill(0.);
+				co.fill(0);
+
+				let mut buf: Vec<u8> = Vec::with_capacity(k.min(n) * 8);
+
+				for (j, v) in select(row, k) {
+					buf.extend(j.to_be_bytes());
+					buf.extend((v as f32).to_be_bytes());
+				}
+
+				out.push((i, buf));
+			}
+		};
+
+		let mut rows: Vec<(usize, Vec<u8>)> = thread::scope(|s| {
+			let workers: Vec<_> = (0..threads.max(1))
+				.map(|_| s.spawn(&work))
+				.collect();
+
+			workers.into_iter()
+				.flat_map(|h| h.join().unwrap())
+				.collect()
+		});
+
+		rows.sort_unstable_by_key(|e| e.0);
+
+		for (_, row) in rows.iter() {
+			w.write_all(row).map_err(|_| ErrorKind::Io)?;
+		}
+
+		w.flush().map_err(|_| ErrorKind::Io)?;
+
+		let secs = started.elapsed().as_secs_f64();
+		let rate = r1 as f64 / secs;
+
+		info!("Ranked {} of {} rows, {:.1} rows/s, ETA {}.", r1, n, rate, clock((n - r1) as f64 / rate));
+	}
+
+	Ok(())
+}
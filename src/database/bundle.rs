@@ -0,0 +1,301 @@
+/// Module packing similarity data and everything a game needs into a single file
+use std::path::Path;
+use std::collections::HashMap;
+use std::io::{BufWriter, Read, Write, Seek, SeekFrom};
+use std::fs::File;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use memmap2::{Mmap, MmapOptions};
+use super::read::Dict;
+use super::correlation::{Correlation, Pruned};
+use super::embedding::{self, Embedding};
+use super::topk::{self, TopK};
+use super::support::Postings;
+use super::matrix::{Header, Precision, Layout};
+use super::measure::Measure;
+use super::similarity::{Similarity, Source, Index};
+use super::error::*;
+
+/// Bytes identifying a bundle.
+pub const MAGIC: &[u8; 4] = b"WKDB";
+/// Current version of the bundle format.
+pub const VERSION: u8 = 1;
+/// Size of the header in bytes.
+pub const HEADER: usize = 16;
+/// Size of an entry of the section table in bytes.
+pub const ENTRY: usize = 24;
+
+/// Build metadata, as JSON.
+const META: &[u8; 4] = b"META";
+/// Dictionary and pruned words, as bincode.
+const DICT: &[u8; 4] = b"DICT";
+/// Data file of the similarity source.
+const DATA: &[u8; 4] = b"DATA";
+/// Articles of every word, kept for correlation data built with support.
+const SUPP: &[u8; 4] = b"SUPP";
+/// Answer list, one word per line.
+const ANSW: &[u8; 4] = b"ANSW";
+
+/// Kind of similarity data bundled.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+	Correlation,
+	Embedding,
+	TopK,
+}
+
+/// Description of the bundled data.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Metadata {
+	pub kind: Kind,
+	/// Version of wikidle that wrote the bundle.
+	pub version: String,
+	/// Seconds since the Unix epoch when the bundle was written.
+	pub created: u64,
+	/// Words with scores.
+	pub words: usize,
+	/// Words left out of the data.
+	pub pruned: usize,
+	pub answers: usize,
+	/// Options correlation data was built with.
+	pub measure: Option<Measure>,
+	pub precision: Option<Precision>,
+	pub layout: Option<Layout>,
+	/// Dimension of embeddings.
+	pub dim: Option<usize>,
+	/// Neighbors kept per word.
+	pub k: Option<usize>,
+}
+
+/// Position of a section in a bundle.
+#[derive(Debug, Clone, Copy)]
+struct Section {
+	tag: [u8; 4],
+	offset: u64,
+	len: u64,
+	crc: u32,
+}
+
+/// Writer computing the checksum of a section.
+struct Checked<'a, W: Write> {
+	w: &'a mut W,
+	len: u64,
+	crc: crc32fast::Hasher,
+}
+
+impl<'a, W: Write> Write for Checked<'a, W> {
+	fn write (&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		let n = self.w.write(buf)?;
+
+		self.crc.update(&buf[..n]);
+		self.len += n as u64;
+
+		Ok(n)
+	}
+
+	fn flush (&mut self) -> std::io::Result<()> {
+		self.w.flush()
+	}
+}
+
+/// Similarity data with its answer list and metadata, loaded from a bundle.
+///
+/// A bundle starts with a header (magic, version, 3 zero bytes, the number of sections
+/// and 0 as big endian `u32`), followed by a table of sections. Every entry holds a tag,
+/// the offset of the section from the start of the bundle and its length as big endian `u64`,
+/// then the CRC-32 of the section as a big endian `u32`. Sections follow the table, so a
+/// bundle may be moved and copied as it is.
+pub struct Bundle {
+	pub metadata: Metadata,
+	pub sim: Box<dyn Similarity>,
+	pub answers: Vec<String>,
+}
+
+impl Bundle {
+	/// Bundle the similarity data of `source` with an answer list into `fname`.
+	///
+	/// Stopwords the data was built without stay among the pruned words of the dictionary.
+	/// Bundles of bundles are refused with `Err(ErrorKind::Unsupported)`.
+	pub fn write <P: AsRef<Path>> (source: &Source, answers: &[String], fname: P) -> Result<Metadata> {
+		let (kind, index) = match source {
+			Source::Correlation(p) => (Kind::Correlation, p),
+			Source::Embedding(p) => (Kind::Embedding, p),
+			Source::TopK(p) => (Kind::TopK, p),
+			Source::Bundle(_) => return Err(ErrorKind::Unsupported.into()),
+		};
+
+		let index = Index::read(index)?;
+
+		let mut data = File::open(&index.fname).map_err(|_| ErrorKind::Io)?;
+		let size = data.metadata().map_err(|_| ErrorKind::Io)?.len();
+		let mut head = [0u8; 16];
+		data.read_exact(&mut head).map_err(|_| ErrorKind::Format)?;
+		data.seek(SeekFrom::Start(0)).map_err(|_| ErrorKind::Io)?;
+
+		let field = |r: std::ops::Range<usize>| u32::from_be_bytes(head[r].try_into().unwrap()) as usize;
+		let header = match kind {
+			Kind::Correlation => Some(Header::read(&mut &head[..], index.dict.len() as u64, size)?),
+			_ => None
+		};
+
+		let metadata = Metadata {
+			kind,
+			version: env!("CARGO_PKG_VERSION").to_owned(),
+			created: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+			words: index.dict.len(),
+			pruned: index.pruned.len(),
+			answers: answers.len(),
+			measure: header.map(|h| h.measure),
+			precision: header.map(|h| h.precision),
+			layout: header.map(|h| h.layout),
+			dim: (kind == Kind::Embedding && &head[0..4] == embedding::MAGIC).then(|| field(8..12)),
+			k: (kind == Kind::TopK && &head[0..4] == topk::MAGIC).then(|| field(8..12)),
+		};
+
+		let support = match kind {
+			Kind::Correlation => File::open(index.fname.with_extension("support")).ok(),
+			_ => None
+		};
+
+		let lines = |words: &mut dyn Iterator<Item = &String>| -> Vec<u8> {
+			words.flat_map(|w| w.bytes().chain([b'\n'])).collect()
+		};
+
+		// small sections are kept in memory, data files are streamed
+		let mut sections: Vec<([u8; 4], Box<dyn Read>)> = vec![
+			(*META, Box::new(std::io::Cursor::new(serde_json::to_vec(&metadata).map_err(|_| ErrorKind::Serialization)?))),
+			(*DICT, Box::new(std::io::Cursor::new(bincode::serialize(&(&index.dict, &index.pruned)).map_err(|_| ErrorKind::Serialization)?))),
+			(*ANSW, Box::new(std::io::Cursor::new(lines(&mut answers.iter())))),
+			(*DATA, Box::new(data)),
+		];
+
+		if let Some(s) = support {
+			sections.push((*SUPP, Box::new(s)));
+		}
+
+		let mut w = BufWriter::new(File::create(&fname).map_err(|_| ErrorKind::Io)?);
+		let mut offset = (HEADER + sections.len() * ENTRY) as u64;
+		let mut table: Vec<Section> = Vec::with_capacity(sections.len());
+
+		w.write_all(&vec![0u8; offset as usize]).map_err(|_| ErrorKind::Io)?;
+
+		for (tag, mut r) in sections {
+			let mut c = Checked { w: &mut w, len: 0, crc: crc32fast::Hasher::new() };
+
+			std::io::copy(&mut r, &mut c).map_err(|_| ErrorKind::Io)?;
+
+			table.push(Section { tag, offset, len: c.len, crc: c.crc.finalize() });
+			offset += table.last().unwrap().len;
+		}
+
+		let mut buf: Vec<u8> = Vec::with_capacity(HEADER + table.len() * ENTRY);
+
+		buf.extend(MAGIC);
+		buf.extend([VERSION, 0, 0, 0]);
+		buf.extend((table.len() as u32).to_be_bytes());
+		buf.extend(0u32.to_be_bytes());
+
+		for s in table.iter() {
+			buf.extend(s.tag);
+			buf.extend(s.offset.to_be_bytes());
+			buf.extend(s.len.to_be_bytes());
+			buf.extend(s.crc.to_be_bytes());
+		}
+
+		w.seek(SeekFrom::Start(0)).map_err(|_| ErrorKind::Io)?;
+		w.write_all(&buf).map_err(|_| ErrorKind::Io)?;
+		w.flush().map_err(|_| ErrorKind::Io)?;
+
+		Ok(metadata)
+	}
+
+	/// Read the header and section table of a bundle.
+	fn table (data: &[u8]) -> Result<Vec<Section>> {
+		if data.len() < HEADER || &data[0..4] != MAGIC || data[4] != VERSION {
+			return Err(ErrorKind::Format.into());
+		}
+
+		let n = u32::from_be_bytes(data[8..12].try_into().unwrap()) as usize;
+
+		if data.len() < HEADER + n * ENTRY {
+			return Err(ErrorKind::Format.into());
+		}
+
+		let table: Vec<Section> = data[HEADER..HEADER + n * ENTRY]
+			.chunks_exact(ENTRY)
+			.map(|e| Section {
+				tag: e[0..4].try_into().unwrap(),
+				offset: u64::from_be_bytes(e[4..12].try_into().unwrap()),
+				len: u64::from_be_bytes(e[12..20].try_into().unwrap()),
+				crc: u32::from_be_bytes(e[20..24].try_into().unwrap()),
+			})
+			.collect();
+
+		match table.iter().all(|s| s.offset.checked_add(s.len).map_or(false, |e| e <= data.len() as u64)) {
+			true => Ok(table),
+			false => Err(ErrorKind::Format.into())
+		}
+	}
+
+	/// Load a bundle, checking every section against its checksum.
+	///
+	/// The whole file is read once to check it, large data is then mapped to memory.
+	///
+	/// Returns
+	/// * `Err(ErrorKind::Checksum)` if a section was damaged.
+	/// * `Err(ErrorKind::Format)` if the file is not a bundle or lacks a section.
+	pub fn open <P: AsRef<Path>> (fname: P) -> Result<Bundle> {
+		let fname = fname.as_ref();
+		let f = File::open(fname).map_err(|_| ErrorKind::Io)?;
+
+		// Safety: bundles are written once and never modified while in use.
+		let all = unsafe { Mmap::map(&f) }.map_err(|_| ErrorKind::Io)?;
+		let table = Bundle::table(&all)?;
+
+		for s in table.iter() {
+			if crc32fast::hash(&all[s.offset as usize..(s.offset + s.len) as usize]) != s.crc {
+				return Err(ErrorKind::Checksum.into());
+			}
+		}
+
+		let find = |tag: &[u8; 4]| table.iter().find(|s| &s.tag == tag);
+		let bytes = |tag: &[u8; 4]| -> Result<&[u8]> {
+			let s = find(tag).ok_or_else(|| ErrorKind::Format)?;
+			Ok(&all[s.offset as usize..(s.offset + s.len) as usize])
+		};
+		let map = |s: &Section| -> Result<Mmap> {
+			// Safety: as above
+			unsafe { MmapOptions::new().offset(s.offset).len(s.len as usize).map(&f) }.map_err(|_| ErrorKind::Io.into())
+		};
+		let lines = |b: &[u8]| -> Vec<String> {
+			String::from_utf8_lossy(b).lines().filter(|l| !l.is_empty()).map(|l| l.to_owned()).collect()
+		};
+
+		let metadata: Metadata = serde_json::from_slice(bytes(META)?).map_err(|_| ErrorKind::Serialization)?;
+		let (dict, pruned): (Dict, HashMap<String, Pruned>) = bincode::deserialize(bytes(DICT)?)
+			.map_err(|_| ErrorKind::Serialization)?;
+
+		let data = find(DATA).ok_or_else(|| ErrorKind::Format)?;
+		let fname = fname.canonicalize().map_err(|_| ErrorKind::Io)?;
+
+		let sim: Box<dyn Similarity> = match metadata.kind {
+			Kind::Correlation => {
+				let support = match find(SUPP) {
+					Some(s) => Some(Postings::from_map(map(s)?, dict.len())?),
+					None => None
+				};
+
+				Box::new(Correlation::from_map(fname, map(data)?, dict, pruned, support)?)
+			},
+			Kind::Embedding => Box::new(Embedding::from_bytes(fname, bytes(DATA)?, dict, pruned)?),
+			Kind::TopK => Box::new(TopK::from_map(fname, map(data)?, dict, pruned)?),
+		};
+
+		Ok(Bundle {
+			metadata,
+			sim,
+			answers: lines(bytes(ANSW)?),
+		})
+	}
+}
@@ -0,0 +1,343 @@
+/// Module grouping words whose rows or vectors are alike
+use core::str::FromStr;
+use serde::{Serialize, Deserialize};
+use rand::{Rng, SeedableRng, rngs::SmallRng};
+use log::debug;
+use super::similarity::Similarity;
+use super::error::*;
+
+/// Way words are grouped.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Method {
+	/// Spherical k-means, seeded with k-means++.
+	KMeans,
+	/// Average linkage agglomeration, keeping a distance per pair of words.
+	Agglomerative,
+}
+
+impl FromStr for Method {
+	type Err = Error;
+
+	fn from_str (s: &str) -> Result<Method> {
+		Ok(match s.to_lowercase().as_str() {
+			"kmeans" | "k-means" => Method::KMeans,
+			"agglomerative" => Method::Agglomerative,
+			_ => return Err(ErrorKind::Format.into())
+		})
+	}
+}
+
+/// Options of a clustering.
+#[derive(Debug, Clone)]
+pub struct Options {
+	pub method: Method,
+	/// Number of clusters.
+	pub k: usize,
+	/// Number of words naming every cluster.
+	pub labels: usize,
+	/// Maximum number of k-means iterations.
+	pub iterations: usize,
+	/// Seed of the first k-means centroids.
+	pub seed: u64,
+	/// Keep only the columns of the clustered words when vectors are rows of scores,
+	/// bounding the size of correlation rows.
+	pub restrict: bool,
+	/// Most words clustered at once, as restricted rows and agglomeration distances
+	/// both grow with the square of the number of words.
+	pub limit: usize,
+}
+
+impl Default for Options {
+	fn default() -> Options {
+		Options {
+			method: Method::KMeans,
+			k: 20,
+			labels: 5,
+			iterations: 100,
+			seed: 0,
+			restrict: true,
+			limit: 10_000,
+		}
+	}
+}
+
+/// Group of words.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Cluster {
+	/// Words closest to the centroid, naming the cluster.
+	pub labels: Vec<String>,
+	/// Every word of the cluster, closest to the centroid first.
+	pub words: Vec<String>,
+	/// Mean cosine similarity of the words to the centroid.
+	pub cohesion: f64,
+}
+
+/// Groups of a set of words, largest first.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Clustering {
+	pub method: Method,
+	pub k: usize,
+	pub clusters: Vec<Cluster>,
+}
+
+fn dot (a: &[f64], b: &[f64]) -> f64 {
+	a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Scale a vector to unit length, leaving null vectors as they are.
+fn unit (mut v: Vec<f64>) -> Vec<f64> {
+	let norm = dot(&v, &v).sqrt();
+
+	if norm > 0. {
+		v.iter_mut().for_each(|x| *x /= norm);
+	}
+
+	v
+}
+
+/// Unit length sum of some vectors.
+fn centroid (vectors: &[Vec<f64>], members: &[usize], dim: usize) -> Vec<f64> {
+	let mut c: Vec<f64> = vec![0.; dim];
+
+	for m in members {
+		c.iter_mut().zip(&vectors[*m]).for_each(|(c, x)| *c += x);
+	}
+
+	unit(c)
+}
+
+/// Cluster of every vector with spherical k-means.
+fn kmeans (vectors: &[Vec<f64>], k: usize, iterations: usize, seed: u64) -> Vec<usize> {
+	let n = vectors.len();
+	let dim = vectors.first().map_or(0, |v| v.len());
+	let mut rng = SmallRng::seed_from_u64(seed);
+
+	// k-means++, picking each next centroid with probability growing with its distance to the others
+	let mut centroids: Vec<Vec<f64>> = vec![vectors[rng.gen_range(0..n)].clone()];
+	let mut dist: Vec<f64> = vec![f64::INFINITY; n];
+
+	while centroids.len() < k {
+		let last = centroids.last().unwrap();
+
+		for (d, v) in dist.iter_mut().zip(vectors) {
+			*d = d.min((1. - dot(v, last)).max(0.));
+		}
+
+		let total: f64 = dist.iter().sum();
+
+		let next = match total > 0. {
+			true => {
+				let mut x = rng.gen_range(0.0..total);
+				dist.iter().position(|d| { x -= d; x < 0. }).unwrap_or(n - 1)
+			},
+			false => rng.gen_range(0..n)
+		};
+
+		centroids.push(vectors[next].clone());
+	}
+
+	let mut assign: Vec<usize> = vec![usize::MAX; n];
+
+	for it in 0..iterations {
+		let mut changed = 0;
+
+		for (i, v) in vectors.iter().enumerate() {
+			let best = (0..k)
+				.max_by(|a, b| dot(v, &centroids[*a]).total_cmp(&dot(v, &centroids[*b])).then(b.cmp(a)))
+				.unwrap();
+
+			if assign[i] != best {
+				assign[i] = best;
+				changed += 1;
+			}
+		}
+
+		let mut members: Vec<Vec<usize>> = vec![Vec::new(); k];
+
+		for (i, c) in assign.iter().enumerate() {
+			members[*c].push(i);
+		}
+
+		for c in 0..k {
+			if !members[c].is_empty() {
+				continue;
+			}
+
+			// reseed an empty cluster with the word furthest from its own centroid,
+			// taken from a cluster it does not leave empty
+			let far = (0..n)
+				.filter(|i| members[assign[*i]].len() > 1)
+				.min_by(|a, b| dot(&vectors[*a], &centroids[assign[*a]]).total_cmp(&dot(&vectors[*b], &centroids[assign[*b]])));
+
+			if let Some(far) = far {
+				members[assign[far]].retain(|m| *m != far);
+				members[c].push(far);
+				assign[far] = c;
+				changed += 1;
+			}
+		}
+
+		// centroids follow every move, donors included
+		for (c, m) in centroids.iter_mut().zip(members.iter()) {
+			*c = centroid(vectors, m, dim);
+		}
+
+		debug!("K-means iteration {}, {} words moved.", it, changed);
+
+		if changed == 0 {
+			break;
+		}
+	}
+
+	assign
+}
+
+/// Cluster of every vector with average linkage, merging along chains of nearest neighbors.
+fn agglomerative (vectors: &[Vec<f64>], k: usize) -> Vec<usize> {
+	let n = vectors.len();
+	let mut dist: Vec<f32> = vec![0.; n * n];
+
+	for i in 0..n {
+		for j in i + 1..n {
+			let d = (1. - dot(&vectors[i], &vectors[j])) as f32;
+			dist[i * n + j] = d;
+			dist[j * n + i] = d;
+		}
+	}
+
+	let mut size: Vec<usize> = vec![1; n];
+	let mut active: Vec<bool> = vec![true; n];
+	let mut owner: Vec<usize> = (0..n).collect();
+	let mut chain: Vec<usize> = Vec::new();
+	let mut left = n;
+
+	while left > k {
+		if chain.is_empty() {
+			chain.push(active.iter().position(|a| *a).unwrap());
+		}
+
+		let (a, b) = loop {
+			let a = *chain.last().unwrap();
+			let prev = chain.len().checked_sub(2).map(|i| chain[i]);
+
+			// the previous cluster wins ties, so chains always end
+			let mut best = prev;
+			let mut bd = prev.map_or(f32::INFINITY, |p| dist[a * n + p]);
+
+			for b in (0..n).filter(|b| active[*b] && *b != a) {
+				if dist[a * n + b] < bd {
+					best = Some(b);
+					bd = dist[a * n + b];
+				}
+			}
+
+			let best = best.unwrap();
+
+			if Some(best) == prev {
+				chain.truncate(chain.len() - 2);
+				break (a.min(best), a.max(best));
+			}
+
+			chain.push(best);
+		};
+
+		let (sa, sb) = (size[a] as f32, size[b] as f32);
+
+		for c in (0..n).filter(|c| active[*c] && *c != a && *c != b) {
+			let d = (sa * dist[a * n + c] + sb * dist[b * n + c]) / (sa + sb);
+			dist[a * n + c] = d;
+			dist[c * n + a] = d;
+		}
+
+		active[b] = false;
+		size[a] += size[b];
+		owner.iter_mut().filter(|o| **o == b).for_each(|o| *o = a);
+		left -= 1;
+	}
+
+	// number the remaining clusters from 0
+	let ids: Vec<usize> = active.iter()
+		.scan(0, |next, a| Some(match a {
+			true => { *next += 1; *next - 1 },
+			false => usize::MAX
+		}))
+		.collect();
+
+	owner.into_iter().map(|o| ids[o]).collect()
+}
+
+/// Group `words` by similarity of their `Similarity::features`, scaled to unit length.
+///
+/// Non finite values, such as unscored pairs, count as 0. Agglomeration keeps a distance
+/// per pair of words, so it suits sets of a few thousand words such as the answer list.
+///
+/// Returns
+/// * `Err(ErrorKind::Format)` if `options.k` is 0.
+/// * `Err(ErrorKind::TooLarge)` for more than `options.limit` words.
+/// * The errors of `Similarity::lookup` for an invalid word.
+pub fn cluster (sim: &dyn Similarity, words: &[&str], options: &Options) -> Result<Clustering> {
+	if options.k == 0 {
+		return Err(ErrorKind::Format.into());
+	}
+
+	if words.len() > options.limit {
+		return Err(ErrorKind::TooLarge.into());
+	}
+
+	let columns: Vec<usize> = words.iter()
+		.map(|w| sim.lookup(w).map(|i| i as usize))
+		.collect::<Result<_>>()?;
+
+	let vectors: Vec<Vec<f64>> = words.iter()
+		.map(|w| {
+			let mut v = sim.features(w).ok_or_else(|| ErrorKind::InvalidWord)?;
+
+			if options.restrict && v.len() == sim.dict().len() {
+				v = columns.iter().map(|c| v[*c]).collect();
+			}
+
+			Ok(unit(v.into_iter().map(|x| if x.is_finite() { x } else { 0. }).collect()))
+		})
+		.collect::<Result<_>>()?;
+
+	let k = options.k.min(words.len());
+
+	let assign = match (words.len(), options.method) {
+		(0, _) => Vec::new(),
+		(_, Method::KMeans) => kmeans(&vectors, k, options.iterations, options.seed),
+		(_, Method::Agglomerative) => agglomerative(&vectors, k),
+	};
+
+	let mut members: Vec<Vec<usize>> = vec![Vec::new(); k];
+
+	for (i, c) in assign.into_iter().enumerate() {
+		members[c].push(i);
+	}
+
+	let dim = vectors.first().map_or(0, |v| v.len());
+
+	let mut clusters: Vec<Cluster> = members.into_iter()
+		.filter(|m| !m.is_empty())
+		.map(|m| {
+			let c = centroid(&vectors, &m, dim);
+
+			let mut scored: Vec<(usize, f64)> = m.into_iter()
+				.map(|i| (i, dot(&vectors[i], &c)))
+				.collect();
+			scored.sort_unstable_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+			let words: Vec<String> = scored.iter().map(|e| words[e.0].to_owned()).collect();
+
+			Cluster {
+				labels: words.iter().take(options.labels).cloned().collect(),
+				cohesion: scored.iter().map(|e| e.1).sum::<f64>() / scored.len() as f64,
+				words,
+			}
+		})
+		.collect();
+
+	clusters.sort_by(|a, b| b.words.len().cmp(&a.words.len()));
+
+	Ok(Clustering { method: options.method, k, clusters })
+}
@@ -1,332 +1,549 @@
 /// Module managing correlation data
 use std::path::{Path, PathBuf};
-use core::hash::{Hasher, Hash};
 use std::collections::{HashSet, HashMap};
-use threadpool::ThreadPool;
-use super::read::{Dict,strip};
-use serde::de::{self, Deserialize, Deserializer, Visitor, MapAccess, SeqAccess};
-use serde::ser::{Serialize, SerializeStruct, Serializer};
+use super::read::Dict;
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
 use core::fmt::{Formatter, Debug};
-use std::io::{BufWriter, BufReader, Write, Read, Seek, SeekFrom};
-use std::fs::File;
-use log::{debug, trace};
+use std::io::{BufWriter, Write, Seek, SeekFrom};
+use std::fs::{File, OpenOptions};
+use memmap2::Mmap;
+use log::{debug, info};
 use super::error::*;
-use std::sync::{Arc,RwLock};
+use super::matrix::{Header, Precision, Layout};
+use super::build::{self, Csr, Marginals, Checkpoint};
+use super::measure::{Measure, Pair};
+use super::support::{self, Postings, Support};
+use super::similarity::Index;
+
+/// Number of cells buffered at once when assembling full rows.
+const BLOCK_CELLS: u64 = 1 << 25;
+
+/// Reason a dictionary word has no row in the correlation data.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pruned {
+	/// Listed in `Config::stopwords`.
+	Stopword,
+	/// Never occurred in any article.
+	Unseen,
+	/// Occurred in fewer than `Config::min_df` articles.
+	Rare,
+	/// Occurred in more than `Config::max_df` of all articles.
+	Common,
+	/// Not among the `Config::top` words with the highest document frequency.
+	Capped,
+	/// Has no vector in the file embeddings were imported from.
+	Missing,
+}
+
+/// Options used when building correlation data.
+#[derive(Debug, Clone)]
+pub struct Config {
+	/// Words never given a row, such as function words.
+	pub stopwords: HashSet<String>,
+	/// Minimum number of articles a word has to occur in.
+	pub min_df: usize,
+	/// Maximum ratio of articles a word may occur in.
+	pub max_df: f64,
+	/// Keep at most this many words, preferring those in the most articles.
+	pub top: Option<usize>,
+	/// Encoding of the stored cells.
+	pub precision: Precision,
+	/// Arrangement of the stored cells.
+	pub layout: Layout,
+	/// Number of threads computing the matrix.
+	pub threads: usize,
+	/// Association measure stored in the cells.
+	pub measure: Measure,
+	/// Keep the articles of every word next to the matrix, to count the support of pairs.
+	pub support: bool,
+	/// Shrink associations toward zero by `co / (co + s)`, `co` being the number of articles
+	/// shared by a pair, so pairs with little support cannot reach extreme scores.
+	pub shrink: Option<f64>,
+}
+
+impl Default for Config {
+	fn default() -> Config {
+		Config {
+			stopwords: HashSet::new(),
+			min_df: 1,
+			max_df: 1.,
+			top: None,
+			precision: Precision::F64,
+			layout: Layout::Triangular,
+			threads: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4),
+			measure: Measure::Pearson,
+			support: true,
+			shrink: None,
+		}
+	}
+}
+
+impl Config {
+	/// Check the options can build scores from article counts.
+	///
+	/// Returns
+	/// * `Err(ErrorKind::Format)` for `Measure::SecondOrder`, derived from existing
+	/// 	correlation data by `second::build` rather than from counts.
+	pub fn validate (&self) -> Result<()> {
+		match self.measure {
+			Measure::SecondOrder => Err(ErrorKind::Format.into()),
+			_ => Ok(())
+		}
+	}
+
+	/// Split a dictionary into the words kept for the matrix, renumbered from 0,
+	/// and the words pruned from it.
+	pub fn prune (&self, dat: &HashMap<u32, Vec<(u32, u16)>>, len: usize, dict: &Dict) -> (Dict, HashMap<String, Pruned>) {
+		let mut pruned: HashMap<String, Pruned> = HashMap::new();
+		let mut kept: Vec<(&String, usize)> = Vec::new();
+
+		for (k, v) in dict.iter() {
+			let df = dat.get(v).map(|e| e.len()).unwrap_or(0);
+
+			match df {
+				_ if self.stopwords.contains(k) => pruned.insert(k.clone(), Pruned::Stopword),
+				0 => pruned.insert(k.clone(), Pruned::Unseen),
+				df if df < self.min_df => pruned.insert(k.clone(), Pruned::Rare),
+				df if df as f64 > self.max_df * len as f64 => pruned.insert(k.clone(), Pruned::Common),
+				df => {
+					kept.push((k, df));
+					None
+				}
+			};
+		}
+
+		if let Some(top) = self.top {
+			if kept.len() > top {
+				kept.sort_unstable_by(|(a, x), (b, y)| y.cmp(x).then_with(|| a.cmp(b)));
+
+				for (k, _) in kept.drain(top..) {
+					pruned.insert(k.clone(), Pruned::Capped);
+				}
+			}
+		}
+
+		let nd: Dict = kept.into_iter()
+			.enumerate()
+			.map(|(a, (b, _))| (b.to_owned(), a as u32))
+			.collect();
+
+		(nd, pruned)
+	}
+}
 
 /// Structure storing correlation data
+///
+/// The matrix file is memory mapped, so lookups only need `&self`
+/// and can be made from many threads at once.
 pub struct Correlation {
-	fname: PathBuf,
-	reader: BufReader<File>,
-	dict: Dict,
+	index: Index,
+	data: Mmap,
+	header: Header,
+	/// Articles of every word, if they were kept.
+	support: Option<Postings>,
 }
 
 impl Correlation {
 	/// Generates a new correlation database from raw exported frequency data.
-	pub fn new <P: AsRef<Path>> (mut dat: HashMap<u32, Vec<(u32, u16)>>, len: usize, fname: P, dict: &Dict) -> Result<Correlation> {
+	pub fn new <P: AsRef<Path>> (dat: HashMap<u32, Vec<(u32, u16)>>, len: usize, fname: P, dict: &Dict) -> Result<Correlation> {
+		Correlation::with_config(dat, len, fname, dict, &Config::default())
+	}
+
+	/// Generates a new correlation database using the given build options.
+	///
+	/// Progress is saved next to the matrix file after every block of rows. If a build
+	/// with the same options was interrupted, it continues from the last saved row.
+	///
+	/// Returns
+	/// * `Err(ErrorKind::Unbounded)` if scores of the measure may not fit cells of the precision.
+	/// * `Err(ErrorKind::Format)` for `Measure::SecondOrder`, built by `second::build`.
+	pub fn with_config <P: AsRef<Path>> (dat: HashMap<u32, Vec<(u32, u16)>>, len: usize, fname: P, dict: &Dict, config: &Config) -> Result<Correlation> {
 
 		debug!(target: "app::dump", "Current dict size {}", dict.len());
 
-		// filter out words that dont appear in wikipedia.
-		let nd: Dict = dict.iter()
-			.filter(|(_, v)| dat.contains_key(v))
-			.map(|(k, _)| k)
-			.enumerate()
-			.map(|(a, b)| (b.to_owned(), a as u32))
-			.collect();
+		let assoc = config.measure.association();
+
+		// scores out of range would be clamped, or stored as infinities
+		if !config.precision.fits(assoc.range()) {
+			return Err(ErrorKind::Unbounded.into());
+		}
+
+		config.validate()?;
+
+		// filter out words that dont appear in wikipedia or fail the thresholds.
+		let (nd, pruned) = config.prune(&dat, len, dict);
 
 		debug!(target: "app::dump", "Pruned dict to size {}", nd.len());
 
-		let mut w = BufWriter::new(File::create(&fname).map_err(|_| ErrorKind::Io)?);
+		// rows are computed left of the diagonal, full rows are assembled afterwards
+		let (out, precision) = match config.layout {
+			Layout::Triangular => (fname.as_ref().to_path_buf(), config.precision),
+			Layout::Full => (fname.as_ref().with_extension("tri"), Precision::F64),
+		};
 
-		let mut ndk: Vec<(&String, &u32)> = nd
-			.iter()
-			.collect();
-		
-		ndk.sort_by(|(_, a), (_, b)| (*a).cmp(*b));
-		
-		let ndk: Vec<u32> = ndk
-			.into_iter()
-			.map(|(k, _)| k)
-			.map(|k| *dict.get(k).unwrap())
-			.collect();
+		let ckpt = out.with_extension("ckpt");
 
-		// calculate sums 
-		let nds: Vec<f64> = ndk.iter()
-			.map(|k| dat.get(k).unwrap())
-			.map(|d| 
-				d.into_iter()
-					.map(|(_, v)| *v as f64)
-					.sum::<f64>()
-				/ len as f64
-			)
-			.collect();
+		let ndk = Csr::words(&nd, dict);
 
-		trace!(target: "app::dump", "Most common word appeared {} times on avg.", *nds.iter().max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap_or(&0.));
+		let x = Csr::new(&dat, &ndk, len);
+		drop(dat);
 
-		let sum = ndk.iter()
-			.map(|k| dat.get(k).unwrap())
-			.enumerate()
-			.map(|(i, a)| (a.into_iter()
-				.map(|(_, c)| *c as f64 - nds[i])
-				.collect::<Vec<f64>>(), a.len())
-			);
+		// a support file left by an earlier build would not match the new rows
+		match config.support {
+			true => Postings::write(&x, fname.as_ref().with_extension("support"))?,
+			false => { std::fs::remove_file(fname.as_ref().with_extension("support")).ok(); }
+		}
 
-		let sum2: Vec<f64> = sum
-			.clone()
-			.enumerate()
-			.map(|(i, (e, l))| e.into_iter()
-				.map(|n| n * n)
-				.sum::<f64>()
-				+ (len - l) as f64 * nds[i] * nds[i]
-			).collect();
+		debug!(target: "app::dump", "Generated sparse matrix");
 
-		let sum: Vec<f64> = sum
-			.map(|(e, _)| e
-				.into_iter().sum()
-			).collect();
+		let header = Header::new(precision, Layout::Triangular, config.measure, nd.len() as u64);
 
-		debug!(target: "app::dump", "Generated presums");
+		// a file cut short of its checkpoint would be padded with zeros, it is rebuilt instead
+		let written = std::fs::metadata(&out).map_or(0, |m| m.len());
 
-		struct Ac(u32, f64);
+		let resume = match Checkpoint::load(&ckpt) {
+			Some(c) if written < header.pos(Layout::Triangular.cells(c.rows as u64)) => {
+				info!("{} is shorter than its checkpoint at row {}, restarting the build.", out.display(), c.rows);
+				None
+			},
+			c => c
+		};
 
-		impl Hash for Ac {
-			fn hash<H> (&self, h: &mut H) 
-				where H: Hasher, {
-					self.0.hash(h);
+		let mut state = match resume {
+			Some(c) if c.len == len && c.precision == precision && c.measure == config.measure && c.shrink == config.shrink && c.dict == nd && c.pruned == pruned => {
+				info!("Resuming correlation build of {} at row {}.", out.display(), c.rows);
+				c
+			},
+			_ => Checkpoint {
+				len,
+				precision,
+				measure: config.measure,
+				shrink: config.shrink,
+				marginals: Marginals::new(&x),
+				dict: nd,
+				pruned,
+				rows: 0,
 			}
-		}
+		};
 
-		impl PartialEq for Ac {
-			fn eq(&self, other: &Self) -> bool {
-				self.0 == other.0
-			}
+		let f = OpenOptions::new()
+			.write(true)
+			.create(true)
+			.open(&out)
+			.map_err(|_| ErrorKind::Io)?;
+
+		// drop cells written after the last checkpoint
+		let end = match state.rows {
+			0 => 0,
+			r => header.pos(Layout::Triangular.cells(r as u64)),
+		};
+
+		f.set_len(end).map_err(|_| ErrorKind::Io)?;
+
+		let mut w = BufWriter::new(f);
+
+		w.seek(SeekFrom::Start(end)).map_err(|_| ErrorKind::Io)?;
+
+		if state.rows == 0 {
+			header.write(&mut w)?;
 		}
 
-		impl Eq for Ac {}
+		let m = state.marginals.clone();
 
-		// consumes data and converts it to hashset form
-		let uniq: Vec<Arc<HashSet<Ac>>> = ndk.into_iter()
-			.map(|e| dat.remove(&e).unwrap())
-			.map(|e| e.into_iter()
-				.map(|(a,b)| Ac(a,b as f64))
-				.collect::<HashSet<Ac>>()
-			)
-			.map(Arc::new)
-			.collect();
+		let score = |i, j, p: Pair| match config.shrink {
+			Some(s) => support::shrink(assoc.score(&m, i, j, p), p.co, s),
+			None => assoc.score(&m, i, j, p)
+		};
+
+		build::lower(&x, state.rows, config.threads, precision, &mut w, score, |r| {
+			state.rows = r;
+			state.save(&ckpt)
+		})?;
 
-		debug!(target: "app::dump", "Generated hashsets");
+		let mut diag: Vec<u8> = Vec::with_capacity(x.len() * precision.width());
+
+		for i in 0..x.len() {
+			precision.encode(assoc.own(&m, i), &mut diag);
+		}
 
-		let nds = Arc::new(nds);
-		let sum = Arc::new(sum);
-		let sum2 = Arc::new(sum2);
+		w.write_all(&diag).map_err(|_| ErrorKind::Io)?;
+		w.flush().map_err(|_| ErrorKind::Io)?;
+		drop(w);
 
-		// println!("{nds:?}\n{sum:?}\n{sum2:?}");
+		let corr = Correlation::deserialize(&out, state.dict, state.pruned)?;
 
-		let pool: Option<ThreadPool> = match nd.len() {
-			0..=1000 => None,
-			_ => Some(ThreadPool::new(4))
+		let corr = match config.layout {
+			Layout::Triangular => corr,
+			Layout::Full => {
+				let full = corr.convert(&fname, config.precision, Layout::Full)?;
+				std::fs::remove_file(&out).map_err(|_| ErrorKind::Io)?;
+				full
+			}
 		};
 
-		for i in 0 .. nd.len() {
-			debug!("Parsing word {}.", i);
+		std::fs::remove_file(&ckpt).ok();
 
-			type SharedDat = (usize, usize, Arc<HashSet<Ac>>, Arc<Vec<f64>>, Arc<Vec<f64>>, Arc<Vec<f64>>);
+		Ok(corr)
+	}
 
-			let shared: Arc<SharedDat> = Arc::new( (
-				i,
-				len,
-				Arc::clone(&uniq[i]),
-				Arc::clone(&nds),
-				Arc::clone(&sum),
-				Arc::clone(&sum2),
-			) );
-
-			let calc = |j: usize, buf: Arc<RwLock<f64>>, shared: Arc<SharedDat>, b: Arc<HashSet<Ac>>| {
-				let b = &*b;
-				let (
-					i,
-					len,
-					a,
-					nds,
-					sum,
-					sum2,
-				) = (
-					shared.0,
-					shared.1,
-					&*shared.2,
-					&*shared.3,
-					&*shared.4,
-					&*shared.5,
-				);
-
-				// calculate numerator ignoring intersection.
-				let mut num = sum[i] * -nds[j] + sum[j] * -nds[i];
-				let mut nc = 0; // number of shared articles
-
-				let mut iter =  |(a, i): (&HashSet<Ac>, usize), (b, j): (&HashSet<Ac>, usize)| {
-					for t in a.iter() {
-						if let Some(t2) = b.get(t) {
-							let (da, db) = (t.1 - nds[i], t2.1 - nds[j]);
-
-							num += da * nds[j];
-							num += db * nds[i];
-							num += da * db;
-							nc += 1;
-						}
+	/// Used to load a correlation database from an existing file
+	fn deserialize <P: AsRef<Path>> (fname: P, dict: Dict, pruned: HashMap<String, Pruned>) -> Result<Correlation> {
+		let f = File::open(&fname).map_err(|_| ErrorKind::Io)?;
+
+		// Safety: correlation files are written once and never modified while in use.
+		let data = unsafe { Mmap::map(&f) }.map_err(|_| ErrorKind::Io)?;
+
+		let support = Postings::open(fname.as_ref().with_extension("support"), dict.len()).ok();
+
+		Correlation::from_map(fname.as_ref().canonicalize().map_err(|_| ErrorKind::Io)?, data, dict, pruned, support)
+	}
+
+	/// Read correlation data already mapped to memory, such as a section of a bundle.
+	///
+	/// `fname` is only kept to be serialized.
+	pub fn from_map (fname: PathBuf, data: Mmap, dict: Dict, pruned: HashMap<String, Pruned>, support: Option<Postings>) -> Result<Correlation> {
+		let header = Header::read(&mut &data[..], dict.len() as u64, data.len() as u64)?;
+
+		if data.len() as u64 != header.size() {
+			return Err(ErrorKind::Format.into());
+		}
+
+		Ok(Correlation {
+			index: Index { fname, dict, pruned },
+			data,
+			header,
+			support,
+		})
+	}
+
+	/// Rewrite the correlation data to `fname` using another cell encoding and layout.
+	///
+	/// `fname` may be the file this data is read from, it is only replaced once the
+	/// conversion has finished.
+	///
+	/// Returns
+	/// * `Err(ErrorKind::Unbounded)` if scores of the measure may not fit cells of `precision`.
+	pub fn convert <P: AsRef<Path>> (&self, fname: P, precision: Precision, layout: Layout) -> Result<Correlation> {
+		let fname = fname.as_ref();
+		let tmp = fname.with_extension("tmp");
+
+		if !precision.fits(self.header.measure.association().range()) {
+			return Err(ErrorKind::Unbounded.into());
+		}
+
+		let mut w = BufWriter::new(File::create(&tmp).map_err(|_| ErrorKind::Io)?);
+
+		let n = self.index.dict.len() as u64;
+		let src = self.header.precision;
+		let width = src.width();
+
+		Header::new(precision, layout, self.header.measure, n).write(&mut w)?;
+
+		let mut out: Vec<u8> = Vec::with_capacity(n as usize * precision.width());
+
+		match layout {
+			Layout::Triangular => {
+				for i in 0..n {
+					out.clear();
+					for cell in self.lower(i).ok_or_else(|| ErrorKind::Io)?.chunks_exact(width) {
+						precision.encode(src.decode(cell), &mut out);
 					}
-				};
 
-				if a.len() < b.len() {
-					iter((a, i), (b, j));
-				} else {
-					iter((b, j), (a, i));
+					w.write_all(&out).map_err(|_| ErrorKind::Io)?;
 				}
 
-				let nc = (len - a.len() - b.len() + nc) as f64;
+				out.clear();
+				for i in 0..n {
+					precision.encode(self.cell(i, i).ok_or_else(|| ErrorKind::Io)?, &mut out);
+				}
 
-				let num = num + nc * nds[i] * nds[j];
-				// println!("{}\t{}", nc, num);
+				w.write_all(&out).map_err(|_| ErrorKind::Io)?;
+			},
+			Layout::Full => {
+				// Rows are assembled in blocks. The cells of a block right of the diagonal are
+				// stored as short runs in every later row, so each block reads the file once.
+				let block = (BLOCK_CELLS / n.max(1)).max(1);
+				let mut buf: Vec<f64> = Vec::new();
 
-				// pearsons r correlation
-				let r = num / (sum2[i] * sum2[j]).sqrt();
-				trace!(target: "app::dump", "{}:{} {}:{};\t{}", i, a.len(), j, b.len(), r);
-				// println!("{}:{} {}:{};\t{}", i, a.len(), j, b.len(), r);
+				for r0 in (0..n).step_by(block as usize) {
+					let r1 = (r0 + block).min(n);
 
-				*buf.write().unwrap() = r;
-			};
+					buf.clear();
+					buf.resize(((r1 - r0) * n) as usize, 0.);
 
-			let mut buf: Vec<Arc<RwLock<f64>>> = Vec::new();
+					for i in r0..r1 {
+						let row = &mut buf[((i - r0) * n) as usize..((i - r0 + 1) * n) as usize];
 
-			for j in 0 .. i {
+						for (j, cell) in self.lower(i).ok_or_else(|| ErrorKind::Io)?.chunks_exact(width).enumerate() {
+							row[j] = src.decode(cell);
+						}
 
-				let shared = Arc::clone(&shared);
-				buf.push(Arc::new(RwLock::new(0.)));
-				let slice = Arc::clone(&buf[j]);
+						row[i as usize] = self.cell(i, i).ok_or_else(|| ErrorKind::Io)?;
+					}
 
-				let b = Arc::clone(&uniq[j]); // b freq data
+					for j in r0 + 1..n {
+						let lower = self.lower(j).ok_or_else(|| ErrorKind::Io)?;
 
-				let cc = move || calc(j, slice, shared, b);
-				match pool {
-					Some(ref p) => {
-						p.execute(cc)
-					},
-					None => cc (),
-				};
-			}
+						for c in r0..r1.min(j) {
+							buf[((c - r0) * n + j) as usize] = src.decode(&lower[c as usize * width..(c as usize + 1) * width]);
+						}
+					}
+
+					for row in buf.chunks(n as usize) {
+						out.clear();
+						for v in row {
+							precision.encode(*v, &mut out);
+						}
 
-			if let Some(ref p) = pool {
-				p.join();
+						w.write_all(&out).map_err(|_| ErrorKind::Io)?;
+					}
+
+					debug!("Converted rows {} to {} of {}.", r0, r1, n);
+				}
 			}
+		}
 
-			let buf: Vec<u8> = buf.iter()
-				.map(|e| *e.read().unwrap())
-				.map(|e| e.to_be_bytes())
-				.flatten()
-				.collect();
+		w.flush().map_err(|_| ErrorKind::Io)?;
+		drop(w);
 
-			w.write(&buf)
-				.map_err(|_| ErrorKind::Io)?;
+		std::fs::rename(&tmp, fname).map_err(|_| ErrorKind::Io)?;
+
+		// the articles of every word stay the same, unless converting in place
+		let (from, to) = (self.index.fname.with_extension("support"), fname.with_extension("support"));
+
+		if self.support.is_some() && to.canonicalize().ok() != Some(from.clone()) {
+			std::fs::copy(&from, &to).map_err(|_| ErrorKind::Io)?;
 		}
 
-		Ok(Correlation {
-			reader: BufReader::new(File::open(&fname).map_err(|_| ErrorKind::Io)?),
-			fname: fname.as_ref().canonicalize().map_err(|_| ErrorKind::Io)?,
-			dict: nd
-		})
+		debug!("Converted {} words from {:?} {:?} to {:?} {:?}.", n, src, self.header.layout, precision, layout);
+
+		Correlation::deserialize(fname, self.index.dict.clone(), self.index.pruned.clone())
 	}
 
-	/// Used to load a correlation database from an existing file
-	fn deserialize <P: AsRef<Path>> (fname: P, dict: Dict) -> Result<Correlation> {
-		Ok(Correlation {
-			reader: BufReader::new(File::open(&fname).map_err(|_| ErrorKind::Io)?),
-			fname: fname.as_ref().canonicalize().map_err(|_| ErrorKind::Io)?,
-			dict
-		})
+	/// Encoding of the stored cells.
+	pub fn precision (&self) -> Precision {
+		self.header.precision
+	}
+
+	/// Arrangement of the stored cells.
+	pub fn layout (&self) -> Layout {
+		self.header.layout
+	}
+
+	/// Association measure stored in the cells.
+	pub fn measure (&self) -> Measure {
+		self.header.measure
+	}
+
+	/// File the cells are read from.
+	pub fn fname (&self) -> &Path {
+		&self.index.fname
 	}
 
 	/// Index of a word in the dictionary.
 	pub fn index (&self, a: &str) -> Option<u32> {
-		self.dict.get(&strip(a)?).map(|e| *e)
+		self.index.index(a)
 	}
 
-	/// Find the f64 index of a word pair correlation.
-	///
-	/// Value should be multiplied by 8 to get the byte index.
-	fn find (&self, a: u64, b: u64) -> Option<u64>{
-		if a == b {
-			return None
-		}
-		let (a, b) = if a < b { (b, a) } else { (a, b) };
+	/// Index of a word in the dictionary, see `Index::lookup`.
+	pub fn lookup (&self, a: &str) -> Result<u32> {
+		self.index.lookup(a)
+	}
 
-		// a should be > b
+	/// Words left out of the correlation data and why.
+	pub fn pruned<'a>(&'a self) -> &'a HashMap<String, Pruned> {
+		self.index.pruned()
+	}
+
+	/// Bytes of `len` consecutive cells starting at cell `i`.
+	fn cells (&self, i: u64, len: u64) -> Option<&[u8]> {
+		self.data.get(self.header.pos(i) as usize..self.header.pos(i + len) as usize)
+	}
 
-		Some(a * (a - 1) / 2 + b)
+	/// Bytes of the cells of row `a` left of the diagonal, which are contiguous in every layout.
+	fn lower (&self, a: u64) -> Option<&[u8]> {
+		self.cells(self.header.layout.find(a, 0, self.header.words).unwrap_or(0), a)
 	}
 
-	/// Returns the pearson's r correlation between two words.
-	pub fn corr (&mut self, a: &str, b: &str) -> Option<f64> {
-		if a == b {
-			return Some(1.)
+	/// Value of the cell `(a, b)`.
+	fn cell (&self, a: u64, b: u64) -> Option<f64> {
+		match self.header.find(a, b) {
+			Some(i) => Some(self.header.precision.decode(self.cells(i, 1)?)),
+			None => Some(1.)
 		}
+	}
 
+	/// Returns the association between two words.
+	pub fn corr (&self, a: &str, b: &str) -> Option<f64> {
 		let a = self.index(a)? as u64;
 		let b = self.index(b)? as u64;
 
-		let ind = self.find(a, b).unwrap();
+		self.cell(a, b)
+	}
 
-		self.reader.seek(SeekFrom::Start(ind * 8)).ok()?;
+	/// Returns the number of articles shared by two words and the significance of their
+	/// association, if the articles of every word were kept when building the data.
+	pub fn support (&self, a: &str, b: &str) -> Option<Support> {
+		let (i, j) = (self.index(a)?, self.index(b)?);
 
-		let mut buf: [u8; 8] = [0; 8];
+		Some(self.support.as_ref()?.support(i as usize, j as usize, self.score(i, j), self.header.measure == Measure::Pearson))
+	}
 
-		self.reader.read_exact(&mut buf).ok()?;
+	/// Returns the number of articles containing the word with index `a`, if the
+	/// articles of every word were kept when building the data.
+	pub fn df (&self, a: u32) -> Option<usize> {
+		Some(self.support.as_ref()?.df(a as usize))
+	}
 
-		Some(f64::from_be_bytes(buf))
+	/// Returns the association between the words with indices `a` and `b`.
+	pub fn score (&self, a: u32, b: u32) -> f64 {
+		self.cell(a as u64, b as u64).unwrap_or(f64::NEG_INFINITY)
 	}
 
-	pub fn corrall (&mut self, a: &str) -> Option<Vec<f64>> {
+	pub fn corrall (&self, a: &str) -> Option<Vec<f64>> {
 		let a = self.index(a)? as u64;
+		let n = self.index.dict.len() as u64;
+		let p = self.header.precision;
+
+		// full rows are a single sequential read
+		if let Layout::Full = self.header.layout {
+			return Some(self.cells(a * n, n)?
+				.chunks_exact(p.width())
+				.map(|t| p.decode(t))
+				.collect());
+		}
 
-		let ind = self.find(a, 0).unwrap_or(0);
-
-		self.reader.seek(SeekFrom::Start(ind * 8)).ok()?;
-
-		let mut buf: Vec<u8> = vec![0; self.dict.len() * 8];
-
-		self.reader.read_exact(&mut buf[0..a as usize * 8]).ok()?;
+		let mut ret: Vec<f64> = Vec::with_capacity(self.index.dict.len());
 
-		for b in a+1..self.dict.len() as u64 {
-			self.reader.seek(SeekFrom::Start(self.find(b, a)? * 8)).ok()?;
-			let b = b as usize * 8;
-			self.reader.read_exact(&mut buf[b..b+8]).ok()?;
-		}
+		ret.extend(self.lower(a)?
+			.chunks_exact(p.width())
+			.map(|t| p.decode(t)));
 
-		let mut ret: Vec<f64> = vec![0.; self.dict.len()];
+		ret.push(self.cell(a, a)?);
 
-		for i in 0..self.dict.len() {
-			let t : [u8; 8] = buf[i*8..i*8+8].try_into().unwrap();
-			ret[i] = f64::from_be_bytes(t);
+		for b in a+1..n {
+			ret.push(self.cell(b, a)?);
 		}
 
-		ret[a as usize] = 1.;
-
 		Some(ret)
 	}
 
 	pub fn dict<'a>(&'a self) -> &'a Dict {
-		&self.dict
+		&self.index.dict
 	}
 }
 
 impl PartialEq for Correlation {
 	fn eq(&self, r: &Correlation) -> bool {
-		self.fname == r.fname
+		self.index.fname == r.index.fname
 	}
 }
 
 impl Debug for Correlation {
 	fn fmt(&self, f: &mut Formatter) -> std::result::Result<(), std::fmt::Error> {
 		f.debug_struct("Correlation")
-			.field("fname", &self.fname)
+			.field("fname", &self.index.fname)
 			.finish()
 	}
 }
@@ -334,11 +551,8 @@ impl Debug for Correlation {
 impl Serialize for Correlation {
 	fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
 		where S: Serializer
-	{ 
-		let mut s = serializer.serialize_struct("Correlation", 2)?;
-		s.serialize_field("fname", &self.fname)?;
-		s.serialize_field("dict", &self.dict)?;
-		s.end()
+	{
+		self.index.serialize(serializer)
 	}
 }
 
@@ -346,74 +560,7 @@ impl<'de> Deserialize<'de> for Correlation {
 	fn deserialize<D>(deserializer: D) -> std::result::Result<Correlation, D::Error>
 		where D: Deserializer<'de>,
 	{
-		// implementation following https://serde.rs/deserialize-struct.html
-
-		#[derive(serde::Deserialize)]
-		#[serde(field_identifier, rename_all = "lowercase")]
-   	enum Field { Fname, Dict }
-
-		struct CorrelationVisitor;
-
-		impl<'de> Visitor<'de> for CorrelationVisitor {
-			type Value = Correlation;
-
-			fn expecting(&self, formatter: &mut Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-				formatter.write_str("A struct representing Correlation analysis data.")
-			}
-
-			fn visit_seq<V>(self, mut seq: V) -> std::result::Result<Self::Value, V::Error>
-				where V: SeqAccess<'de>,
-			{
-				let fname: PathBuf = seq.next_element()?
-					.ok_or_else(|| de::Error::invalid_length(0, &self))?;
-
-				let dict: Dict = seq.next_element()?
-					.ok_or_else(|| de::Error::invalid_length(0, &self))?;
-
-				Ok(Correlation::deserialize(&fname, dict)
-					.map_err(|_| de::Error::invalid_value(
-						de::Unexpected::Str(&format!("Unexpected path: <{}>", fname.to_str().unwrap_or("none"))),
-						&"A valid filepath."
-					))?)
-			}
-
-			fn visit_map<V>(self, mut map: V) -> std::result::Result<Self::Value, V::Error>
-        where V: MapAccess<'de>,
-      {
-      	let mut fname = None;
-      	let mut dict = None;
-
-      	while let Some(key) = map.next_key()? {
-      		match key {
-      			Field::Fname => {
-      				if fname.is_some() {
-      					return Err(de::Error::duplicate_field("fname"));
-      				}
-
-      				fname = Some(map.next_value()?);
-      			},
-      			Field::Dict => {
-      				if dict.is_some() {
-      					return Err(de::Error::duplicate_field("dict"));
-      				}
-
-      				dict = Some(map.next_value()?);
-      			}
-      		}
-      	}
-
-      	let fname: PathBuf = fname.ok_or_else(|| de::Error::missing_field("fname"))?;
-      	let dict: Dict = dict.ok_or_else(|| de::Error::missing_field("dict"))?;
-
-				Ok(Correlation::deserialize(&fname, dict)
-					.map_err(|_| de::Error::invalid_value(
-						de::Unexpected::Str(&format!("Unexpected path: <{}>", fname.to_str().unwrap_or("none"))),
-						&"A valid filepath."
-					))?)
-      }
-		}
-
-    const FIELDS: &'static [&'static str] = &["fname", "index"];
-		deserializer.deserialize_struct("Correlation", FIELDS, CorrelationVisitor)
+		Index::deserialize(deserializer)?
+			.open(|fname, dict, pruned| Correlation::deserialize(fname, dict, pruned))
 	}
 }
\ No newline at end of file
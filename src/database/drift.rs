@@ -0,0 +1,247 @@
+/// Module comparing the similarity data of two builds
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use core::fmt::{self, Display, Formatter};
+use serde::Serialize;
+use super::similarity::Similarity;
+use super::build;
+
+/// Options of a comparison.
+#[derive(Debug, Clone)]
+pub struct Options {
+	/// Number of neighbors compared per word.
+	pub k: usize,
+	/// Number of most changed words reported.
+	pub changed: usize,
+	/// Only compare every `step`-th shared word, as every compared word scores all others.
+	pub step: usize,
+	/// Number of threads comparing words.
+	pub threads: usize,
+}
+
+impl Default for Options {
+	fn default() -> Options {
+		Options {
+			k: 20,
+			changed: 50,
+			step: 1,
+			threads: thread::available_parallelism().map_or(1, |n| n.get()),
+		}
+	}
+}
+
+/// Change of a word's neighborhood between builds.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WordDrift {
+	pub word: String,
+	/// Jaccard index of the `k` nearest shared words in both builds.
+	pub overlap: f64,
+	/// Spearman correlation of the scores against every other shared word.
+	pub spearman: f64,
+	/// Nearest words only found in the old build.
+	pub lost: Vec<String>,
+	/// Nearest words only found in the new build.
+	pub gained: Vec<String>,
+}
+
+/// Differences between two builds, words being aligned through their dictionaries.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Drift {
+	pub k: usize,
+	/// Words scored by both builds.
+	pub shared: usize,
+	/// Shared words compared.
+	pub compared: usize,
+	/// Words only scored by the new build.
+	pub added: Vec<String>,
+	/// Words only scored by the old build.
+	pub removed: Vec<String>,
+	/// Mean neighbor overlap of compared words.
+	pub overlap: f64,
+	/// Mean rank correlation of compared words.
+	pub spearman: f64,
+	/// Words whose neighborhoods changed most, lowest overlap first.
+	pub changed: Vec<WordDrift>,
+}
+
+/// Ranks of values from 1, tied values sharing the mean of their ranks.
+fn ranks (v: &[f64]) -> Vec<f64> {
+	let mut order: Vec<usize> = (0..v.len()).collect();
+	order.sort_unstable_by(|a, b| v[*a].total_cmp(&v[*b]));
+
+	let mut r: Vec<f64> = vec![0.; v.len()];
+	let mut i = 0;
+
+	while i < order.len() {
+		let mut j = i;
+
+		while j + 1 < order.len() && v[order[j + 1]] == v[order[i]] {
+			j += 1;
+		}
+
+		let mean = (i + j) as f64 / 2. + 1.;
+
+		for o in &order[i..=j] {
+			r[*o] = mean;
+		}
+
+		i = j + 1;
+	}
+
+	r
+}
+
+/// Spearman rank correlation of two series, ties sharing their mean rank.
+///
+/// Not a number values rank lowest. Returns 0 if either series is constant.
+pub fn spearman (a: &[f64], b: &[f64]) -> f64 {
+	let clean = |v: &[f64]| -> Vec<f64> {
+		v.iter().map(|x| if x.is_nan() { f64::NEG_INFINITY } else { *x }).collect()
+	};
+
+	let (ra, rb) = (ranks(&clean(a)), ranks(&clean(b)));
+	let n = ra.len() as f64;
+	let mean = (n + 1.) / 2.;
+
+	let (mut ab, mut aa, mut bb) = (0., 0., 0.);
+
+	for (x, y) in ra.iter().zip(&rb) {
+		ab += (x - mean) * (y - mean);
+		aa += (x - mean) * (x - mean);
+		bb += (y - mean) * (y - mean);
+	}
+
+	match aa > 0. && bb > 0. {
+		true => ab / (aa * bb).sqrt(),
+		false => 0.
+	}
+}
+
+/// Compare the neighborhoods of the words shared by an old and a new build.
+///
+/// Scores are only compared between shared words, so words added or removed do not
+/// count as changes of their neighbors.
+pub fn compare (old: &dyn Similarity, new: &dyn Similarity, options: &Options) -> Drift {
+	let (on, nn) = (old.names(), new.names());
+
+	let mut shared: Vec<(&str, u32, u32)> = old.dict().iter()
+		.filter_map(|(w, i)| Some((w.as_str(), *i, *new.dict().get(w)?)))
+		.collect();
+	shared.sort_unstable();
+
+	let mut added: Vec<String> = nn.iter().filter(|w| !old.dict().contains_key(**w)).map(|w| w.to_string()).collect();
+	let mut removed: Vec<String> = on.iter().filter(|w| !new.dict().contains_key(**w)).map(|w| w.to_string()).collect();
+	added.sort_unstable();
+	removed.sort_unstable();
+
+	let compared: Vec<usize> = (0..shared.len()).step_by(options.step.max(1)).collect();
+	let next = AtomicUsize::new(0);
+
+	let work = || {
+		let mut out: Vec<WordDrift> = Vec::new();
+
+		loop {
+			let c = next.fetch_add(1, Ordering::Relaxed);
+
+			let s = match compared.get(c) {
+				Some(s) => s,
+				None => return out
+			};
+
+			let (word, _, _) = shared[*s];
+
+			let (ro, rn) = match (old.corrall(word), new.corrall(word)) {
+				(Some(ro), Some(rn)) => (ro, rn),
+				_ => continue
+			};
+
+			// scores against the other shared words, aligned by position in `shared`
+			let (mut a, mut b): (Vec<(u32, f64)>, Vec<(u32, f64)>) = shared.iter()
+				.enumerate()
+				.filter(|(j, _)| j != s)
+				.map(|(j, (_, i, n))| ((j as u32, ro[*i as usize]), (j as u32, rn[*n as usize])))
+				.unzip();
+
+			let rho = spearman(
+				&a.iter().map(|e| e.1).collect::<Vec<f64>>(),
+				&b.iter().map(|e| e.1).collect::<Vec<f64>>()
+			);
+
+			a = build::select(a, options.k);
+			b = build::select(b, options.k);
+
+			let ta: HashSet<u32> = a.iter().map(|e| e.0).collect();
+			let tb: HashSet<u32> = b.iter().map(|e| e.0).collect();
+			let union = ta.union(&tb).count();
+
+			out.push(WordDrift {
+				word: word.to_owned(),
+				overlap: match union {
+					0 => 1.,
+					u => ta.intersection(&tb).count() as f64 / u as f64
+				},
+				spearman: rho,
+				lost: a.iter().filter(|e| !tb.contains(&e.0)).map(|e| shared[e.0 as usize].0.to_owned()).collect(),
+				gained: b.iter().filter(|e| !ta.contains(&e.0)).map(|e| shared[e.0 as usize].0.to_owned()).collect(),
+			});
+		}
+	};
+
+	let mut words: Vec<WordDrift> = thread::scope(|s| {
+		let workers: Vec<_> = (0..options.threads.max(1))
+			.map(|_| s.spawn(&work))
+			.collect();
+
+		workers.into_iter()
+			.flat_map(|h| h.join().unwrap())
+			.collect()
+	});
+
+	let n = words.len().max(1) as f64;
+	let overlap = words.iter().map(|w| w.overlap).sum::<f64>() / n;
+	let rho = words.iter().map(|w| w.spearman).sum::<f64>() / n;
+
+	words.sort_unstable_by(|a, b| a.overlap.total_cmp(&b.overlap)
+		.then(a.spearman.total_cmp(&b.spearman))
+		.then_with(|| a.word.cmp(&b.word)));
+
+	Drift {
+		k: options.k,
+		shared: shared.len(),
+		compared: words.len(),
+		added,
+		removed,
+		overlap,
+		spearman: rho,
+		changed: words.into_iter().take(options.changed).collect(),
+	}
+}
+
+impl Display for Drift {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		writeln!(f, "Shared words:     {} ({} compared)", self.shared, self.compared)?;
+		writeln!(f, "Added words:      {}", self.added.len())?;
+		writeln!(f, "Removed words:    {}", self.removed.len())?;
+		writeln!(f, "Neighbor overlap: {:.4} (top {})", self.overlap, self.k)?;
+		writeln!(f, "Rank correlation: {:.4}", self.spearman)?;
+
+		writeln!(f)?;
+		writeln!(f, "Most changed words:")?;
+
+		for w in self.changed.iter() {
+			writeln!(f, "  {:<24} {:.3} {:>7.3}  -{} +{}", w.word, w.overlap, w.spearman, w.lost.join(","), w.gained.join(","))?;
+		}
+
+		for (name, words) in [("Added", &self.added), ("Removed", &self.removed)] {
+			writeln!(f)?;
+			writeln!(f, "{name} words:")?;
+
+			for w in words.iter() {
+				writeln!(f, "  {}", w)?;
+			}
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,559 @@
+/// Module managing dense word embeddings
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::fs::File;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use core::fmt::{Formatter, Debug};
+use rand::{Rng, SeedableRng, rngs::SmallRng};
+use log::debug;
+use super::read::{Dict, strip};
+use super::correlation::{Config, Pruned};
+use super::build::{self, Csr, Marginals};
+use super::measure::{Association, Ppmi};
+use super::similarity::Index;
+use super::error::*;
+
+/// Bytes identifying an embedding file.
+pub const MAGIC: &[u8; 4] = b"WKDE";
+/// Current version of the embedding file header.
+pub const VERSION: u8 = 1;
+/// Size of the header in bytes.
+pub const HEADER: usize = 16;
+
+/// Options of the randomized truncated SVD.
+#[derive(Debug, Clone)]
+pub struct Svd {
+	/// Dimension of the vectors.
+	pub dim: usize,
+	/// Extra random directions sampled beyond `dim`, improving accuracy.
+	pub oversample: usize,
+	/// Number of power iterations, improving accuracy when singular values decay slowly.
+	pub power: usize,
+	/// Seed of the random directions.
+	pub seed: u64,
+}
+
+impl Default for Svd {
+	fn default() -> Svd {
+		Svd {
+			dim: 100,
+			oversample: 10,
+			power: 2,
+			seed: 0,
+		}
+	}
+}
+
+/// Structure storing a vector per word
+///
+/// The file holds a header (magic, version, 3 zero bytes, then the dimension and the
+/// number of words as big endian `u32`) followed by the vectors as big endian `f32`.
+/// The dictionary and pruned words are serialized separately, like `Correlation`.
+pub struct Embedding {
+	index: Index,
+	dim: usize,
+	/// Vectors of all words, one after another.
+	vectors: Vec<f32>,
+	norms: Vec<f64>,
+}
+
+/// Product of a sparse matrix with dense columns, computed by `threads` workers.
+fn mul (m: &Csr, cols: &[Vec<f64>], threads: usize) -> Vec<Vec<f64>> {
+	let next = AtomicUsize::new(0);
+
+	let work = || {
+		let mut out: Vec<(usize, Vec<f64>)> = Vec::new();
+
+		loop {
+			let c = next.fetch_add(1, Ordering::Relaxed);
+
+			if c >= cols.len() {
+				return out;
+			}
+
+			out.push((c, m.mul(&cols[c])));
+		}
+	};
+
+	let mut out: Vec<(usize, Vec<f64>)> = thread::scope(|s| {
+		let workers: Vec<_> = (0..threads.max(1))
+			.map(|_| s.spawn(&work))
+			.collect();
+
+		workers.into_iter()
+			.flat_map(|h| h.join().unwrap())
+			.collect()
+	});
+
+	out.sort_unstable_by_key(|e| e.0);
+	out.into_iter().map(|e| e.1).collect()
+}
+
+fn dot (a: &[f64], b: &[f64]) -> f64 {
+	a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Orthonormalize columns in place with modified Gram-Schmidt, done twice for stability.
+///
+/// Columns depending on earlier ones are set to 0.
+fn orth (cols: &mut [Vec<f64>]) {
+	for i in 0..cols.len() {
+		let (done, rest) = cols.split_at_mut(i);
+		let c = &mut rest[0];
+
+		for _ in 0..2 {
+			for d in done.iter() {
+				let p = dot(c, d);
+				c.iter_mut().zip(d).for_each(|(x, y)| *x -= p * y);
+			}
+		}
+
+		let norm = dot(c, c).sqrt();
+
+		match norm > 1e-12 {
+			true => c.iter_mut().for_each(|x| *x /= norm),
+			false => c.iter_mut().for_each(|x| *x = 0.)
+		}
+	}
+}
+
+/// Eigen decomposition of a symmetric matrix with cyclic Jacobi rotations.
+///
+/// Returns the eigenvalues and a matrix with the matching eigenvectors as columns.
+fn jacobi (mut a: Vec<Vec<f64>>) -> (Vec<f64>, Vec<Vec<f64>>) {
+	let l = a.len();
+	let mut v: Vec<Vec<f64>> = (0..l)
+		.map(|i| (0..l).map(|j| if i == j { 1. } else { 0. }).collect())
+		.collect();
+
+	let scale: f64 = a.iter().flatten().map(|x| x * x).sum();
+
+	for _ in 0..100 {
+		let off: f64 = (0..l).flat_map(|p| (p + 1..l).map(move |q| (p, q)))
+			.map(|(p, q)| a[p][q] * a[p][q])
+			.sum();
+
+		if off <= scale * 1e-30 {
+			break;
+		}
+
+		for p in 0..l {
+			for q in p + 1..l {
+				if a[p][q] == 0. {
+					continue;
+				}
+
+				let theta = (a[q][q] - a[p][p]) / (2. * a[p][q]);
+				let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+				let c = 1. / (t * t + 1.).sqrt();
+				let s = t * c;
+
+				for k in 0..l {
+					let (kp, kq) = (a[k][p], a[k][q]);
+					a[k][p] = c * kp - s * kq;
+					a[k][q] = s * kp + c * kq;
+				}
+
+				for k in 0..l {
+					let (pk, qk) = (a[p][k], a[q][k]);
+					a[p][k] = c * pk - s * qk;
+					a[q][k] = s * pk + c * qk;
+				}
+
+				for row in v.iter_mut() {
+					let (kp, kq) = (row[p], row[q]);
+					row[p] = c * kp - s * kq;
+					row[q] = s * kp + c * kq;
+				}
+			}
+		}
+	}
+
+	((0..l).map(|i| a[i][i]).collect(), v)
+}
+
+impl Embedding {
+	/// Compute embeddings from raw exported frequency data.
+	///
+	/// The PPMI matrix of the words kept by `config` is factored by a randomized truncated
+	/// SVD, `M ≈ U Σ Vᵀ`, and every word gets its row of `U Σ^½`.
+	pub fn new <P: AsRef<Path>> (dat: HashMap<u32, Vec<(u32, u16)>>, len: usize, fname: P, dict: &Dict, config: &Config, svd: &Svd) -> Result<Embedding> {
+		let (nd, pruned) = config.prune(&dat, len, dict);
+
+		let x = Csr::new(&dat, &Csr::words(&nd, dict), len);
+		drop(dat);
+
+		let m = Marginals::new(&x);
+		let ppmi = build::sparse(&x, config.threads, |i, j, p| Ppmi.score(&m, i, j, p));
+		drop(x);
+
+		let n = ppmi.len();
+		let l = (svd.dim + svd.oversample).min(n);
+
+		// range of the matrix, sampled with random directions
+		let mut rng = SmallRng::seed_from_u64(svd.seed);
+		let omega: Vec<Vec<f64>> = (0..l)
+			.map(|_| (0..n).map(|_| rng.gen_range(-1.0..1.0)).collect())
+			.collect();
+
+		let mut q = mul(&ppmi, &omega, config.threads);
+		orth(&mut q);
+
+		for i in 0..svd.power {
+			for _ in 0..2 {
+				q = mul(&ppmi, &q, config.threads);
+				orth(&mut q);
+			}
+
+			debug!(target: "app::dump", "Power iteration {} of {}.", i + 1, svd.power);
+		}
+
+		// singular values of B = Qᵀ M from the eigenvalues of B Bᵀ, M being symmetric
+		let b = mul(&ppmi, &q, config.threads);
+		let c: Vec<Vec<f64>> = b.iter()
+			.map(|r| b.iter().map(|s| dot(r, s)).collect())
+			.collect();
+
+		let (lambda, v) = jacobi(c);
+
+		let mut order: Vec<usize> = (0..l).collect();
+		order.sort_unstable_by(|a, b| lambda[*b].total_cmp(&lambda[*a]));
+		order.truncate(svd.dim);
+
+		debug!(target: "app::dump", "Top singular values {:?}.", order.iter().take(5).map(|c| lambda[*c].max(0.).sqrt()).collect::<Vec<f64>>());
+
+		let dim = order.len();
+		let mut vectors: Vec<f32> = Vec::with_capacity(n * dim);
+
+		for i in 0..n {
+			for c in order.iter() {
+				let u: f64 = (0..l).map(|r| q[r][i] * v[r][*c]).sum();
+
+				vectors.push((u * lambda[*c].max(0.).sqrt().sqrt()) as f32);
+			}
+		}
+
+		Embedding::from_vectors(fname, nd, pruned, dim, vectors)
+	}
+
+	/// Read vectors trained elsewhere, keeping the words of `dict`, write them to `fname` and load them.
+	///
+	/// Text files, as written by GloVe, word2vec or fastText, hold a word and its values per line,
+	/// optionally after a line giving the number of words and the dimension. `binary` files are those
+	/// written by word2vec, the same header followed by every word, a space and its little endian `f32` values.
+	///
+	/// Words are matched after `strip`, an exact spelling winning over others such as `Cat` for `cat`,
+	/// then the first one in the file. Words of `dict` without a vector are pruned as `Pruned::Missing`.
+	pub fn import <P: AsRef<Path>, Q: AsRef<Path>> (src: P, binary: bool, fname: Q, dict: &Dict) -> Result<Embedding> {
+		let mut r = BufReader::new(File::open(src).map_err(|_| ErrorKind::Io)?);
+		// dictionary index to whether the spelling was exact and the vector
+		let mut found: HashMap<u32, (bool, Vec<f32>)> = HashMap::new();
+		let mut dim: Option<usize> = None;
+		let mut buf: Vec<u8> = Vec::new();
+
+		// index a word should be stored at, if any
+		let slot = |found: &HashMap<u32, (bool, Vec<f32>)>, word: &str| -> Option<(u32, bool)> {
+			let s = strip(word)?;
+			let i = *dict.get(&s)?;
+			let exact = s == word;
+
+			match found.get(&i) {
+				Some((e, _)) if *e || !exact => None,
+				_ => Some((i, exact))
+			}
+		};
+
+		let header = |line: &str| -> Option<(usize, usize)> {
+			let (n, d) = line.trim().split_once(' ')?;
+			Some((n.parse().ok()?, d.parse().ok()?))
+		};
+
+		if binary {
+			r.read_until(b'\n', &mut buf).map_err(|_| ErrorKind::Io)?;
+			let (n, d) = header(&String::from_utf8_lossy(&buf)).ok_or_else(|| ErrorKind::Format)?;
+			let mut v: Vec<u8> = vec![0; d * 4];
+
+			dim = Some(d);
+
+			for _ in 0..n {
+				buf.clear();
+				r.read_until(b' ', &mut buf).map_err(|_| ErrorKind::Io)?;
+				r.read_exact(&mut v).map_err(|_| ErrorKind::Format)?;
+
+				let word = String::from_utf8_lossy(&buf);
+
+				if let Some((i, exact)) = slot(&found, word.trim()) {
+					found.insert(i, (exact, v.chunks(4).map(|c| f32::from_le_bytes(c.try_into().unwrap())).collect()));
+				}
+			}
+		} else {
+			let mut first = true;
+
+			loop {
+				buf.clear();
+
+				if r.read_until(b'\n', &mut buf).map_err(|_| ErrorKind::Io)? == 0 {
+					break;
+				}
+
+				let line = String::from_utf8_lossy(&buf);
+
+				if std::mem::take(&mut first) && header(&line).is_some() {
+					dim = header(&line).map(|h| h.1);
+					continue;
+				}
+
+				let tokens: Vec<&str> = line.split_whitespace().collect();
+
+				if tokens.is_empty() {
+					continue;
+				}
+
+				let d = *dim.get_or_insert(tokens.len() - 1);
+
+				// some GloVe words hold spaces, leaving the values as the last tokens
+				if tokens.len() <= d {
+					return Err(ErrorKind::Format.into());
+				}
+
+				let (word, values) = tokens.split_at(tokens.len() - d);
+
+				if let Some((i, exact)) = slot(&found, &word.join(" ")) {
+					let v = values.iter()
+						.map(|x| x.parse::<f32>())
+						.collect::<core::result::Result<Vec<f32>, _>>()
+						.map_err(|_| ErrorKind::Format)?;
+
+					found.insert(i, (exact, v));
+				}
+			}
+		}
+
+		let dim = dim.ok_or_else(|| ErrorKind::Format)?;
+
+		let mut kept: Vec<(&String, &u32)> = dict.iter()
+			.filter(|(_, i)| found.contains_key(i))
+			.collect();
+		kept.sort_unstable_by_key(|e| *e.1);
+
+		let pruned: HashMap<String, Pruned> = dict.iter()
+			.filter(|(_, i)| !found.contains_key(i))
+			.map(|(w, _)| (w.clone(), Pruned::Missing))
+			.collect();
+
+		let mut nd: Dict = HashMap::with_capacity(kept.len());
+		let mut vectors: Vec<f32> = Vec::with_capacity(kept.len() * dim);
+
+		for (w, i) in kept {
+			nd.insert(w.clone(), nd.len() as u32);
+			vectors.extend(found.remove(i).unwrap().1);
+		}
+
+		debug!("Imported {} vectors of dimension {}, {} words pruned.", nd.len(), dim, pruned.len());
+
+		Embedding::from_vectors(fname, nd, pruned, dim, vectors)
+	}
+
+	/// Write vectors to `fname` and load them.
+	///
+	/// Arguments
+	/// * `dict`: word to index, index `i` owning `vectors[i * dim..(i + 1) * dim]`.
+	/// * `pruned`: words without a vector and why.
+	pub fn from_vectors <P: AsRef<Path>> (fname: P, dict: Dict, pruned: HashMap<String, Pruned>, dim: usize, vectors: Vec<f32>) -> Result<Embedding> {
+		if vectors.len() != dict.len() * dim {
+			return Err(ErrorKind::Format.into());
+		}
+
+		let mut w = BufWriter::new(File::create(&fname).map_err(|_| ErrorKind::Io)?);
+
+		let mut buf: Vec<u8> = Vec::with_capacity(HEADER + vectors.len() * 4);
+
+		buf.extend(MAGIC);
+		buf.extend([VERSION, 0, 0, 0]);
+		buf.extend((dim as u32).to_be_bytes());
+		buf.extend((dict.len() as u32).to_be_bytes());
+
+		for v in vectors.iter() {
+			buf.extend(v.to_be_bytes());
+		}
+
+		w.write_all(&buf).map_err(|_| ErrorKind::Io)?;
+		w.flush().map_err(|_| ErrorKind::Io)?;
+		drop(w);
+
+		Embedding::deserialize(fname, dict, pruned)
+	}
+
+	/// Used to load embeddings from an existing file
+	fn deserialize <P: AsRef<Path>> (fname: P, dict: Dict, pruned: HashMap<String, Pruned>) -> Result<Embedding> {
+		let mut buf: Vec<u8> = Vec::new();
+
+		File::open(&fname)
+			.and_then(|mut f| f.read_to_end(&mut buf))
+			.map_err(|_| ErrorKind::Io)?;
+
+		Embedding::from_bytes(fname.as_ref().canonicalize().map_err(|_| ErrorKind::Io)?, &buf, dict, pruned)
+	}
+
+	/// Read vectors from the bytes of an embedding file, such as a section of a bundle.
+	///
+	/// `fname` is only kept to be serialized.
+	pub fn from_bytes (fname: PathBuf, buf: &[u8], dict: Dict, pruned: HashMap<String, Pruned>) -> Result<Embedding> {
+		if buf.len() < HEADER || &buf[0..4] != MAGIC || buf[4] != VERSION {
+			return Err(ErrorKind::Format.into());
+		}
+
+		let dim = u32::from_be_bytes(buf[8..12].try_into().unwrap()) as usize;
+		let n = u32::from_be_bytes(buf[12..16].try_into().unwrap()) as usize;
+
+		if n != dict.len() || buf.len() != HEADER + n * dim * 4 {
+			return Err(ErrorKind::Format.into());
+		}
+
+		let vectors: Vec<f32> = buf[HEADER..]
+			.chunks_exact(4)
+			.map(|b| f32::from_be_bytes(b.try_into().unwrap()))
+			.collect();
+
+		let norms: Vec<f64> = vectors.chunks(dim.max(1))
+			.map(|v| v.iter().map(|x| *x as f64 * *x as f64).sum::<f64>().sqrt())
+			.collect();
+
+		Ok(Embedding {
+			index: Index { fname, dict, pruned },
+			dim,
+			vectors,
+			norms,
+		})
+	}
+
+	/// Dimension of the vectors.
+	pub fn dim (&self) -> usize {
+		self.dim
+	}
+
+	/// Index of a word in the dictionary.
+	pub fn index (&self, a: &str) -> Option<u32> {
+		self.index.index(a)
+	}
+
+	/// Index of a word in the dictionary, see `Index::lookup`.
+	pub fn lookup (&self, a: &str) -> Result<u32> {
+		self.index.lookup(a)
+	}
+
+	/// Words left out of the embedding and why.
+	pub fn pruned<'a>(&'a self) -> &'a HashMap<String, Pruned> {
+		self.index.pruned()
+	}
+
+	/// Vector of the word with index `i`.
+	fn at (&self, i: usize) -> &[f32] {
+		&self.vectors[i * self.dim..(i + 1) * self.dim]
+	}
+
+	/// Vector of a word.
+	pub fn vector (&self, a: &str) -> Option<&[f32]> {
+		Some(self.at(self.index(a)? as usize))
+	}
+
+	/// Cosine similarity of words with indices `i` and `j`, 0 for a null vector.
+	fn cosine (&self, i: usize, j: usize) -> f64 {
+		if i == j {
+			return 1.;
+		}
+
+		let d: f64 = self.at(i).iter().zip(self.at(j)).map(|(x, y)| *x as f64 * *y as f64).sum();
+
+		match self.norms[i] * self.norms[j] {
+			n if n > 0. => d / n,
+			_ => 0.
+		}
+	}
+
+	/// Returns the cosine similarity between two words.
+	pub fn corr (&self, a: &str, b: &str) -> Option<f64> {
+		Some(self.cosine(self.index(a)? as usize, self.index(b)? as usize))
+	}
+
+	/// Returns the cosine similarity between the words with indices `a` and `b`.
+	pub fn score (&self, a: u32, b: u32) -> f64 {
+		self.cosine(a as usize, b as usize)
+	}
+
+	/// Returns the cosine similarities of a word with every word, ordered by index.
+	pub fn corrall (&self, a: &str) -> Option<Vec<f64>> {
+		let a = self.index(a)? as usize;
+
+		Some((0..self.index.dict.len()).map(|b| self.cosine(a, b)).collect())
+	}
+
+	/// Returns the cosine similarity of every word, ordered by index, with the weighted
+	/// sum of the unit vectors of the words with the given indices.
+	pub fn centroid (&self, words: &[(u32, f64)]) -> Vec<f64> {
+		let mut c: Vec<f64> = vec![0.; self.dim];
+
+		for (i, w) in words {
+			let n = self.norms[*i as usize];
+
+			if n > 0. {
+				for (c, x) in c.iter_mut().zip(self.at(*i as usize)) {
+					*c += w * *x as f64 / n;
+				}
+			}
+		}
+
+		let cn = c.iter().map(|x| x * x).sum::<f64>().sqrt();
+
+		(0..self.index.dict.len())
+			.map(|j| {
+				let d: f64 = c.iter().zip(self.at(j)).map(|(c, x)| c * *x as f64).sum();
+
+				match cn * self.norms[j] {
+					n if n > 0. => d / n,
+					_ => 0.
+				}
+			})
+			.collect()
+	}
+
+	pub fn dict<'a>(&'a self) -> &'a Dict {
+		&self.index.dict
+	}
+}
+
+impl PartialEq for Embedding {
+	fn eq(&self, r: &Embedding) -> bool {
+		self.index.fname == r.index.fname
+	}
+}
+
+impl Debug for Embedding {
+	fn fmt(&self, f: &mut Formatter) -> std::result::Result<(), std::fmt::Error> {
+		f.debug_struct("Embedding")
+			.field("fname", &self.index.fname)
+			.field("dim", &self.dim)
+			.finish()
+	}
+}
+
+impl Serialize for Embedding {
+	fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+		where S: Serializer
+	{
+		self.index.serialize(serializer)
+	}
+}
+
+impl<'de> Deserialize<'de> for Embedding {
+	fn deserialize<D>(deserializer: D) -> std::result::Result<Embedding, D::Error>
+		where D: Deserializer<'de>,
+	{
+		Index::deserialize(deserializer)?
+			.open(|fname, dict, pruned| Embedding::deserialize(fname, dict, pruned))
+	}
+}
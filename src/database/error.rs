@@ -1,12 +1,23 @@
 use core::fmt::{self, Display, Formatter};
+use super::correlation::Pruned;
 
 /// Error type enum.
 #[derive(Debug, PartialEq)]
 pub enum ErrorKind {
 	MissingDict,
 	InvalidWord,
+	PrunedWord(Pruned),
 	XML,
 	Serialization,
+	Format,
+	/// The data lacks what was asked of it, such as article counts.
+	Unsupported,
+	/// Stored data does not match its checksum.
+	Checksum,
+	/// The input would need more memory than allowed, such as too many words to cluster.
+	TooLarge,
+	/// Scores of the measure may exceed what cells of the precision can store.
+	Unbounded,
 	Io,
 }
 
@@ -17,9 +28,15 @@ impl Display for Error {
 	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
 		let s = match &**self {
 			ErrorKind::InvalidWord => String::from("Asked for word that was not part of the dictionary."),
+			ErrorKind::PrunedWord(p) => format!("Asked for word that was pruned from the correlation data ({:?}).", p),
     	ErrorKind::MissingDict => String::from("Frequency database missing dictionary."),
     	ErrorKind::XML => String::from("XML Error."),
     	ErrorKind::Serialization => format!("Error during serialization."),
+    	ErrorKind::Format => format!("Unrecognized data format."),
+    	ErrorKind::Unsupported => format!("Asked for data that was not kept."),
+    	ErrorKind::Checksum => format!("Data does not match its checksum."),
+    	ErrorKind::TooLarge => format!("Input too large for the memory it would need."),
+    	ErrorKind::Unbounded => format!("Scores of this measure do not fit cells of this precision."),
     	ErrorKind::Io => format!("IO Error.")
     };
     write!(f, "{}", s)
@@ -0,0 +1,345 @@
+/// Module writing similarity data in formats read by other tools
+use core::str::FromStr;
+use std::path::Path;
+use std::io::{BufWriter, Write, Seek, SeekFrom};
+use std::fs::File;
+use super::similarity::{Similarity, Filter};
+use super::error::*;
+
+/// Interchange format of exported data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+	/// word2vec text, a line with the number of words and the dimension, then a line per word.
+	Word2Vec,
+	/// word2vec binary, the same header followed by every word and its little endian `f32` values.
+	Word2VecBinary,
+	/// GloVe text, a line per word without header.
+	Glove,
+	/// Neighbor lists, a `word,neighbor,rank,score` line for each of the closest words of every word.
+	Csv,
+	/// NumPy `float32` matrix, with the words in another `.npy` file next to it.
+	Npy,
+	/// NumPy archive holding the matrix as `matrix.npy` and the words as `vocab.npy`.
+	Npz,
+}
+
+impl Format {
+	/// Usual file extension.
+	pub fn extension (&self) -> &'static str {
+		match self {
+			Format::Word2Vec => "vec",
+			Format::Word2VecBinary => "bin",
+			Format::Glove => "txt",
+			Format::Csv => "csv",
+			Format::Npy => "npy",
+			Format::Npz => "npz",
+		}
+	}
+}
+
+impl FromStr for Format {
+	type Err = Error;
+
+	fn from_str (s: &str) -> Result<Format> {
+		Ok(match s.to_lowercase().as_str() {
+			"word2vec" => Format::Word2Vec,
+			"word2vec-bin" => Format::Word2VecBinary,
+			"glove" => Format::Glove,
+			"csv" => Format::Csv,
+			"npy" => Format::Npy,
+			"npz" => Format::Npz,
+			_ => return Err(ErrorKind::Format.into())
+		})
+	}
+}
+
+/// Vector of every word in index order, as given by `Similarity::features`.
+fn rows<'a> (sim: &'a dyn Similarity, words: &'a [&'a str]) -> impl Iterator<Item = Result<Vec<f64>>> + 'a {
+	words.iter().map(move |w| sim.features(w).ok_or_else(|| ErrorKind::InvalidWord.into()))
+}
+
+/// Dimension of the vectors.
+fn dim (sim: &dyn Similarity, words: &[&str]) -> Result<usize> {
+	Ok(match words.first() {
+		Some(w) => sim.features(w).ok_or_else(|| ErrorKind::InvalidWord)?.len(),
+		None => 0
+	})
+}
+
+/// Write the data to `fname` in `format`, listing `k` neighbors per word for `Format::Csv`.
+///
+/// `Format::Npy` writes the words to the same path with the extension `vocab.npy`.
+pub fn export <P: AsRef<Path>> (sim: &dyn Similarity, format: Format, fname: P, k: usize) -> Result<()> {
+	let fname = fname.as_ref();
+	let f = File::create(fname).map_err(|_| ErrorKind::Io)?;
+
+	match format {
+		Format::Word2Vec => text(sim, &mut BufWriter::new(f), true),
+		Format::Glove => text(sim, &mut BufWriter::new(f), false),
+		Format::Word2VecBinary => binary(sim, &mut BufWriter::new(f)),
+		Format::Csv => csv(sim, &mut BufWriter::new(f), k),
+		Format::Npy => {
+			npy(sim, &mut BufWriter::new(f))?;
+
+			let v = File::create(fname.with_extension("vocab.npy")).map_err(|_| ErrorKind::Io)?;
+			vocab(sim, &mut BufWriter::new(v))
+		},
+		Format::Npz => npz(sim, f),
+	}
+}
+
+/// word2vec text, or GloVe text without `header`.
+pub fn text <W: Write> (sim: &dyn Similarity, w: &mut W, header: bool) -> Result<()> {
+	let words = sim.names();
+
+	if header {
+		writeln!(w, "{} {}", words.len(), dim(sim, &words)?).map_err(|_| ErrorKind::Io)?;
+	}
+
+	for (word, row) in words.iter().zip(rows(sim, &words)) {
+		let mut line = String::from(*word);
+
+		for v in row? {
+			line.push(' ');
+			line.push_str(&(v as f32).to_string());
+		}
+
+		line.push('\n');
+		w.write_all(line.as_bytes()).map_err(|_| ErrorKind::Io)?;
+	}
+
+	w.flush().map_err(|_| ErrorKind::Io)?;
+
+	Ok(())
+}
+
+/// word2vec binary.
+pub fn binary <W: Write> (sim: &dyn Similarity, w: &mut W) -> Result<()> {
+	let words = sim.names();
+
+	writeln!(w, "{} {}", words.len(), dim(sim, &words)?).map_err(|_| ErrorKind::Io)?;
+
+	for (word, row) in words.iter().zip(rows(sim, &words)) {
+		let mut buf: Vec<u8> = Vec::new();
+
+		buf.extend(word.as_bytes());
+		buf.push(b' ');
+
+		for v in row? {
+			buf.extend((v as f32).to_le_bytes());
+		}
+
+		buf.push(b'\n');
+		w.write_all(&buf).map_err(|_| ErrorKind::Io)?;
+	}
+
+	w.flush().map_err(|_| ErrorKind::Io)?;
+
+	Ok(())
+}
+
+/// The `k` closest words of every word, in the order of `Similarity::neighbors`.
+pub fn csv <W: Write> (sim: &dyn Similarity, w: &mut W, k: usize) -> Result<()> {
+	writeln!(w, "word,neighbor,rank,score").map_err(|_| ErrorKind::Io)?;
+
+	for word in sim.names() {
+		for n in sim.neighbors(word, k, &Filter::default())? {
+			writeln!(w, "{},{},{},{}", word, n.word, n.rank, n.score).map_err(|_| ErrorKind::Io)?;
+		}
+	}
+
+	w.flush().map_err(|_| ErrorKind::Io)?;
+
+	Ok(())
+}
+
+/// Header of a version 1.0 `.npy` file, padded so the data starts on 64 bytes.
+fn npy_header (descr: &str, shape: &str) -> Vec<u8> {
+	let mut dict = format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': {shape}, }}");
+
+	while (10 + dict.len() + 1) % 64 != 0 {
+		dict.push(' ');
+	}
+
+	dict.push('\n');
+
+	let mut buf: Vec<u8> = Vec::with_capacity(10 + dict.len());
+
+	buf.extend(b"\x93NUMPY\x01\x00");
+	buf.extend((dict.len() as u16).to_le_bytes());
+	buf.extend(dict.as_bytes());
+
+	buf
+}
+
+/// Matrix of the vectors of every word as a `.npy` file.
+pub fn npy <W: Write> (sim: &dyn Similarity, w: &mut W) -> Result<()> {
+	let words = sim.names();
+
+	w.write_all(&npy_header("<f4", &format!("({}, {})", words.len(), dim(sim, &words)?))).map_err(|_| ErrorKind::Io)?;
+
+	for row in rows(sim, &words) {
+		let buf: Vec<u8> = row?.into_iter()
+			.flat_map(|v| (v as f32).to_le_bytes())
+			.collect();
+
+		w.write_all(&buf).map_err(|_| ErrorKind::Io)?;
+	}
+
+	w.flush().map_err(|_| ErrorKind::Io)?;
+
+	Ok(())
+}
+
+/// Words in index order as a `.npy` file of fixed width unicode strings.
+pub fn vocab <W: Write> (sim: &dyn Similarity, w: &mut W) -> Result<()> {
+	let words = sim.names();
+	let width = words.iter().map(|w| w.chars().count()).max().unwrap_or(0).max(1);
+
+	w.write_all(&npy_header(&format!("<U{width}"), &format!("({},)", words.len()))).map_err(|_| ErrorKind::Io)?;
+
+	for word in words {
+		let mut buf: Vec<u8> = Vec::with_capacity(width * 4);
+
+		for c in word.chars() {
+			buf.extend((c as u32).to_le_bytes());
+		}
+
+		buf.resize(width * 4, 0);
+		w.write_all(&buf).map_err(|_| ErrorKind::Io)?;
+	}
+
+	w.flush().map_err(|_| ErrorKind::Io)?;
+
+	Ok(())
+}
+
+/// Writer counting the bytes and checksum of a zip entry.
+struct Entry<'a, W: Write> {
+	w: &'a mut W,
+	len: u64,
+	crc: crc32fast::Hasher,
+}
+
+impl<'a, W: Write> Write for Entry<'a, W> {
+	fn write (&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		let n = self.w.write(buf)?;
+
+		self.crc.update(&buf[..n]);
+		self.len += n as u64;
+
+		Ok(n)
+	}
+
+	fn flush (&mut self) -> std::io::Result<()> {
+		self.w.flush()
+	}
+}
+
+/// Matrix and words as an uncompressed zip64 `.npz` archive.
+///
+/// Entries are streamed, their sizes and checksums being filled in afterwards.
+pub fn npz (sim: &dyn Similarity, f: File) -> Result<()> {
+	const VERSION: u16 = 45;
+	// 1980-01-01, the earliest DOS date
+	const DATE: u16 = 33;
+
+	let mut w = BufWriter::new(f);
+	let mut central: Vec<u8> = Vec::new();
+	let mut offset: u64 = 0;
+
+	let entries: [(&str, fn(&dyn Similarity, &mut Entry<BufWriter<File>>) -> Result<()>); 2] = [
+		("matrix.npy", |s, w| npy(s, w)),
+		("vocab.npy", |s, w| vocab(s, w)),
+	];
+
+	for (name, write) in entries {
+		let mut local: Vec<u8> = Vec::new();
+
+		local.extend(0x04034b50u32.to_le_bytes());
+		local.extend(VERSION.to_le_bytes());
+		local.extend([0, 0, 0, 0]); // flags, stored
+		local.extend([0, 0]);
+		local.extend(DATE.to_le_bytes());
+		local.extend(0u32.to_le_bytes()); // crc, filled in later
+		local.extend(u32::MAX.to_le_bytes());
+		local.extend(u32::MAX.to_le_bytes());
+		local.extend((name.len() as u16).to_le_bytes());
+		local.extend(20u16.to_le_bytes());
+		local.extend(name.as_bytes());
+		local.extend(1u16.to_le_bytes()); // zip64 sizes, filled in later
+		local.extend(16u16.to_le_bytes());
+		local.extend([0; 16]);
+
+		w.write_all(&local).map_err(|_| ErrorKind::Io)?;
+
+		let mut e = Entry { w: &mut w, len: 0, crc: crc32fast::Hasher::new() };
+		write(sim, &mut e)?;
+
+		let (len, crc) = (e.len, e.crc.finalize());
+
+		w.seek(SeekFrom::Start(offset + 14)).map_err(|_| ErrorKind::Io)?;
+		w.write_all(&crc.to_le_bytes()).map_err(|_| ErrorKind::Io)?;
+		w.seek(SeekFrom::Start(offset + 30 + name.len() as u64 + 4)).map_err(|_| ErrorKind::Io)?;
+		w.write_all(&len.to_le_bytes()).map_err(|_| ErrorKind::Io)?;
+		w.write_all(&len.to_le_bytes()).map_err(|_| ErrorKind::Io)?;
+		w.seek(SeekFrom::End(0)).map_err(|_| ErrorKind::Io)?;
+
+		central.extend(0x02014b50u32.to_le_bytes());
+		central.extend(VERSION.to_le_bytes());
+		central.extend(VERSION.to_le_bytes());
+		central.extend([0, 0, 0, 0]);
+		central.extend([0, 0]);
+		central.extend(DATE.to_le_bytes());
+		central.extend(crc.to_le_bytes());
+		central.extend(u32::MAX.to_le_bytes());
+		central.extend(u32::MAX.to_le_bytes());
+		central.extend((name.len() as u16).to_le_bytes());
+		central.extend(28u16.to_le_bytes());
+		central.extend([0; 6]); // comment, disk, internal attributes
+		central.extend(0u32.to_le_bytes());
+		central.extend(u32::MAX.to_le_bytes());
+		central.extend(name.as_bytes());
+		central.extend(1u16.to_le_bytes());
+		central.extend(24u16.to_le_bytes());
+		central.extend(len.to_le_bytes());
+		central.extend(len.to_le_bytes());
+		central.extend(offset.to_le_bytes());
+
+		offset += local.len() as u64 + len;
+	}
+
+	let end = offset + central.len() as u64;
+	let n = entries.len() as u64;
+
+	let mut tail: Vec<u8> = central;
+
+	// zip64 end of central directory record and its locator
+	tail.extend(0x06064b50u32.to_le_bytes());
+	tail.extend(44u64.to_le_bytes());
+	tail.extend(VERSION.to_le_bytes());
+	tail.extend(VERSION.to_le_bytes());
+	tail.extend([0; 8]);
+	tail.extend(n.to_le_bytes());
+	tail.extend(n.to_le_bytes());
+	tail.extend((end - offset).to_le_bytes());
+	tail.extend(offset.to_le_bytes());
+
+	tail.extend(0x07064b50u32.to_le_bytes());
+	tail.extend(0u32.to_le_bytes());
+	tail.extend(end.to_le_bytes());
+	tail.extend(1u32.to_le_bytes());
+
+	tail.extend(0x06054b50u32.to_le_bytes());
+	tail.extend([0; 4]);
+	tail.extend(u16::MAX.to_le_bytes());
+	tail.extend(u16::MAX.to_le_bytes());
+	tail.extend(u32::MAX.to_le_bytes());
+	tail.extend(u32::MAX.to_le_bytes());
+	tail.extend(0u16.to_le_bytes());
+
+	w.write_all(&tail).map_err(|_| ErrorKind::Io)?;
+	w.flush().map_err(|_| ErrorKind::Io)?;
+
+	Ok(())
+}
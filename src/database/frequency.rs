@@ -7,7 +7,8 @@ use serde::ser::{Serialize, SerializeStruct, Serializer};
 use std::collections::{ HashMap };
 use regex::Regex;
 use lazy_static::lazy_static;
-use std::io::{BufWriter, BufReader, Seek, SeekFrom};
+use std::io::{self, BufWriter, BufReader, Seek, SeekFrom, Write};
+use std::sync::Arc;
 use std::fs::{File, OpenOptions};
 use log::{debug, trace, error};
 use super::error::*;
@@ -17,18 +18,28 @@ lazy_static! {
 	static ref WORD: Regex = Regex::new(r"\b([^\s]+?)(?:'s?)?\b").unwrap();
 }
 
+/// Raw tokens of an article as byte ranges, in the order `Frequency::insert` reads them.
+pub fn tokens ( article: &str ) -> impl Iterator<Item = (usize, usize)> + '_ {
+	WORD.captures_iter(article)
+		.filter_map(|c| c.get(1))
+		.map(|m| (m.start(), m.end()))
+}
+
 /// Struct representing the frequency analysis of words in the database.
-pub struct Frequency<'a> {
+///
+/// The dictionary is shared, so a table can be moved to another thread
+/// and several tables (shards) can be filled in parallel and merged afterwards.
+pub struct Frequency {
 	fname: PathBuf,
 	writer: BufWriter<File>,
 	reader: BufReader<File>,
 	index: Vec<u64>,
-	dict: Option<&'a Dict>,
+	dict: Option<Arc<Dict>>,
 }
 
-impl<'a> Frequency<'a> {
+impl Frequency {
 	/// Create a new empty frequency data table with a dictionary.
-	pub fn new <P: AsRef<Path>> ( fname: P, dict: &'a Dict ) -> Result<Frequency<'a>> {
+	pub fn new <P: AsRef<Path>> ( fname: P, dict: Arc<Dict> ) -> Result<Frequency> {
 		Ok(Frequency {
 			writer: BufWriter::new(File::create(&fname).map_err(|_| ErrorKind::Io)?),
 			reader: BufReader::new(File::open(&fname).map_err(|_| ErrorKind::Io)?),
@@ -39,7 +50,7 @@ impl<'a> Frequency<'a> {
 	}
 
 	/// Load a read-only frequency data table from data.
-	fn deserialize <P: AsRef<Path>> ( fname: P, index: Vec<u64> ) -> Result<Frequency<'static>> {
+	fn deserialize <P: AsRef<Path>> ( fname: P, index: Vec<u64> ) -> Result<Frequency> {
 		Ok(Frequency {
 			writer: BufWriter::new(OpenOptions::new().append(true).open(&fname).map_err(|_| ErrorKind::Io)?),
 			reader: BufReader::new(File::open(&fname).map_err(|_| ErrorKind::Io)?),
@@ -50,10 +61,113 @@ impl<'a> Frequency<'a> {
 	}
 
 	/// Make a frequency table writable by loading a dict
-	pub fn set_dict ( &mut self, dict: &'a Dict ) {
+	pub fn set_dict ( &mut self, dict: Arc<Dict> ) {
 		self.dict = Some(dict);
 	}
 
+	/// Create a new empty shard sharing this table's dictionary.
+	///
+	/// Returns
+	/// * `Err(ErrorKind::MissingDict)` if this table has no dictionary.
+	pub fn shard <P: AsRef<Path>> ( &self, fname: P ) -> Result<Frequency> {
+		let dict = self.dict.clone().ok_or_else(|| ErrorKind::MissingDict)?;
+
+		Frequency::new(fname, dict)
+	}
+
+	/// Merge shards into a single table stored at `fname`.
+	///
+	/// Articles keep the order of `shards`, so the articles of the second shard
+	/// directly follow those of the first and the document index stays continuous.
+	/// The shard files are left untouched.
+	///
+	/// Returns
+	/// * `Err(ErrorKind::Format)` if the shards were not filled with the same dictionary,
+	/// 	or only some of them have one.
+	/// * `Err(ErrorKind::Io)` if `fname` is one of the shards, which would be
+	/// 	truncated before it is read.
+	pub fn merge <P: AsRef<Path>> ( fname: P, shards: Vec<Frequency> ) -> Result<Frequency> {
+		Frequency::join(fname, shards, |indices| indices.into_iter().flatten().collect())
+	}
+
+	/// Merge shards dealt articles in turn, the `i`th article having gone to shard `i % shards.len()`,
+	/// into a single table stored at `fname`.
+	///
+	/// Articles take back the order they were dealt in, so document ids follow that order
+	/// as if a single table had been filled. The shard files are left untouched.
+	///
+	/// Returns
+	/// * `Err(ErrorKind::Format)` if the shards were not filled with the same dictionary,
+	/// 	only some of them have one, or their lengths could not come from dealing articles in turn.
+	/// * `Err(ErrorKind::Io)` if `fname` is one of the shards.
+	pub fn interleave <P: AsRef<Path>> ( fname: P, shards: Vec<Frequency> ) -> Result<Frequency> {
+		// earlier shards hold at most one article more than later ones
+		if shards.windows(2).any(|w| w[0].len() < w[1].len()) || shards.first().map_or(0, |s| s.len()) > shards.last().map_or(0, |s| s.len()) + 1 {
+			return Err(ErrorKind::Format.into());
+		}
+
+		Frequency::join(fname, shards, |indices| {
+			let len = indices.iter().map(|i| i.len()).sum();
+
+			(0..len).map(|i| indices[i % indices.len()][i / indices.len()]).collect()
+		})
+	}
+
+	/// Copy shards one after the other into `fname`, `order` arranging their rebased indices
+	/// into the index of the merged table.
+	fn join <P: AsRef<Path>, F: FnOnce(Vec<Vec<u64>>) -> Vec<u64>> ( fname: P, shards: Vec<Frequency>, order: F ) -> Result<Frequency> {
+		let out = fname.as_ref().canonicalize().ok();
+
+		if shards.iter().any(|s| out.as_ref() == Some(&s.fname)) {
+			return Err(ErrorKind::Io.into());
+		}
+
+		let dict = shards.iter().find_map(|s| s.dict.as_ref());
+
+		if shards.iter().any(|s| match (&s.dict, dict) {
+			(Some(a), Some(b)) => !Arc::ptr_eq(a, b) && a != b,
+			// a shard read back from disk cannot tell which dictionary filled it
+			(None, Some(_)) => true,
+			_ => false
+		}) {
+			return Err(ErrorKind::Format.into());
+		}
+
+		let dict = dict.cloned();
+		let mut writer = BufWriter::new(File::create(&fname).map_err(|_| ErrorKind::Io)?);
+		let mut indices: Vec<Vec<u64>> = Vec::with_capacity(shards.len());
+		let mut base = 0;
+
+		for mut shard in shards {
+			shard.flush()?;
+
+			debug!("Merging shard {:?} with {} articles at byte {}.", shard.fname, shard.len(), base);
+
+			indices.push(shard.index.iter().map(|e| e + base).collect());
+
+			shard.reader.seek(SeekFrom::Start(0))
+				.map_err(|_| ErrorKind::Io)?;
+
+			base += io::copy(&mut shard.reader, &mut writer)
+				.map_err(|_| ErrorKind::Io)?;
+		}
+
+		writer.flush().map_err(|_| ErrorKind::Io)?;
+
+		Ok(Frequency {
+			writer,
+			reader: BufReader::new(File::open(&fname).map_err(|_| ErrorKind::Io)?),
+			fname: fname.as_ref().canonicalize().map_err(|_| ErrorKind::Io)?,
+			index: order(indices),
+			dict,
+		})
+	}
+
+	/// Flush pending article data to disk.
+	pub fn flush ( &mut self ) -> Result<()> {
+		self.writer.flush().map_err(|_| ErrorKind::Io.into())
+	}
+
 	/// Parses a string to find all occurrences of valid words.
 	///
 	/// Arguments
@@ -64,7 +178,7 @@ impl<'a> Frequency<'a> {
 	/// 	usually occurring if the data has been loaded from file.
 	/// * `Ok( () )` if parsed properly
 	pub fn insert ( &mut self, article: String ) -> Result<()> {
-		let dict = self.dict.ok_or_else(|| ErrorKind::MissingDict)?;
+		let dict = self.dict.as_ref().ok_or_else(|| ErrorKind::MissingDict)?;
 		let mut data: HashMap<u32,u16> = HashMap::new();
 
 		self.index.push(self.writer.stream_position()
@@ -73,9 +187,8 @@ impl<'a> Frequency<'a> {
 		debug!("Loading article {} with {} chars.", self.index.len(), article.len());
 		trace!(target: "app::dump", "raw article:\n{}", article);
 
-		for word in WORD.captures_iter(&article) {
-			let word = &word[1];
-			if let Some(word) = strip(word) {
+		for (s, e) in tokens(&article) {
+			if let Some(word) = strip(&article[s..e]) {
 				if let Some(i) = dict.get(&word) {
 					*data.entry(*i)
 						.or_insert(0) += 1;
@@ -99,6 +212,8 @@ impl<'a> Frequency<'a> {
 	pub fn load( &mut self ) -> Result<HashMap<u32, Vec<(u32, u16)>>> {
 		let mut map: HashMap<u32, Vec<(u32, u16)>> = HashMap::new();
 
+		self.flush()?;
+
 		for id in 0..self.index.len() {
 			let start = self.index[id];
 
@@ -131,13 +246,13 @@ impl<'a> Frequency<'a> {
 	}
 }
 
-impl PartialEq for Frequency<'_> {
+impl PartialEq for Frequency {
 	fn eq(&self, r: &Frequency) -> bool {
 		self.fname == r.fname && self.index.len() == r.index.len() && self.index == r.index
 	}
 }
 
-impl Debug for Frequency<'_> {
+impl Debug for Frequency {
 	fn fmt(&self, f: &mut Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
 		f.debug_struct("Frequency")
 			.field("fname", &self.fname)
@@ -146,7 +261,7 @@ impl Debug for Frequency<'_> {
 	}
 }
 
-impl Serialize for Frequency<'_> {
+impl Serialize for Frequency {
 	fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
 		where S: Serializer
 	{ 
@@ -157,8 +272,8 @@ impl Serialize for Frequency<'_> {
 	}
 }
 
-impl<'de> Deserialize<'de> for Frequency<'_> {
-	fn deserialize<D>(deserializer: D) -> std::result::Result<Frequency<'static>, D::Error>
+impl<'de> Deserialize<'de> for Frequency {
+	fn deserialize<D>(deserializer: D) -> std::result::Result<Frequency, D::Error>
 		where D: Deserializer<'de>,
 	{
 		// implementation following https://serde.rs/deserialize-struct.html
@@ -170,7 +285,7 @@ impl<'de> Deserialize<'de> for Frequency<'_> {
 		struct FrequencyVisitor;
 
 		impl<'de> Visitor<'de> for FrequencyVisitor {
-			type Value = Frequency<'static>;
+			type Value = Frequency;
 
 			fn expecting(&self, formatter: &mut Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
 				formatter.write_str("A struct representing frequency analysis data.")
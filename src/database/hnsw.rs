@@ -0,0 +1,341 @@
+/// Module providing approximate nearest neighbor search over any similarity source
+use std::path::Path;
+use std::collections::{BinaryHeap, HashSet};
+use std::cmp::{Ordering, Reverse};
+use std::io::{BufWriter, Write};
+use std::fs::File;
+use std::time::Instant;
+use serde::{Serialize, Deserialize};
+use rand::{Rng, SeedableRng, rngs::SmallRng};
+use log::info;
+use super::similarity::Similarity;
+use super::build;
+use super::error::*;
+
+/// Options of the graph construction.
+#[derive(Debug, Clone)]
+pub struct Options {
+	/// Links of a word on every layer above the bottom one, which keeps twice as many.
+	pub m: usize,
+	/// Candidates considered when linking a new word.
+	pub ef: usize,
+	/// Seed of the layer assignment.
+	pub seed: u64,
+}
+
+impl Default for Options {
+	fn default() -> Options {
+		Options {
+			m: 16,
+			ef: 200,
+			seed: 0,
+		}
+	}
+}
+
+/// Word index and its score against a query, ordered by score as in `build::by_score`.
+#[derive(Debug, Clone, Copy)]
+struct Scored(f64, u32);
+
+impl PartialEq for Scored {
+	fn eq(&self, r: &Scored) -> bool {
+		self.cmp(r) == Ordering::Equal
+	}
+}
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+	fn partial_cmp(&self, r: &Scored) -> Option<Ordering> {
+		Some(self.cmp(r))
+	}
+}
+
+impl Ord for Scored {
+	fn cmp(&self, r: &Scored) -> Ordering {
+		build::key(self.0).total_cmp(&build::key(r.0)).then(r.1.cmp(&self.1))
+	}
+}
+
+/// Hierarchical navigable small world graph over the words of a similarity source.
+///
+/// Only the links are stored, scores are read from the source the graph was built
+/// on, which has to be given again to every query.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Hnsw {
+	m: usize,
+	/// Word every search starts from, on the top layer.
+	entry: u32,
+	/// Highest layer.
+	top: usize,
+	/// Links of every word on every layer it is part of.
+	links: Vec<Vec<Vec<u32>>>,
+}
+
+/// Quality and speed of approximate search against exact search.
+#[derive(Serialize, Debug, Clone)]
+pub struct Recall {
+	pub queries: usize,
+	pub k: usize,
+	pub ef: usize,
+	/// Mean share of the exact `k` nearest words found.
+	pub recall: f64,
+	/// Mean time of an exact query in milliseconds.
+	pub exact: f64,
+	/// Mean time of an approximate query in milliseconds.
+	pub approx: f64,
+}
+
+impl std::fmt::Display for Recall {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		writeln!(f, "{} queries, k = {}, ef = {}", self.queries, self.k, self.ef)?;
+		writeln!(f, "recall: {:.4}", self.recall)?;
+		writeln!(f, "exact: {:.3} ms/query", self.exact)?;
+		writeln!(f, "approximate: {:.3} ms/query", self.approx)
+	}
+}
+
+impl Hnsw {
+	/// Link every word of `sim`, in index order.
+	///
+	/// Words are given a random highest layer, and on each layer linked to the closest
+	/// words found by a search of `options.ef` candidates, preferring candidates closer
+	/// to the word than to those already picked so links span the neighborhood.
+	pub fn new (sim: &dyn Similarity, options: &Options) -> Hnsw {
+		let n = sim.dict().len();
+		let m = options.m.max(2);
+		let ml = 1. / (m as f64).ln();
+
+		let mut rng = SmallRng::seed_from_u64(options.seed);
+		let mut g = Hnsw { m, entry: 0, top: 0, links: Vec::with_capacity(n) };
+
+		let started = Instant::now();
+
+		for q in 0..n as u32 {
+			let level = (-rng.gen_range(f64::EPSILON..1.).ln() * ml) as usize;
+
+			g.links.push(vec![Vec::new(); level + 1]);
+
+			if q == 0 {
+				g.top = level;
+				continue;
+			}
+
+			let mut ep = vec![Scored(sim.score(q, g.entry), g.entry)];
+
+			for lc in (level + 1..=g.top).rev() {
+				ep = g.layer(sim, q, ep, 1, lc);
+			}
+
+			for lc in (0..=level.min(g.top)).rev() {
+				let found = g.layer(sim, q, ep, options.ef, lc);
+				let picked = pick(sim, &found, m);
+
+				for e in picked.iter() {
+					g.links[*e as usize][lc].push(q);
+
+					if g.links[*e as usize][lc].len() > g.max(lc) {
+						let cands = g.scored(sim, *e, &g.links[*e as usize][lc]);
+						g.links[*e as usize][lc] = pick(sim, &cands, g.max(lc));
+					}
+				}
+
+				g.links[q as usize][lc] = picked;
+				ep = found;
+			}
+
+			if level > g.top {
+				g.top = level;
+				g.entry = q;
+			}
+
+			if (q + 1) % 10000 == 0 {
+				info!("Linked {} of {} words, {:.1} words/s.", q + 1, n, (q + 1) as f64 / started.elapsed().as_secs_f64());
+			}
+		}
+
+		g
+	}
+
+	/// Most links of a word on layer `lc`.
+	fn max (&self, lc: usize) -> usize {
+		match lc {
+			0 => 2 * self.m,
+			_ => self.m
+		}
+	}
+
+	/// Words scored against `q`, highest first.
+	fn scored (&self, sim: &dyn Similarity, q: u32, words: &[u32]) -> Vec<Scored> {
+		let mut s: Vec<Scored> = words.iter()
+			.map(|e| Scored(sim.score(q, *e), *e))
+			.collect();
+
+		s.sort_unstable_by(|a, b| b.cmp(a));
+		s
+	}
+
+	/// The `ef` words closest to `q` reached from `ep` on layer `lc`, highest first.
+	fn layer (&self, sim: &dyn Similarity, q: u32, ep: Vec<Scored>, ef: usize, lc: usize) -> Vec<Scored> {
+		let mut seen: HashSet<u32> = ep.iter().map(|e| e.1).collect();
+		let mut cands: BinaryHeap<Scored> = ep.iter().copied().collect();
+		let mut found: BinaryHeap<Reverse<Scored>> = ep.into_iter().map(Reverse).collect();
+
+		while found.len() > ef {
+			found.pop();
+		}
+
+		while let Some(c) = cands.pop() {
+			match found.peek() {
+				Some(Reverse(w)) if found.len() >= ef && c < *w => break,
+				_ => ()
+			}
+
+			for e in self.links[c.1 as usize][lc].iter() {
+				if !seen.insert(*e) {
+					continue;
+				}
+
+				let s = Scored(sim.score(q, *e), *e);
+
+				match found.peek() {
+					Some(Reverse(w)) if found.len() >= ef && s < *w => (),
+					_ => {
+						cands.push(s);
+						found.push(Reverse(s));
+
+						if found.len() > ef {
+							found.pop();
+						}
+					}
+				}
+			}
+		}
+
+		let mut ret: Vec<Scored> = found.into_iter().map(|e| e.0).collect();
+		ret.sort_unstable_by(|a, b| b.cmp(a));
+		ret
+	}
+
+	/// Approximately the `k` words most similar to the word with index `q`, itself included,
+	/// highest first, searching `ef` candidates.
+	pub fn search (&self, sim: &dyn Similarity, q: u32, k: usize, ef: usize) -> Vec<(u32, f64)> {
+		if self.links.is_empty() {
+			return Vec::new();
+		}
+
+		let mut ep = vec![Scored(sim.score(q, self.entry), self.entry)];
+
+		for lc in (1..=self.top).rev() {
+			ep = self.layer(sim, q, ep, 1, lc);
+		}
+
+		self.layer(sim, q, ep, ef.max(k), 0)
+			.into_iter()
+			.take(k)
+			.map(|s| (s.1, s.0))
+			.collect()
+	}
+
+	/// Approximately the `k` words most similar to a word, like `search`.
+	pub fn query (&self, sim: &dyn Similarity, a: &str, k: usize, ef: usize) -> Option<Vec<(u32, f64)>> {
+		Some(self.search(sim, sim.index(a)?, k, ef))
+	}
+
+	/// Compare approximate search of the `k` most similar words to exact search, for every word of `words`.
+	pub fn recall (&self, sim: &dyn Similarity, words: &[&str], k: usize, ef: usize) -> Recall {
+		let (mut hits, mut exact, mut approx) = (0, 0., 0.);
+
+		for w in words {
+			let start = Instant::now();
+			let e = sim.top(w, k).unwrap_or_default();
+			exact += start.elapsed().as_secs_f64();
+
+			let start = Instant::now();
+			let a = self.query(sim, w, k, ef).unwrap_or_default();
+			approx += start.elapsed().as_secs_f64();
+
+			let e: HashSet<u32> = e.into_iter().map(|e| e.0).collect();
+			hits += a.iter().filter(|(i, _)| e.contains(i)).count();
+		}
+
+		let q = words.len().max(1) as f64;
+
+		Recall {
+			queries: words.len(),
+			k,
+			ef,
+			recall: hits as f64 / (q * k.min(self.links.len()).max(1) as f64),
+			exact: exact * 1e3 / q,
+			approx: approx * 1e3 / q,
+		}
+	}
+
+	/// Number of words in the graph.
+	pub fn len (&self) -> usize {
+		self.links.len()
+	}
+
+	/// Read a graph built on `sim`.
+	///
+	/// Returns
+	/// * `Err(ErrorKind::Format)` if the graph holds another number of words, or links
+	/// 	words or layers it does not hold, as a stale or damaged file may.
+	pub fn load <P: AsRef<Path>> (fname: P, sim: &dyn Similarity) -> Result<Hnsw> {
+		let f = File::open(fname).map_err(|_| ErrorKind::Io)?;
+		let g: Hnsw = bincode::deserialize_from(f).map_err(|_| ErrorKind::Serialization)?;
+
+		let n = g.len();
+
+		// searches start from the entry on the top layer and follow links down the layers
+		let entry = n == 0 || ((g.entry as usize) < n && g.links[g.entry as usize].len() == g.top + 1);
+		let links = g.links.iter().all(|layers| layers.iter().enumerate().all(|(lc, l)| {
+			l.iter().all(|e| (*e as usize) < n && g.links[*e as usize].len() > lc)
+		}));
+
+		match n == sim.dict().len() && entry && links {
+			true => Ok(g),
+			false => Err(ErrorKind::Format.into())
+		}
+	}
+
+	/// Write the graph.
+	pub fn save <P: AsRef<Path>> (&self, fname: P) -> Result<()> {
+		let mut w = BufWriter::new(File::create(fname).map_err(|_| ErrorKind::Io)?);
+
+		bincode::serialize_into(&mut w, self).map_err(|_| ErrorKind::Io)?;
+		w.flush().map_err(|_| ErrorKind::Io)?;
+
+		Ok(())
+	}
+}
+
+/// Up to `m` of the candidates, highest first, to link to the word they were scored against.
+///
+/// A candidate is picked when it is closer to the word than to any picked one, the
+/// closest remaining candidates fill up the rest.
+fn pick (sim: &dyn Similarity, cands: &[Scored], m: usize) -> Vec<u32> {
+	let mut out: Vec<u32> = Vec::with_capacity(m);
+
+	for c in cands {
+		if out.len() >= m {
+			break;
+		}
+
+		if out.iter().all(|r| build::key(sim.score(c.1, *r)) < build::key(c.0)) {
+			out.push(c.1);
+		}
+	}
+
+	for c in cands {
+		if out.len() >= m {
+			break;
+		}
+
+		if !out.contains(&c.1) {
+			out.push(c.1);
+		}
+	}
+
+	out
+}
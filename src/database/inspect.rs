@@ -0,0 +1,406 @@
+/// Module checking correlation files for damage and repairing them
+use core::str::FromStr;
+use core::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write, Seek, SeekFrom};
+use serde::Serialize;
+use memmap2::Mmap;
+use super::correlation::Correlation;
+use super::matrix::{Header, Precision, Layout};
+use super::measure::Measure;
+use super::similarity::{Similarity, Index, Filter};
+use super::support::Postings;
+use super::error::*;
+
+/// Tolerance of rounding errors when checking that scores fall in the range of their measure.
+const EPSILON: f64 = 1e-6;
+
+/// Value given to damaged cells.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Policy {
+	/// 0, no association.
+	Zero,
+	/// Lowest score of the measure, or the lowest finite score of the file for unbounded
+	/// measures, so damaged pairs rank last.
+	Lowest,
+	/// Finite scores out of range are clamped to it, not a number and infinite ones become 0.
+	Clamp,
+}
+
+impl FromStr for Policy {
+	type Err = Error;
+
+	fn from_str (s: &str) -> Result<Policy> {
+		Ok(match s.to_lowercase().as_str() {
+			"zero" => Policy::Zero,
+			"lowest" => Policy::Lowest,
+			"clamp" => Policy::Clamp,
+			_ => return Err(ErrorKind::Format.into())
+		})
+	}
+}
+
+/// Damaged cell.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Cell {
+	pub a: String,
+	pub b: String,
+	/// Stored value, not a number values being serialized as `null`.
+	pub value: f64,
+}
+
+/// Closest words of a sample word.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Sample {
+	pub word: String,
+	pub neighbors: Vec<(String, f64)>,
+}
+
+/// Options of an inspection.
+#[derive(Debug, Clone)]
+pub struct Options {
+	/// Data file read instead of the one named by the index, such as a moved copy.
+	pub data: Option<PathBuf>,
+	/// Number of damaged cells listed.
+	pub examples: usize,
+	/// Words whose neighbors are listed.
+	pub samples: Vec<String>,
+	/// Number of words listed when `samples` is empty, spread evenly over the dictionary.
+	pub spread: usize,
+	/// Number of neighbors listed per sample word.
+	pub k: usize,
+	/// Rewrite damaged cells, the data file being replaced by a repaired copy.
+	pub repair: Option<Policy>,
+}
+
+impl Default for Options {
+	fn default() -> Options {
+		Options {
+			data: None,
+			examples: 20,
+			samples: Vec::new(),
+			spread: 3,
+			k: 10,
+			repair: None,
+		}
+	}
+}
+
+/// State of a correlation index and its data file.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Inspection {
+	pub data: PathBuf,
+	/// Words of the dictionary.
+	pub words: usize,
+	pub pruned: usize,
+	/// Whether the data file predates headers.
+	pub legacy: bool,
+	pub precision: Precision,
+	pub layout: Layout,
+	pub measure: Measure,
+	/// Words given by the header of the data file.
+	pub header_words: u64,
+	/// Size of the data file in bytes, as expected from the dictionary and header.
+	pub expected: u64,
+	pub actual: u64,
+	/// Disagreements between the dictionary and the files.
+	pub mismatches: Vec<String>,
+	/// Stored cells read.
+	pub cells: u64,
+	pub nan: u64,
+	pub infinite: u64,
+	/// Finite scores outside of `range`.
+	pub out_of_range: u64,
+	pub range: (f64, f64),
+	/// First damaged cells found.
+	pub damaged: Vec<Cell>,
+	/// Words with not a number scores and how many, most first, such as words without variance.
+	pub nan_words: Vec<(String, u64)>,
+	pub samples: Vec<Sample>,
+	/// Cells rewritten by the repair.
+	pub repaired: u64,
+}
+
+/// Value replacing a damaged cell.
+fn fix (v: f64, policy: Policy, range: (f64, f64), lowest: f64) -> f64 {
+	match policy {
+		Policy::Zero => 0.,
+		Policy::Lowest => match range.0.is_finite() {
+			true => range.0,
+			false => lowest
+		},
+		Policy::Clamp => match v.is_finite() {
+			true => v.clamp(range.0, range.1),
+			false => 0.
+		},
+	}
+}
+
+/// Check a correlation index and its data file, and repair damaged cells if asked.
+///
+/// Files are read directly rather than through `Correlation`, so that data of the wrong
+/// size, or moved away from the path its index holds, may still be inspected.
+pub fn inspect <P: AsRef<Path>> (index: P, options: &Options) -> Result<Inspection> {
+	let index = Index::read(index)?;
+	let fname = options.data.clone().unwrap_or_else(|| index.fname.clone());
+	let n = index.dict.len();
+
+	let mut mismatches: Vec<String> = Vec::new();
+
+	let mut names: Vec<Option<&str>> = vec![None; n];
+
+	for (w, i) in index.dict.iter() {
+		match names.get_mut(*i as usize) {
+			Some(s @ None) => *s = Some(w),
+			Some(Some(o)) => mismatches.push(format!("{w} and {o} share index {i}")),
+			None => mismatches.push(format!("{w} has index {i}, beyond the {n} words of the dictionary")),
+		}
+	}
+
+	if names.iter().any(|w| w.is_none()) {
+		mismatches.push(format!("{} indices have no word", names.iter().filter(|w| w.is_none()).count()));
+	}
+
+	let both: Vec<&String> = index.pruned.keys().filter(|w| index.dict.contains_key(*w)).collect();
+
+	if !both.is_empty() {
+		mismatches.push(format!("{} words are both scored and pruned, such as {}", both.len(), both[0]));
+	}
+
+	let f = File::open(&fname).map_err(|_| ErrorKind::Io)?;
+
+	// Safety: the data is never written, repairs replace it with a copy.
+	let data = unsafe { Mmap::map(&f) }.map_err(|_| ErrorKind::Io)?;
+	let header = Header::parse(&mut &data[..], n as u64, data.len() as u64)?;
+
+	if header.words != n as u64 {
+		mismatches.push(format!("the header holds {} words, the dictionary {}", header.words, n));
+	}
+
+	let (expected, actual) = (header.size(), data.len() as u64);
+
+	if expected != actual {
+		mismatches.push(format!("the data file holds {actual} bytes, {expected} were expected"));
+	}
+
+	let support = fname.with_extension("support");
+
+	if support.exists() && Postings::open(&support, n).is_err() {
+		mismatches.push(format!("{} does not match the dictionary", support.display()));
+	}
+
+	let range = header.measure.association().range();
+	let width = header.precision.width() as u64;
+	let name = |i: u64| names[i as usize].unwrap_or("?").to_owned();
+
+	let mut cells: u64 = 0;
+	let (mut nan, mut infinite, mut out_of_range) = (0, 0, 0);
+	let mut per_word: Vec<u64> = vec![0; n];
+	let mut damaged: Vec<Cell> = Vec::new();
+	let mut bad: Vec<(u64, f64)> = Vec::new();
+	let mut lowest = f64::INFINITY;
+
+	// rows beyond either count of words are not read, as their cells may mean other pairs
+	let m = header.words.min(n as u64);
+
+	for a in 0..m {
+		let bs = match header.layout {
+			Layout::Full => 0..m,
+			Layout::Triangular => 0..a + 1,
+		};
+
+		for b in bs {
+			let i = match header.find(a, b) {
+				Some(i) if header.pos(i) + width <= actual => i,
+				_ => continue
+			};
+
+			let p = header.pos(i) as usize;
+			let v = header.precision.decode(&data[p..p + width as usize]);
+
+			cells += 1;
+
+			let ok = match v {
+				v if v.is_nan() => {
+					nan += 1;
+					per_word[a as usize] += 1;
+
+					if a != b {
+						per_word[b as usize] += 1;
+					}
+
+					false
+				},
+				v if v.is_infinite() => { infinite += 1; false },
+				v if v < range.0 - EPSILON || v > range.1 + EPSILON => { out_of_range += 1; false },
+				v => {
+					lowest = lowest.min(v);
+					true
+				}
+			};
+
+			if !ok {
+				if damaged.len() < options.examples {
+					damaged.push(Cell { a: name(a), b: name(b), value: v });
+				}
+
+				if options.repair.is_some() {
+					bad.push((i, v));
+				}
+			}
+		}
+	}
+
+	let mut nan_words: Vec<(String, u64)> = per_word.iter()
+		.enumerate()
+		.filter(|(_, c)| **c > 0)
+		.map(|(i, c)| (name(i as u64), *c))
+		.collect();
+	nan_words.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+	nan_words.truncate(options.examples);
+
+	let words: Vec<String> = match options.samples.is_empty() {
+		true => (0..options.spread.min(n))
+			.filter_map(|i| names[i * n / options.spread].map(|w| w.to_owned()))
+			.collect(),
+		false => options.samples.clone()
+	};
+
+	// neighbors are only listed when the file is whole, as rows would be cut short otherwise
+	let samples: Vec<Sample> = match (expected == actual && header.words == n as u64, words.is_empty()) {
+		(true, false) => {
+			// Safety: as above
+			let map = unsafe { Mmap::map(&f) }.map_err(|_| ErrorKind::Io)?;
+			let corr = Correlation::from_map(fname.clone(), map, index.dict.clone(), index.pruned.clone(), None)?;
+
+			words.into_iter()
+				.filter_map(|w| Some(Sample {
+					neighbors: corr.neighbors(&w, options.k, &Filter::default()).ok()?
+						.into_iter()
+						.map(|n| (n.word, n.score))
+						.collect(),
+					word: w,
+				}))
+				.collect()
+		},
+		_ => Vec::new()
+	};
+
+	drop(data);
+
+	let repaired = match options.repair {
+		Some(policy) => {
+			let lowest = if lowest.is_finite() { lowest } else { 0. };
+
+			// readers map the data as written once, so the repair goes to a copy replacing it
+			let tmp = fname.with_extension("tmp");
+			std::fs::copy(&fname, &tmp).map_err(|_| ErrorKind::Io)?;
+
+			let f = OpenOptions::new().write(true).open(&tmp).map_err(|_| ErrorKind::Io)?;
+			let mut w = BufWriter::new(f);
+			let mut buf: Vec<u8> = Vec::with_capacity(width as usize);
+
+			for (i, v) in bad.iter() {
+				buf.clear();
+				header.precision.encode(fix(*v, policy, range, lowest), &mut buf);
+
+				w.seek(SeekFrom::Start(header.pos(*i))).map_err(|_| ErrorKind::Io)?;
+				w.write_all(&buf).map_err(|_| ErrorKind::Io)?;
+			}
+
+			w.flush().map_err(|_| ErrorKind::Io)?;
+			drop(w);
+
+			std::fs::rename(&tmp, &fname).map_err(|_| ErrorKind::Io)?;
+			bad.len() as u64
+		},
+		None => 0
+	};
+
+	Ok(Inspection {
+		data: fname,
+		words: n,
+		pruned: index.pruned.len(),
+		legacy: header.offset == 0,
+		precision: header.precision,
+		layout: header.layout,
+		measure: header.measure,
+		header_words: header.words,
+		expected,
+		actual,
+		mismatches,
+		cells,
+		nan,
+		infinite,
+		out_of_range,
+		range,
+		damaged,
+		nan_words,
+		samples,
+		repaired,
+	})
+}
+
+impl Inspection {
+	/// Whether the files are whole and every cell holds a valid score.
+	pub fn healthy (&self) -> bool {
+		self.mismatches.is_empty() && self.nan + self.infinite + self.out_of_range == 0
+	}
+}
+
+impl Display for Inspection {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		writeln!(f, "Data:          {}{}", self.data.display(), if self.legacy { " (no header)" } else { "" })?;
+		writeln!(f, "Words:         {} ({} pruned, {} in header)", self.words, self.pruned, self.header_words)?;
+		writeln!(f, "Encoding:      {:?} {:?} {:?}", self.measure, self.precision, self.layout)?;
+		writeln!(f, "Size:          {} bytes ({} expected)", self.actual, self.expected)?;
+		writeln!(f, "Cells:         {}", self.cells)?;
+		writeln!(f, "Not a number:  {}", self.nan)?;
+		writeln!(f, "Infinite:      {}", self.infinite)?;
+		writeln!(f, "Out of range:  {} (outside [{}, {}])", self.out_of_range, self.range.0, self.range.1)?;
+
+		if self.repaired > 0 {
+			writeln!(f, "Repaired:      {}", self.repaired)?;
+		}
+
+		if !self.mismatches.is_empty() {
+			writeln!(f)?;
+			writeln!(f, "Mismatches:")?;
+
+			for m in self.mismatches.iter() {
+				writeln!(f, "  {}", m)?;
+			}
+		}
+
+		if !self.damaged.is_empty() {
+			writeln!(f)?;
+			writeln!(f, "Damaged cells:")?;
+
+			for c in self.damaged.iter() {
+				writeln!(f, "  {:<24} {:<24} {}", c.a, c.b, c.value)?;
+			}
+		}
+
+		if !self.nan_words.is_empty() {
+			writeln!(f)?;
+			writeln!(f, "Words with not a number scores:")?;
+
+			for (w, c) in self.nan_words.iter() {
+				writeln!(f, "  {:<24} {:>12}", w, c)?;
+			}
+		}
+
+		for s in self.samples.iter() {
+			writeln!(f)?;
+			writeln!(f, "Closest to {}:", s.word)?;
+
+			for (w, v) in s.neighbors.iter() {
+				writeln!(f, "  {:<24} {:.5}", w, v)?;
+			}
+		}
+
+		Ok(())
+	}
+}
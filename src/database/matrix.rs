@@ -0,0 +1,301 @@
+/// Binary format of correlation matrix files
+use std::io::{Read, Write};
+use core::str::FromStr;
+use half::f16;
+use serde::{Serialize, Deserialize};
+use super::error::*;
+use super::measure::Measure;
+
+/// Bytes identifying a correlation matrix file with a header.
+pub const MAGIC: &[u8; 4] = b"WKDC";
+/// Current version of the header.
+pub const VERSION: u8 = 1;
+/// Size of the header in bytes.
+pub const HEADER: u64 = 16;
+
+/// Encoding of a single matrix cell.
+///
+/// Cells are stored big endian. The integer encodings quantize linearly over `[-1, 1]`,
+/// clamping values outside of it, and use their minimum value to store NaN.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+	F64,
+	F32,
+	F16,
+	I16,
+	I8,
+}
+
+impl Precision {
+	/// Bytes taken by a cell.
+	pub fn width (&self) -> usize {
+		match self {
+			Precision::F64 => 8,
+			Precision::F32 => 4,
+			Precision::F16 |
+			Precision::I16 => 2,
+			Precision::I8 => 1,
+		}
+	}
+
+	/// Append the encoding of `v` to a buffer.
+	pub fn encode (&self, v: f64, buf: &mut Vec<u8>) {
+		match self {
+			Precision::F64 => buf.extend(v.to_be_bytes()),
+			Precision::F32 => buf.extend((v as f32).to_be_bytes()),
+			Precision::F16 => buf.extend(f16::from_f64(v).to_be_bytes()),
+			Precision::I16 => buf.extend(match v.is_nan() {
+				true => i16::MIN,
+				false => (v.clamp(-1., 1.) * i16::MAX as f64).round() as i16
+			}.to_be_bytes()),
+			Precision::I8 => buf.extend(match v.is_nan() {
+				true => i8::MIN,
+				false => (v.clamp(-1., 1.) * i8::MAX as f64).round() as i8
+			}.to_be_bytes()),
+		}
+	}
+
+	/// Decode a cell, `b` being exactly `width()` bytes long.
+	pub fn decode (&self, b: &[u8]) -> f64 {
+		match self {
+			Precision::F64 => f64::from_be_bytes(b.try_into().unwrap()),
+			Precision::F32 => f32::from_be_bytes(b.try_into().unwrap()) as f64,
+			Precision::F16 => f16::from_be_bytes(b.try_into().unwrap()).to_f64(),
+			Precision::I16 => match i16::from_be_bytes(b.try_into().unwrap()) {
+				i16::MIN => f64::NAN,
+				q => q as f64 / i16::MAX as f64
+			},
+			Precision::I8 => match i8::from_be_bytes(b.try_into().unwrap()) {
+				i8::MIN => f64::NAN,
+				q => q as f64 / i8::MAX as f64
+			},
+		}
+	}
+
+	/// Whether every score in `range` can be stored, the quantized encodings holding
+	/// `[-1, 1]` and `F16` at most `f16::MAX` in magnitude.
+	///
+	/// `F32` and `F64` hold any score a corpus yields.
+	pub fn fits (&self, range: (f64, f64)) -> bool {
+		let max = match self {
+			Precision::F64 | Precision::F32 => return true,
+			Precision::F16 => f16::MAX.to_f64(),
+			Precision::I16 | Precision::I8 => 1.,
+		};
+
+		-max <= range.0 && range.1 <= max
+	}
+
+	fn id (&self) -> u8 {
+		match self {
+			Precision::F64 => 0,
+			Precision::F32 => 1,
+			Precision::F16 => 2,
+			Precision::I16 => 3,
+			Precision::I8 => 4,
+		}
+	}
+
+	fn of_id (id: u8) -> Option<Precision> {
+		Some(match id {
+			0 => Precision::F64,
+			1 => Precision::F32,
+			2 => Precision::F16,
+			3 => Precision::I16,
+			4 => Precision::I8,
+			_ => return None
+		})
+	}
+}
+
+impl FromStr for Precision {
+	type Err = Error;
+
+	fn from_str (s: &str) -> Result<Precision> {
+		Ok(match s.to_lowercase().as_str() {
+			"f64" => Precision::F64,
+			"f32" => Precision::F32,
+			"f16" => Precision::F16,
+			"i16" => Precision::I16,
+			"i8" => Precision::I8,
+			_ => return Err(ErrorKind::Format.into())
+		})
+	}
+}
+
+/// Arrangement of the cells of a symmetric matrix.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+	/// Lower triangle without the diagonal, row `a` holding the pairs `(a, b < a)`.
+	Triangular,
+	/// Every row in full, including the diagonal, so a row is one sequential read.
+	Full,
+}
+
+impl Layout {
+	/// Cell index of the pair `(a, b)` in a matrix of `n` words.
+	///
+	/// Returns `None` for cells that are not stored, such as the diagonal of a triangular matrix.
+	pub fn find (&self, a: u64, b: u64, n: u64) -> Option<u64> {
+		match self {
+			Layout::Triangular => {
+				if a == b {
+					return None
+				}
+				let (a, b) = if a < b { (b, a) } else { (a, b) };
+
+				// a should be > b
+
+				Some(a * (a - 1) / 2 + b)
+			},
+			Layout::Full => Some(a * n + b)
+		}
+	}
+
+	/// Number of cells in a matrix of `n` words.
+	pub fn cells (&self, n: u64) -> u64 {
+		match self {
+			Layout::Triangular => n * n.saturating_sub(1) / 2,
+			Layout::Full => n * n,
+		}
+	}
+
+	fn id (&self) -> u8 {
+		match self {
+			Layout::Triangular => 0,
+			Layout::Full => 1,
+		}
+	}
+
+	fn of_id (id: u8) -> Option<Layout> {
+		Some(match id {
+			0 => Layout::Triangular,
+			1 => Layout::Full,
+			_ => return None
+		})
+	}
+}
+
+impl FromStr for Layout {
+	type Err = Error;
+
+	fn from_str (s: &str) -> Result<Layout> {
+		Ok(match s.to_lowercase().as_str() {
+			"triangular" => Layout::Triangular,
+			"full" => Layout::Full,
+			_ => return Err(ErrorKind::Format.into())
+		})
+	}
+}
+
+/// Header at the start of a correlation matrix file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Header {
+	pub precision: Precision,
+	pub layout: Layout,
+	pub measure: Measure,
+	/// Number of words in the matrix.
+	pub words: u64,
+	/// Byte offset of the first cell.
+	pub offset: u64,
+	/// Whether a triangular matrix is followed by its diagonal, which headerless files lack.
+	pub diagonal: bool,
+}
+
+impl Header {
+	pub fn new (precision: Precision, layout: Layout, measure: Measure, words: u64) -> Header {
+		Header {
+			precision,
+			layout,
+			measure,
+			words,
+			offset: HEADER,
+			diagonal: true,
+		}
+	}
+
+	/// Write the header.
+	pub fn write <W: Write> (&self, w: &mut W) -> Result<()> {
+		let mut buf: Vec<u8> = Vec::with_capacity(HEADER as usize);
+
+		buf.extend(MAGIC);
+		buf.extend([VERSION, self.precision.id(), self.layout.id(), self.measure.id()]);
+		buf.extend(self.words.to_be_bytes());
+
+		w.write_all(&buf).map_err(|_| ErrorKind::Io)?;
+
+		Ok(())
+	}
+
+	/// Read the header of a file of `size` bytes holding `words` words.
+	///
+	/// Files written before headers were introduced start directly with `f64` cells,
+	/// they are read as such when their size is exactly that of such a matrix.
+	///
+	/// # Returns
+	/// * `Err(ErrorKind::Format)` if the header is unreadable or holds another number of words.
+	pub fn read <R: Read> (r: &mut R, words: u64, size: u64) -> Result<Header> {
+		let header = Header::parse(r, words, size)?;
+
+		if header.words != words {
+			return Err(ErrorKind::Format.into());
+		}
+
+		Ok(header)
+	}
+
+	/// Read the header of a file of `size` bytes like `read`, without checking the number of
+	/// words it holds, which callers reporting a mismatch compare themselves.
+	pub fn parse <R: Read> (r: &mut R, words: u64, size: u64) -> Result<Header> {
+		let mut buf = [0u8; HEADER as usize];
+
+		match r.read_exact(&mut buf) {
+			Ok(_) if &buf[0..4] == MAGIC => (),
+			_ if size == Layout::Triangular.cells(words) * 8 => return Ok(Header {
+				precision: Precision::F64,
+				layout: Layout::Triangular,
+				measure: Measure::Pearson,
+				words,
+				offset: 0,
+				diagonal: false,
+			}),
+			_ => return Err(ErrorKind::Format.into())
+		}
+
+		if buf[4] != VERSION {
+			return Err(ErrorKind::Format.into());
+		}
+
+		Ok(Header {
+			precision: Precision::of_id(buf[5]).ok_or_else(|| ErrorKind::Format)?,
+			layout: Layout::of_id(buf[6]).ok_or_else(|| ErrorKind::Format)?,
+			measure: Measure::of_id(buf[7]).ok_or_else(|| ErrorKind::Format)?,
+			words: u64::from_be_bytes(buf[8..16].try_into().unwrap()),
+			offset: HEADER,
+			diagonal: true,
+		})
+	}
+
+	/// Cell index of the pair `(a, b)`.
+	///
+	/// Returns `None` for the diagonal of triangular matrices without one, which is 1.
+	pub fn find (&self, a: u64, b: u64) -> Option<u64> {
+		match (self.layout, a == b) {
+			(Layout::Triangular, true) if self.diagonal => Some(self.layout.cells(self.words) + a),
+			_ => self.layout.find(a, b, self.words)
+		}
+	}
+
+	/// Byte index of cell `i`.
+	pub fn pos (&self, i: u64) -> u64 {
+		self.offset + i * self.precision.width() as u64
+	}
+
+	/// Byte size of the file described by this header.
+	pub fn size (&self) -> u64 {
+		match (self.layout, self.diagonal) {
+			(Layout::Triangular, true) => self.pos(self.layout.cells(self.words) + self.words),
+			_ => self.pos(self.layout.cells(self.words))
+		}
+	}
+}
@@ -0,0 +1,260 @@
+/// Word association measures computed from article co-occurrence
+use core::str::FromStr;
+use serde::{Serialize, Deserialize};
+use super::build::Marginals;
+use super::error::*;
+
+/// Statistics of a word pair over all articles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pair {
+	/// Sum over articles of the product of both word counts.
+	pub dot: f64,
+	/// Number of articles containing both words.
+	pub co: u32,
+}
+
+/// Association between two words, given their marginals and pair statistics.
+///
+/// Measures working on occurrence rather than counts treat an article as containing
+/// a word or not, with probabilities estimated over all articles.
+pub trait Association: Sync {
+	/// Association of the words of rows `i` and `j`.
+	fn score (&self, m: &Marginals, i: usize, j: usize, p: Pair) -> f64;
+
+	/// Association of a word with itself, never lower than any other score of its row.
+	fn own (&self, m: &Marginals, i: usize) -> f64 {
+		self.score(m, i, i, m.pair(i))
+	}
+
+	/// Lowest and highest possible scores.
+	fn range (&self) -> (f64, f64) {
+		(-1., 1.)
+	}
+}
+
+/// Pearson's r correlation of article counts.
+pub struct Pearson;
+/// Positive pointwise mutual information of occurrence, `max(0, ln(p(a, b) / (p(a) p(b))))`.
+pub struct Ppmi;
+/// Pointwise mutual information normalized by `-ln(p(a, b))`, -1 for words never seen together.
+pub struct Npmi;
+/// Cosine similarity of article counts.
+pub struct Cosine;
+/// Shared articles over articles containing either word.
+pub struct Jaccard;
+/// Twice the shared articles over the articles of both words.
+pub struct Dice;
+/// Dunning's log-likelihood ratio of occurrence, negative when the words occur together
+/// less often than expected.
+pub struct Llr;
+/// Cosine similarity of the centered rows of two words in other correlation data, so words
+/// related to the same words are alike even if they rarely occur together.
+pub struct SecondOrder;
+
+impl Association for Pearson {
+	/// Deviations from the mean are summed over the stored cells only, articles
+	/// containing neither word all contribute the same product of means.
+	fn score (&self, m: &Marginals, i: usize, j: usize, p: Pair) -> f64 {
+		let (mi, mj) = (m.mean[i], m.mean[j]);
+
+		let num = p.dot
+			- m.sum[i] * mj
+			- m.sum[j] * mi
+			+ (m.len as f64 - m.nnz[i] as f64 - m.nnz[j] as f64) * mi * mj;
+
+		num / (m.sum2[i] * m.sum2[j]).sqrt()
+	}
+
+	fn own (&self, _: &Marginals, _: usize) -> f64 {
+		1.
+	}
+}
+
+/// Pointwise mutual information of the occurrence of two words seen together.
+fn pmi (m: &Marginals, i: usize, j: usize, co: u32) -> f64 {
+	(co as f64 * m.len as f64 / (m.nnz[i] as f64 * m.nnz[j] as f64)).ln()
+}
+
+impl Association for Ppmi {
+	fn score (&self, m: &Marginals, i: usize, j: usize, p: Pair) -> f64 {
+		match p.co {
+			0 => 0.,
+			co => pmi(m, i, j, co).max(0.)
+		}
+	}
+
+	fn range (&self) -> (f64, f64) {
+		(0., f64::INFINITY)
+	}
+}
+
+impl Association for Npmi {
+	fn score (&self, m: &Marginals, i: usize, j: usize, p: Pair) -> f64 {
+		match p.co {
+			0 => -1.,
+			co if co as usize == m.len => 1.,
+			co => pmi(m, i, j, co) / -(co as f64 / m.len as f64).ln()
+		}
+	}
+
+	fn own (&self, _: &Marginals, _: usize) -> f64 {
+		1.
+	}
+}
+
+impl Association for Cosine {
+	fn score (&self, m: &Marginals, i: usize, j: usize, p: Pair) -> f64 {
+		p.dot / (m.sq[i] * m.sq[j]).sqrt()
+	}
+
+	fn own (&self, _: &Marginals, _: usize) -> f64 {
+		1.
+	}
+
+	fn range (&self) -> (f64, f64) {
+		(0., 1.)
+	}
+}
+
+impl Association for Jaccard {
+	fn score (&self, m: &Marginals, i: usize, j: usize, p: Pair) -> f64 {
+		p.co as f64 / (m.nnz[i] + m.nnz[j] - p.co as usize) as f64
+	}
+
+	fn own (&self, _: &Marginals, _: usize) -> f64 {
+		1.
+	}
+
+	fn range (&self) -> (f64, f64) {
+		(0., 1.)
+	}
+}
+
+impl Association for Dice {
+	fn score (&self, m: &Marginals, i: usize, j: usize, p: Pair) -> f64 {
+		2. * p.co as f64 / (m.nnz[i] + m.nnz[j]) as f64
+	}
+
+	fn own (&self, _: &Marginals, _: usize) -> f64 {
+		1.
+	}
+
+	fn range (&self) -> (f64, f64) {
+		(0., 1.)
+	}
+}
+
+impl Association for Llr {
+	/// Computed over the 2x2 table of articles containing either word or not.
+	fn score (&self, m: &Marginals, i: usize, j: usize, p: Pair) -> f64 {
+		let n = m.len as f64;
+		let (a, b, k) = (m.nnz[i] as f64, m.nnz[j] as f64, p.co as f64);
+
+		// contribution of a cell with `k` articles, out of `row` and `col` articles
+		let h = |k: f64, row: f64, col: f64| match k {
+			k if k > 0. => k * (k * n / (row * col)).ln(),
+			_ => 0.
+		};
+
+		let g2 = 2. * (h(k, a, b)
+			+ h(a - k, a, n - b)
+			+ h(b - k, n - a, b)
+			+ h(n - a - b + k, n - a, n - b));
+
+		match k * n < a * b {
+			true => -g2,
+			false => g2
+		}
+	}
+
+	fn range (&self) -> (f64, f64) {
+		(f64::NEG_INFINITY, f64::INFINITY)
+	}
+}
+
+impl Association for SecondOrder {
+	/// Second-order scores are computed from rows of scores by `second::build`, not from
+	/// article counts, which builds refuse this measure for.
+	fn score (&self, _: &Marginals, _: usize, _: usize, _: Pair) -> f64 {
+		f64::NAN
+	}
+
+	fn own (&self, _: &Marginals, _: usize) -> f64 {
+		1.
+	}
+}
+
+/// Association measure a correlation matrix was built with.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Measure {
+	Pearson,
+	Ppmi,
+	Npmi,
+	Cosine,
+	Jaccard,
+	Dice,
+	Llr,
+	SecondOrder,
+}
+
+impl Measure {
+	/// Implementation of the measure.
+	pub fn association (&self) -> &'static dyn Association {
+		match self {
+			Measure::Pearson => &Pearson,
+			Measure::Ppmi => &Ppmi,
+			Measure::Npmi => &Npmi,
+			Measure::Cosine => &Cosine,
+			Measure::Jaccard => &Jaccard,
+			Measure::Dice => &Dice,
+			Measure::Llr => &Llr,
+			Measure::SecondOrder => &SecondOrder,
+		}
+	}
+
+	/// Identifier stored in matrix headers.
+	pub fn id (&self) -> u8 {
+		match self {
+			Measure::Pearson => 0,
+			Measure::Ppmi => 1,
+			Measure::Npmi => 2,
+			Measure::Cosine => 3,
+			Measure::Jaccard => 4,
+			Measure::Dice => 5,
+			Measure::Llr => 6,
+			Measure::SecondOrder => 7,
+		}
+	}
+
+	/// Measure of a header identifier.
+	pub fn of_id (id: u8) -> Option<Measure> {
+		Some(match id {
+			0 => Measure::Pearson,
+			1 => Measure::Ppmi,
+			2 => Measure::Npmi,
+			3 => Measure::Cosine,
+			4 => Measure::Jaccard,
+			5 => Measure::Dice,
+			6 => Measure::Llr,
+			7 => Measure::SecondOrder,
+			_ => return None
+		})
+	}
+}
+
+impl FromStr for Measure {
+	type Err = Error;
+
+	fn from_str (s: &str) -> Result<Measure> {
+		Ok(match s.to_lowercase().as_str() {
+			"pearson" => Measure::Pearson,
+			"ppmi" => Measure::Ppmi,
+			"npmi" => Measure::Npmi,
+			"cosine" => Measure::Cosine,
+			"jaccard" => Measure::Jaccard,
+			"dice" => Measure::Dice,
+			"llr" => Measure::Llr,
+			_ => return Err(ErrorKind::Format.into())
+		})
+	}
+}
@@ -1,4 +1,22 @@
 pub mod read;
 pub mod frequency;
 pub mod correlation;
-pub mod error;
\ No newline at end of file
+pub mod matrix;
+pub mod build;
+pub mod measure;
+pub mod support;
+pub mod embedding;
+pub mod topk;
+pub mod hnsw;
+pub mod query;
+pub mod export;
+pub mod cluster;
+pub mod drift;
+pub mod benchmark;
+pub mod bundle;
+pub mod inspect;
+pub mod second;
+pub mod similarity;
+pub mod error;
+pub mod stats;
+pub mod audit;
\ No newline at end of file
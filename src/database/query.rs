@@ -0,0 +1,135 @@
+/// Module scoring every word against weighted sets of words
+use core::str::FromStr;
+use serde::{Serialize, Deserialize};
+use super::build;
+use super::similarity::{self, Similarity, Filter, Neighbor};
+use super::error::*;
+
+/// Way the scores against the words of a query are combined.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregate {
+	/// Weighted mean of the scores, those against negative words counted negatively.
+	Mean,
+	/// Lowest weighted score against a positive word, less the highest against a negative
+	/// one, so a word has to relate to every positive word and to no negative word.
+	Min,
+	/// Score against the weighted sum of the words, as given by `Similarity::centroid`.
+	Centroid,
+}
+
+impl FromStr for Aggregate {
+	type Err = Error;
+
+	fn from_str (s: &str) -> Result<Aggregate> {
+		Ok(match s.to_lowercase().as_str() {
+			"mean" => Aggregate::Mean,
+			"min" => Aggregate::Min,
+			"centroid" => Aggregate::Centroid,
+			_ => return Err(ErrorKind::Format.into())
+		})
+	}
+}
+
+/// Words that results should relate to, or not, with their weights.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Query {
+	#[serde(default)]
+	pub positive: Vec<(String, f64)>,
+	#[serde(default)]
+	pub negative: Vec<(String, f64)>,
+	pub aggregate: Aggregate,
+}
+
+impl Query {
+	/// Score of every word against the query, ordered by index.
+	///
+	/// Non finite scores, such as unscored pairs, count as 0.
+	///
+	/// Returns
+	/// * `Err(ErrorKind::Format)` for a query without words.
+	/// * The errors of `Similarity::lookup` for an invalid query word.
+	pub fn scores (&self, sim: &dyn Similarity) -> Result<Vec<f64>> {
+		if self.positive.is_empty() && self.negative.is_empty() {
+			return Err(ErrorKind::Format.into());
+		}
+
+		fn words<'a> (sim: &dyn Similarity, set: &'a [(String, f64)]) -> Result<Vec<(u32, &'a str, f64)>> {
+			set.iter()
+				.map(|(w, x)| Ok((sim.lookup(w)?, w.as_str(), *x)))
+				.collect()
+		}
+
+		let (pos, neg) = (words(sim, &self.positive)?, words(sim, &self.negative)?);
+		let n = sim.dict().len();
+
+		// unscored pairs, such as those outside the neighbor lists of `TopK`, count as 0 as in `Similarity::centroid`
+		let row = |w: &str| -> Result<Vec<f64>> {
+			Ok(sim.corrall(w)
+				.ok_or_else(|| Error::from(ErrorKind::InvalidWord))?
+				.into_iter()
+				.map(|v| if v.is_finite() { v } else { 0. })
+				.collect())
+		};
+
+		Ok(match self.aggregate {
+			Aggregate::Mean => {
+				let total: f64 = pos.iter().chain(neg.iter()).map(|e| e.2.abs()).sum();
+				let mut s: Vec<f64> = vec![0.; n];
+
+				for (set, sign) in [(&pos, 1.), (&neg, -1.)] {
+					for (_, w, x) in set.iter() {
+						for (s, v) in s.iter_mut().zip(row(w)?) {
+							*s += sign * x * v;
+						}
+					}
+				}
+
+				s.into_iter().map(|v| v / total.max(f64::MIN_POSITIVE)).collect()
+			},
+			Aggregate::Min => {
+				let mut lo: Vec<f64> = vec![if pos.is_empty() { 0. } else { f64::INFINITY }; n];
+				let mut hi: Vec<f64> = vec![if neg.is_empty() { 0. } else { f64::NEG_INFINITY }; n];
+
+				for (_, w, x) in pos.iter() {
+					for (l, v) in lo.iter_mut().zip(row(w)?) {
+						*l = l.min(x * v);
+					}
+				}
+
+				for (_, w, x) in neg.iter() {
+					for (h, v) in hi.iter_mut().zip(row(w)?) {
+						*h = h.max(x * v);
+					}
+				}
+
+				lo.into_iter().zip(hi).map(|(l, h)| l - h).collect()
+			},
+			Aggregate::Centroid => {
+				let words: Vec<(u32, f64)> = pos.iter().map(|e| (e.0, e.2))
+					.chain(neg.iter().map(|e| (e.0, -e.2)))
+					.collect();
+
+				sim.centroid(&words)
+			}
+		})
+	}
+
+	/// The `k` best scoring words passing `filter`, leaving out the words of the query.
+	///
+	/// Ranks are positions among all words scored against the query.
+	pub fn top (&self, sim: &dyn Similarity, k: usize, filter: &Filter) -> Result<Vec<Neighbor>> {
+		let row: Vec<(u32, f64)> = self.scores(sim)?
+			.into_iter()
+			.enumerate()
+			.map(|(j, v)| (j as u32, v))
+			.collect();
+
+		let skip: Vec<u32> = self.positive.iter()
+			.chain(self.negative.iter())
+			.filter_map(|(w, _)| sim.index(w))
+			.collect();
+
+		similarity::list(sim, build::select(row, usize::MAX), &skip, k, filter)
+	}
+}
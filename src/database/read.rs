@@ -1,7 +1,7 @@
 use std::path::Path;
 use std::fs::File;
 use xml::reader::{EventReader, XmlEvent};
-use std::collections::{ HashMap };
+use std::collections::{ HashMap, HashSet };
 
 use std::io::{BufReader, BufRead};
 use lazy_static::lazy_static;
@@ -62,6 +62,52 @@ pub fn load_dict<P: AsRef<Path>>(fname: P) -> Result<Dict> {
 	Ok(dict)
 }
 
+/// Load a list of words, such as answers, one per line, normalized by `strip`.
+///
+/// Blank lines and lines starting with `#` are ignored.
+pub fn load_words<P: AsRef<Path>>(fname: P) -> Result<HashSet<String>> {
+	let df = File::open(fname).map_err(|_| ErrorKind::Io)?;
+	let df = BufReader::new(df);
+
+	let mut words = HashSet::new();
+
+	for l in df.lines() {
+		let l = l.map_err(|_| ErrorKind::Io)?;
+		let l = l.trim();
+
+		if l.is_empty() || l.starts_with('#') {
+			continue;
+		}
+
+		match strip(l) {
+			Some(s) => { words.insert(s); },
+			None => warn!("Ignoring invalid word {:?}.", l)
+		}
+	}
+
+	Ok(words)
+}
+
+/// Lines of a file in order, as the game reads its answer list.
+///
+/// Unlike `load_words`, the order is kept, as it decides the answer of every day.
+pub fn load_lines<P: AsRef<Path>>(fname: P) -> Result<Vec<String>> {
+	let df = File::open(fname).map_err(|_| ErrorKind::Io)?;
+
+	BufReader::new(df)
+		.lines()
+		.collect::<std::io::Result<Vec<String>>>()
+		.map_err(|_| ErrorKind::Io.into())
+}
+
+/// Copy of a dictionary without the given words, keeping the indices of the rest.
+pub fn without(dict: &Dict, words: &HashSet<String>) -> Dict {
+	dict.iter()
+		.filter(|(k, _)| !words.contains(*k))
+		.map(|(k, v)| (k.clone(), *v))
+		.collect()
+}
+
 // Type representing a page.
 #[derive(Debug,PartialEq,Clone)]
 pub struct Page {
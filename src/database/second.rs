@@ -0,0 +1,232 @@
+/// Module deriving second-order similarity from correlation data
+use std::path::Path;
+use std::io::{BufWriter, Write};
+use std::fs::File;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use std::thread;
+use memmap2::Mmap;
+use log::{debug, info};
+use super::correlation::Correlation;
+use super::matrix::{Header, Precision, Layout};
+use super::measure::Measure;
+use super::build;
+use super::similarity::Similarity;
+use super::error::*;
+
+/// Number of rows whose scores against a group of rows are computed together, so every
+/// row read from the scratch file serves that many dot products.
+const GROUP: usize = 64;
+
+/// Options of a second-order build.
+#[derive(Debug, Clone)]
+pub struct Options {
+	/// Encoding of the stored cells, scores being in `[-1, 1]` any precision fits.
+	pub precision: Precision,
+	/// Arrangement of the stored cells.
+	pub layout: Layout,
+	/// Number of threads computing the matrix.
+	pub threads: usize,
+	/// Number of rows computed before they are written out, bounding memory to about
+	/// `block` rows of cells plus two groups of rows per thread.
+	pub block: usize,
+	/// Subtract the mean of every row before comparing rows, so scores are Pearson
+	/// correlations of rows rather than cosines.
+	pub center: bool,
+}
+
+impl Default for Options {
+	fn default() -> Options {
+		Options {
+			precision: Precision::F32,
+			layout: Layout::Triangular,
+			threads: thread::available_parallelism().map_or(1, |n| n.get()),
+			block: 1024,
+			center: true,
+		}
+	}
+}
+
+/// Rows `r0..r1` of the scratch file, as read into `buf`.
+fn read <'a> (unit: &Mmap, n: usize, r0: usize, r1: usize, buf: &'a mut Vec<f32>) -> &'a [f32] {
+	buf.clear();
+	buf.extend(unit[r0 * n * 4..r1 * n * 4]
+		.chunks_exact(4)
+		.map(|b| f32::from_le_bytes(b.try_into().unwrap())));
+
+	buf
+}
+
+/// Build second-order similarity from correlation data, two words scoring the cosine
+/// similarity of their rows.
+///
+/// Rows are first written to a scratch file next to `fname`, with the cell of the word
+/// itself and non finite scores set to 0, then centered if asked and scaled to unit length.
+/// Cells are then computed in blocks of rows as in `build::lower`, every worker comparing a
+/// group of rows against the scratch file one group at a time, so memory does not grow
+/// with the square of the dictionary. Words with constant rows score 0 against all others.
+///
+/// The data has the dictionary of `corr` and no support, as articles are not shared by
+/// pairs of rows.
+///
+/// Returns
+/// * `Err(ErrorKind::Io)` if the scratch file or the data cannot be written, or if any
+/// 	of them is the data file of `corr`, which would be truncated while it is read.
+pub fn build <P: AsRef<Path>> (corr: &Correlation, fname: P, options: &Options) -> Result<Correlation> {
+	let fname = fname.as_ref();
+	let n = corr.dict().len();
+	let precision = options.precision;
+	let scratch = fname.with_extension("rows");
+
+	// full rows are assembled from the triangle afterwards, like in `Correlation::with_config`
+	let out = match options.layout {
+		Layout::Triangular => fname.to_path_buf(),
+		Layout::Full => fname.with_extension("tri"),
+	};
+
+	if [fname, out.as_path(), scratch.as_path()].iter().any(|p| p.canonicalize().ok().as_deref() == Some(corr.fname())) {
+		return Err(ErrorKind::Io.into());
+	}
+
+	let names = corr.names();
+
+	let mut w = BufWriter::new(File::create(&scratch).map_err(|_| ErrorKind::Io)?);
+
+	for (i, word) in names.iter().enumerate() {
+		let mut row = corr.corrall(word).ok_or_else(|| ErrorKind::Io)?;
+
+		row[i] = 0.;
+		row.iter_mut().filter(|v| !v.is_finite()).for_each(|v| *v = 0.);
+
+		if options.center {
+			let mean = row.iter().sum::<f64>() / n as f64;
+			row.iter_mut().for_each(|v| *v -= mean);
+		}
+
+		let norm = row.iter().map(|v| v * v).sum::<f64>().sqrt();
+
+		for v in row {
+			let v = if norm > 0. { v / norm } else { 0. };
+			w.write_all(&(v as f32).to_le_bytes()).map_err(|_| ErrorKind::Io)?;
+		}
+	}
+
+	w.flush().map_err(|_| ErrorKind::Io)?;
+	drop(w);
+
+	debug!(target: "app::dump", "Wrote {} normalized rows to {}.", n, scratch.display());
+
+	let f = File::open(&scratch).map_err(|_| ErrorKind::Io)?;
+
+	// Safety: the scratch file is only written above and removed once the build is done.
+	let unit = unsafe { Mmap::map(&f) }.map_err(|_| ErrorKind::Io)?;
+
+	let mut w = BufWriter::new(File::create(&out).map_err(|_| ErrorKind::Io)?);
+
+	Header::new(precision, Layout::Triangular, Measure::SecondOrder, n as u64).write(&mut w)?;
+
+	let started = Instant::now();
+	let block = options.block.max(1);
+	let mut r0 = 0;
+
+	while r0 < n {
+		let r1 = (r0 + block).min(n);
+		let next = AtomicUsize::new(r0);
+
+		let work = || {
+			let (mut a, mut b): (Vec<f32>, Vec<f32>) = (Vec::new(), Vec::new());
+			let mut out: Vec<(usize, Vec<u8>)> = Vec::new();
+
+			loop {
+				let g0 = next.fetch_add(GROUP, Ordering::Relaxed);
+
+				if g0 >= r1 {
+					return out;
+				}
+
+				let g1 = (g0 + GROUP).min(r1);
+				let a = read(&unit, n, g0, g1, &mut a);
+				let mut cells: Vec<Vec<f64>> = (g0..g1).map(|i| vec![0.; i]).collect();
+
+				for c0 in (0..g1).step_by(GROUP) {
+					let c1 = (c0 + GROUP).min(g1);
+					let b = read(&unit, n, c0, c1, &mut b);
+
+					for (i, (x, row)) in a.chunks_exact(n).zip(cells.iter_mut()).enumerate() {
+						let i = g0 + i;
+
+						for (j, y) in b.chunks_exact(n).enumerate().take(i.saturating_sub(c0)) {
+							row[c0 + j] = x.iter().zip(y).map(|(x, y)| *x as f64 * *y as f64).sum::<f64>();
+						}
+					}
+				}
+
+				for (i, row) in cells.into_iter().enumerate() {
+					let mut bytes: Vec<u8> = Vec::with_capacity(row.len() * precision.width());
+
+					for v in row {
+						precision.encode(v.clamp(-1., 1.), &mut bytes);
+					}
+
+					out.push((g0 + i, bytes));
+				}
+			}
+		};
+
+		let mut rows: Vec<(usize, Vec<u8>)> = thread::scope(|s| {
+			let workers: Vec<_> = (0..options.threads.max(1))
+				.map(|_| s.spawn(&work))
+				.collect();
+
+			workers.into_iter()
+				.flat_map(|h| h.join().unwrap())
+				.collect()
+		});
+
+		rows.sort_unstable_by_key(|e| e.0);
+
+		for (_, row) in rows.iter() {
+			w.write_all(row).map_err(|_| ErrorKind::Io)?;
+		}
+
+		let tri = |r: usize| Layout::Triangular.cells(r as u64) as f64;
+		let secs = started.elapsed().as_secs_f64();
+
+		info!("Computed {} of {} second-order rows, ETA {}.", r1, n, build::clock((tri(n) - tri(r1)) / (tri(r1) / secs)));
+
+		r0 = r1;
+	}
+
+	let mut diag: Vec<u8> = Vec::with_capacity(n * precision.width());
+
+	for _ in 0..n {
+		precision.encode(1., &mut diag);
+	}
+
+	w.write_all(&diag).map_err(|_| ErrorKind::Io)?;
+	w.flush().map_err(|_| ErrorKind::Io)?;
+	drop(w);
+
+	drop(unit);
+	std::fs::remove_file(&scratch).ok();
+
+	// a support file left at the destination would not describe these scores
+	std::fs::remove_file(fname.with_extension("support")).ok();
+
+	let f = File::open(&out).map_err(|_| ErrorKind::Io)?;
+
+	// Safety: correlation files are written once and never modified while in use.
+	let data = unsafe { Mmap::map(&f) }.map_err(|_| ErrorKind::Io)?;
+	let path = out.canonicalize().map_err(|_| ErrorKind::Io)?;
+	let second = Correlation::from_map(path, data, corr.dict().clone(), corr.pruned().clone(), None)?;
+
+	match options.layout {
+		Layout::Triangular => Ok(second),
+		Layout::Full => {
+			let full = second.convert(fname, precision, Layout::Full)?;
+			drop(second);
+			std::fs::remove_file(&out).map_err(|_| ErrorKind::Io)?;
+			Ok(full)
+		}
+	}
+}
@@ -0,0 +1,522 @@
+/// Module defining the sources of word similarity a game can be played on
+use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use serde::de::{self, Deserialize, Deserializer, Visitor, MapAccess, SeqAccess};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use core::fmt::Formatter;
+use super::read::{Dict, strip};
+use super::correlation::{Correlation, Pruned};
+use super::embedding::Embedding;
+use super::topk::TopK;
+use super::bundle::Bundle;
+use super::support::Support;
+use super::build;
+use super::error::*;
+
+/// Position of a word in the list of another word's neighbors, 0 being the most similar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rank {
+	Within(usize),
+	/// Somewhere after the `k` neighbors kept by the source, serialized as `">k"`.
+	Beyond(usize),
+}
+
+impl Serialize for Rank {
+	fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+		where S: Serializer
+	{
+		match self {
+			Rank::Within(r) => serializer.serialize_u64(*r as u64),
+			Rank::Beyond(k) => serializer.serialize_str(&format!(">{k}")),
+		}
+	}
+}
+
+/// Restriction of the words listed by `Similarity::neighbors`.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+	/// Only list words of this set, such as the answer list.
+	pub words: Option<HashSet<String>>,
+	/// Only list words occurring in at least this many articles.
+	pub min_df: Option<usize>,
+}
+
+impl Filter {
+	/// Whether every word passes the filter.
+	pub fn is_empty (&self) -> bool {
+		self.words.is_none() && self.min_df.is_none()
+	}
+
+	/// Whether the word with index `j` passes the filter.
+	///
+	/// Returns `Err(ErrorKind::Unsupported)` if filtering by article count on data that does not keep it.
+	pub fn accepts <S: Similarity + ?Sized> (&self, sim: &S, j: u32, word: &str) -> Result<bool> {
+		if self.words.as_ref().map(|s| !s.contains(word)).unwrap_or(false) {
+			return Ok(false);
+		}
+
+		Ok(match self.min_df {
+			Some(m) => sim.df(j).ok_or_else(|| ErrorKind::Unsupported)? >= m,
+			None => true
+		})
+	}
+}
+
+/// Word close to another one.
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct Neighbor {
+	pub word: String,
+	pub score: f64,
+	/// Position among all words, regardless of the filter, the word itself being 0.
+	pub rank: usize,
+}
+
+/// Similarity scores between the words of a dictionary.
+pub trait Similarity: Send + Sync {
+	/// Words with scores and their index.
+	fn dict (&self) -> &Dict;
+
+	/// Index of a word in the dictionary.
+	///
+	/// Returns
+	/// * `Err(ErrorKind::PrunedWord(p))` if the word was removed while building the data.
+	/// * `Err(ErrorKind::InvalidWord)` if the word was never part of the dictionary.
+	fn lookup (&self, a: &str) -> Result<u32>;
+
+	/// Index of a word in the dictionary.
+	fn index (&self, a: &str) -> Option<u32> {
+		self.lookup(a).ok()
+	}
+
+	/// Words of the dictionary ordered by index.
+	fn names (&self) -> Vec<&str> {
+		let mut names: Vec<&str> = vec![""; self.dict().len()];
+
+		for (w, i) in self.dict().iter() {
+			names[*i as usize] = w;
+		}
+
+		names
+	}
+
+	/// Words left out of the data and why.
+	fn pruned (&self) -> &HashMap<String, Pruned>;
+
+	/// Similarity of two words.
+	fn corr (&self, a: &str, b: &str) -> Option<f64>;
+
+	/// Similarity of the words with indices `a` and `b`, both in the dictionary.
+	fn score (&self, a: u32, b: u32) -> f64;
+
+	/// Similarity of a word with every word, ordered by index.
+	fn corrall (&self, a: &str) -> Option<Vec<f64>>;
+
+	/// Articles shared by two words and the significance of their similarity, if known.
+	fn support (&self, _: &str, _: &str) -> Option<Support> {
+		None
+	}
+
+	/// Number of neighbors scored for every word, `None` if every pair is scored.
+	///
+	/// Pairs outside of them score `f64::NEG_INFINITY`.
+	fn limit (&self) -> Option<usize> {
+		None
+	}
+
+	/// Number of articles containing the word with index `a`, if known.
+	fn df (&self, _: u32) -> Option<usize> {
+		None
+	}
+
+	/// The `k` highest scoring words of a word's row that pass `filter`, without the word itself.
+	///
+	/// Returns
+	/// * `Err(ErrorKind::Unsupported)` if filtering by article count on data that does not keep it.
+	/// * The errors of `lookup` for an invalid word.
+	fn neighbors (&self, a: &str, k: usize, filter: &Filter) -> Result<Vec<Neighbor>> {
+		let i = self.lookup(a)?;
+
+		// unfiltered, the word itself is the only one skipped among the best
+		let n = match filter.is_empty() {
+			true => k.saturating_add(1),
+			false => usize::MAX
+		};
+
+		let row = self.top(a, n.min(self.limit().unwrap_or(usize::MAX)))
+			.ok_or_else(|| ErrorKind::InvalidWord)?;
+
+		list(self, row, &[i], k, filter)
+	}
+
+	/// Vector standing for a word outside of wikidle, its row of scores unless the data
+	/// has vectors of its own.
+	///
+	/// Unscored pairs and other non finite scores are 0, to be read by tools without infinities.
+	fn features (&self, a: &str) -> Option<Vec<f64>> {
+		Some(self.corrall(a)?
+			.into_iter()
+			.map(|v| if v.is_finite() { v } else { 0. })
+			.collect())
+	}
+
+	/// Score of every word against the weighted sum of some words, ordered by index.
+	///
+	/// The rows of the words are scaled to unit length before they are summed, so every
+	/// word counts the same whatever the range of its scores, unscored pairs counting as 0.
+	fn centroid (&self, words: &[(u32, f64)]) -> Vec<f64> {
+		let mut c: Vec<f64> = vec![0.; self.dict().len()];
+		let names = self.names();
+
+		for (i, weight) in words {
+			let row: Vec<f64> = match names.get(*i as usize).and_then(|w| self.corrall(w)) {
+				Some(r) => r.into_iter().map(|v| if v.is_finite() { v } else { 0. }).collect(),
+				None => continue
+			};
+
+			let norm = row.iter().map(|v| v * v).sum::<f64>().sqrt();
+
+			if norm > 0. {
+				for (c, v) in c.iter_mut().zip(row) {
+					*c += weight * v / norm;
+				}
+			}
+		}
+
+		c
+	}
+
+	/// Indices and scores of the `k` highest scoring words of a word's row, highest first.
+	fn top (&self, a: &str, k: usize) -> Option<Vec<(u32, f64)>> {
+		let row = self.corrall(a)?
+			.into_iter()
+			.enumerate()
+			.map(|(j, v)| (j as u32, v))
+			.collect();
+
+		Some(build::select(row, k))
+	}
+}
+
+/// The first `k` words of `row` that pass `filter` and are not in `skip`, `row` holding
+/// the indices and scores of words ordered from best to worst.
+///
+/// Returns `Err(ErrorKind::Unsupported)` if filtering by article count on data that does not keep it.
+pub fn list <S: Similarity + ?Sized> (sim: &S, row: Vec<(u32, f64)>, skip: &[u32], k: usize, filter: &Filter) -> Result<Vec<Neighbor>> {
+	let names = sim.names();
+
+	let mut out: Vec<Neighbor> = Vec::with_capacity(k.min(row.len()));
+
+	for (rank, (j, score)) in row.into_iter().enumerate() {
+		if out.len() >= k {
+			break;
+		}
+
+		if skip.contains(&j) || !filter.accepts(sim, j, names[j as usize])? {
+			continue;
+		}
+
+		out.push(Neighbor { word: names[j as usize].to_owned(), score, rank });
+	}
+
+	Ok(out)
+}
+
+impl Similarity for Correlation {
+	fn dict (&self) -> &Dict {
+		Correlation::dict(self)
+	}
+
+	fn lookup (&self, a: &str) -> Result<u32> {
+		Correlation::lookup(self, a)
+	}
+
+	fn pruned (&self) -> &HashMap<String, Pruned> {
+		Correlation::pruned(self)
+	}
+
+	fn corr (&self, a: &str, b: &str) -> Option<f64> {
+		Correlation::corr(self, a, b)
+	}
+
+	fn score (&self, a: u32, b: u32) -> f64 {
+		Correlation::score(self, a, b)
+	}
+
+	fn corrall (&self, a: &str) -> Option<Vec<f64>> {
+		Correlation::corrall(self, a)
+	}
+
+	fn support (&self, a: &str, b: &str) -> Option<Support> {
+		Correlation::support(self, a, b)
+	}
+
+	fn df (&self, a: u32) -> Option<usize> {
+		Correlation::df(self, a)
+	}
+}
+
+impl Similarity for Embedding {
+	fn dict (&self) -> &Dict {
+		Embedding::dict(self)
+	}
+
+	fn lookup (&self, a: &str) -> Result<u32> {
+		Embedding::lookup(self, a)
+	}
+
+	fn pruned (&self) -> &HashMap<String, Pruned> {
+		Embedding::pruned(self)
+	}
+
+	fn corr (&self, a: &str, b: &str) -> Option<f64> {
+		Embedding::corr(self, a, b)
+	}
+
+	fn score (&self, a: u32, b: u32) -> f64 {
+		Embedding::score(self, a, b)
+	}
+
+	fn corrall (&self, a: &str) -> Option<Vec<f64>> {
+		Embedding::corrall(self, a)
+	}
+
+	fn centroid (&self, words: &[(u32, f64)]) -> Vec<f64> {
+		Embedding::centroid(self, words)
+	}
+
+	fn features (&self, a: &str) -> Option<Vec<f64>> {
+		Some(self.vector(a)?.iter().map(|x| *x as f64).collect())
+	}
+}
+
+impl Similarity for TopK {
+	fn dict (&self) -> &Dict {
+		TopK::dict(self)
+	}
+
+	fn lookup (&self, a: &str) -> Result<u32> {
+		TopK::lookup(self, a)
+	}
+
+	fn pruned (&self) -> &HashMap<String, Pruned> {
+		TopK::pruned(self)
+	}
+
+	fn corr (&self, a: &str, b: &str) -> Option<f64> {
+		TopK::corr(self, a, b)
+	}
+
+	fn score (&self, a: u32, b: u32) -> f64 {
+		TopK::score(self, a, b)
+	}
+
+	fn corrall (&self, a: &str) -> Option<Vec<f64>> {
+		TopK::corrall(self, a)
+	}
+
+	fn limit (&self) -> Option<usize> {
+		Some(self.k())
+	}
+
+	fn top (&self, a: &str, k: usize) -> Option<Vec<(u32, f64)>> {
+		TopK::top(self, a, k)
+	}
+}
+
+/// Index of serialized similarity data as written by every source, read without
+/// opening the data file it names.
+#[derive(Debug, Clone)]
+pub struct Index {
+	pub fname: PathBuf,
+	pub dict: Dict,
+	pub pruned: HashMap<String, Pruned>,
+}
+
+/// Read data serialized with its index, such as `Correlation`, or the `Index` itself.
+///
+/// Indices written before words were pruned end after the dictionary, which bincode
+/// reports as the end of the file. Only then, and only if the dictionary ends exactly
+/// at the end of the file, is the index read again without pruned words. Any other error,
+/// such as an index cut short among its pruned words, is returned.
+pub fn read_index <T, P> (fname: P) -> Result<T>
+	where T: for<'de> Deserialize<'de>, P: AsRef<std::path::Path>,
+{
+	let buf = std::fs::read(fname).map_err(|_| ErrorKind::Io)?;
+
+	match bincode::deserialize(&buf) {
+		Ok(t) => Ok(t),
+		Err(e) => match *e {
+			bincode::ErrorKind::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+				let (fname, dict): (PathBuf, Dict) = bincode::deserialize(&buf)
+					.map_err(|_| ErrorKind::Serialization)?;
+
+				if bincode::serialized_size(&(&fname, &dict)).ok() != Some(buf.len() as u64) {
+					return Err(ErrorKind::Serialization.into());
+				}
+
+				let index = bincode::serialize(&Index { fname, dict, pruned: HashMap::new() })
+					.map_err(|_| ErrorKind::Serialization)?;
+
+				bincode::deserialize(&index).map_err(|_| ErrorKind::Serialization.into())
+			},
+			_ => Err(ErrorKind::Serialization.into())
+		}
+	}
+}
+
+impl Index {
+	/// Read an index file, see `read_index`.
+	pub fn read <P: AsRef<std::path::Path>> (fname: P) -> Result<Index> {
+		read_index(fname)
+	}
+
+	/// Index of a word in the dictionary.
+	pub fn index (&self, a: &str) -> Option<u32> {
+		self.lookup(a).ok()
+	}
+
+	/// Index of a word in the dictionary.
+	///
+	/// Returns
+	/// * `Err(ErrorKind::PrunedWord(p))` if the word was removed while building the data.
+	/// * `Err(ErrorKind::InvalidWord)` if the word was never part of the dictionary.
+	pub fn lookup (&self, a: &str) -> Result<u32> {
+		let a = strip(a).ok_or_else(|| ErrorKind::InvalidWord)?;
+
+		match (self.dict.get(&a), self.pruned.get(&a)) {
+			(Some(i), _) => Ok(*i),
+			(None, Some(p)) => Err(ErrorKind::PrunedWord(*p).into()),
+			(None, None) => Err(ErrorKind::InvalidWord.into())
+		}
+	}
+
+	/// Words left out of the data and why.
+	pub fn pruned<'a>(&'a self) -> &'a HashMap<String, Pruned> {
+		&self.pruned
+	}
+
+	/// Open the data file named by the index with `load`, reporting a failure as an
+	/// invalid path to the deserializer.
+	pub fn open <T, E: de::Error> (self, load: impl FnOnce(&PathBuf, Dict, HashMap<String, Pruned>) -> Result<T>) -> std::result::Result<T, E> {
+		load(&self.fname, self.dict, self.pruned)
+			.map_err(|_| de::Error::invalid_value(
+				de::Unexpected::Str(&format!("Unexpected path: <{}>", self.fname.to_str().unwrap_or("none"))),
+				&"A valid filepath."
+			))
+	}
+}
+
+impl Serialize for Index {
+	fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+		where S: Serializer
+	{
+		let mut s = serializer.serialize_struct("Index", 3)?;
+		s.serialize_field("fname", &self.fname)?;
+		s.serialize_field("dict", &self.dict)?;
+		s.serialize_field("pruned", &self.pruned)?;
+		s.end()
+	}
+}
+
+impl<'de> Deserialize<'de> for Index {
+	fn deserialize<D>(deserializer: D) -> std::result::Result<Index, D::Error>
+		where D: Deserializer<'de>,
+	{
+		// implementation following https://serde.rs/deserialize-struct.html
+
+		#[derive(serde::Deserialize)]
+		#[serde(field_identifier, rename_all = "lowercase")]
+		enum Field { Fname, Dict, Pruned }
+
+		struct IndexVisitor;
+
+		impl<'de> Visitor<'de> for IndexVisitor {
+			type Value = Index;
+
+			fn expecting(&self, formatter: &mut Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+				formatter.write_str("A struct representing the index of similarity data.")
+			}
+
+			fn visit_seq<V>(self, mut seq: V) -> std::result::Result<Self::Value, V::Error>
+				where V: SeqAccess<'de>,
+			{
+				let fname: PathBuf = seq.next_element()?
+					.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+
+				let dict: Dict = seq.next_element()?
+					.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+
+				// indices written before words were pruned are read by `read_index`
+				let pruned: HashMap<String, Pruned> = seq.next_element()?
+					.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+
+				Ok(Index { fname, dict, pruned })
+			}
+
+			fn visit_map<V>(self, mut map: V) -> std::result::Result<Self::Value, V::Error>
+				where V: MapAccess<'de>,
+			{
+				let mut fname = None;
+				let mut dict = None;
+				let mut pruned = None;
+
+				while let Some(key) = map.next_key()? {
+					match key {
+						Field::Fname => {
+							if fname.is_some() {
+								return Err(de::Error::duplicate_field("fname"));
+							}
+
+							fname = Some(map.next_value()?);
+						},
+						Field::Dict => {
+							if dict.is_some() {
+								return Err(de::Error::duplicate_field("dict"));
+							}
+
+							dict = Some(map.next_value()?);
+						},
+						Field::Pruned => {
+							if pruned.is_some() {
+								return Err(de::Error::duplicate_field("pruned"));
+							}
+
+							pruned = Some(map.next_value()?);
+						}
+					}
+				}
+
+				Ok(Index {
+					fname: fname.ok_or_else(|| de::Error::missing_field("fname"))?,
+					dict: dict.ok_or_else(|| de::Error::missing_field("dict"))?,
+					pruned: pruned.unwrap_or_default(),
+				})
+			}
+		}
+
+		const FIELDS: &'static [&'static str] = &["fname", "dict", "pruned"];
+		deserializer.deserialize_struct("Index", FIELDS, IndexVisitor)
+	}
+}
+
+/// Serialized similarity data, given by the path of its index.
+#[derive(Debug, Clone)]
+pub enum Source {
+	Correlation(PathBuf),
+	Embedding(PathBuf),
+	TopK(PathBuf),
+	/// Single file written by `Bundle::write`, given by its own path.
+	Bundle(PathBuf),
+}
+
+impl Source {
+	/// Load the data.
+	pub fn load (&self) -> Result<Box<dyn Similarity>> {
+		Ok(match self {
+			Source::Correlation(p) => Box::new(read_index::<Correlation, _>(p)?),
+			Source::Embedding(p) => Box::new(read_index::<Embedding, _>(p)?),
+			Source::TopK(p) => Box::new(read_index::<TopK, _>(p)?),
+			Source::Bundle(p) => Bundle::open(p)?.sim,
+		})
+	}
+}
@@ -0,0 +1,198 @@
+/// Corpus statistics computed from frequency data
+use std::collections::HashMap;
+use core::fmt::{self, Display, Formatter};
+use serde::Serialize;
+use super::read::Dict;
+
+/// Occurrence counts of a single dictionary word.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct WordStats {
+	pub word: String,
+	/// Collection frequency, total occurrences over all articles.
+	pub cf: u64,
+	/// Document frequency, number of articles containing the word.
+	pub df: u32,
+}
+
+/// Distribution of article lengths, counted in dictionary words.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Lengths {
+	pub min: u64,
+	pub max: u64,
+	pub mean: f64,
+	pub median: u64,
+	/// `(lower bound, articles)` buckets, bucket `i > 0` holding lengths in `[2^(i-1), 2^i)`.
+	pub histogram: Vec<(u64, u32)>,
+}
+
+/// Least squares fit of `ln(cf) = intercept - exponent * ln(rank)`.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Zipf {
+	pub exponent: f64,
+	pub intercept: f64,
+	pub r2: f64,
+}
+
+/// How much of the dictionary appears in the corpus.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Coverage {
+	pub dict: usize,
+	pub seen: usize,
+	pub ratio: f64,
+}
+
+/// Statistics report over a frequency database.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Stats {
+	pub articles: usize,
+	pub tokens: u64,
+	pub coverage: Coverage,
+	pub lengths: Lengths,
+	pub zipf: Zipf,
+	/// Number of words shown in the text report.
+	pub top: usize,
+	/// Every word that occurs at least once, most frequent first.
+	pub words: Vec<WordStats>,
+}
+
+impl Stats {
+	/// Compute statistics from data exported by `Frequency::load`.
+	///
+	/// Arguments
+	/// * `dat`: word id to `(article, count)` pairs.
+	/// * `len`: number of articles.
+	/// * `dict`: dictionary used to build the data.
+	/// * `top`: number of words listed by the text report.
+	pub fn new (dat: &HashMap<u32, Vec<(u32, u16)>>, len: usize, dict: &Dict, top: usize) -> Stats {
+		let mut lengths: Vec<u64> = vec![0; len];
+
+		let mut words: Vec<WordStats> = dict.iter()
+			.filter_map(|(k, v)| Some((k, dat.get(v)?)))
+			.map(|(k, d)| {
+				for (a, c) in d {
+					lengths[*a as usize] += *c as u64;
+				}
+
+				WordStats {
+					word: k.clone(),
+					cf: d.iter().map(|(_, c)| *c as u64).sum(),
+					df: d.len() as u32,
+				}
+			})
+			.collect();
+
+		words.sort_unstable_by(|a, b| b.cf.cmp(&a.cf).then_with(|| a.word.cmp(&b.word)));
+
+		let coverage = Coverage {
+			dict: dict.len(),
+			seen: words.len(),
+			ratio: match dict.len() {
+				0 => 0.,
+				l => words.len() as f64 / l as f64
+			}
+		};
+
+		Stats {
+			articles: len,
+			tokens: lengths.iter().sum(),
+			coverage,
+			lengths: Lengths::new(lengths),
+			zipf: Zipf::new(&words),
+			top,
+			words,
+		}
+	}
+}
+
+impl Lengths {
+	fn new (mut lengths: Vec<u64>) -> Lengths {
+		lengths.sort_unstable();
+
+		let mut histogram: Vec<(u64, u32)> = Vec::new();
+
+		for l in lengths.iter() {
+			let b = (u64::BITS - l.leading_zeros()) as usize;
+
+			if histogram.len() <= b {
+				histogram.extend((histogram.len()..=b).map(|i| (match i { 0 => 0, i => 1 << (i - 1) }, 0)));
+			}
+
+			histogram[b].1 += 1;
+		}
+
+		Lengths {
+			min: lengths.first().map(|e| *e).unwrap_or(0),
+			max: lengths.last().map(|e| *e).unwrap_or(0),
+			mean: match lengths.len() {
+				0 => 0.,
+				l => lengths.iter().sum::<u64>() as f64 / l as f64
+			},
+			median: lengths.get(lengths.len() / 2).map(|e| *e).unwrap_or(0),
+			histogram,
+		}
+	}
+}
+
+impl Zipf {
+	/// Fit over words sorted by descending collection frequency.
+	fn new (words: &[WordStats]) -> Zipf {
+		let pts: Vec<(f64, f64)> = words.iter()
+			.enumerate()
+			.map(|(i, w)| (((i + 1) as f64).ln(), (w.cf as f64).ln()))
+			.collect();
+
+		let n = pts.len() as f64;
+
+		if pts.len() < 2 {
+			return Zipf { exponent: 0., intercept: pts.first().map(|e| e.1).unwrap_or(0.), r2: 0. };
+		}
+
+		let mx = pts.iter().map(|e| e.0).sum::<f64>() / n;
+		let my = pts.iter().map(|e| e.1).sum::<f64>() / n;
+
+		let sxy: f64 = pts.iter().map(|(x, y)| (x - mx) * (y - my)).sum();
+		let sxx: f64 = pts.iter().map(|(x, _)| (x - mx) * (x - mx)).sum();
+		let syy: f64 = pts.iter().map(|(_, y)| (y - my) * (y - my)).sum();
+
+		let slope = sxy / sxx;
+
+		Zipf {
+			exponent: -slope,
+			intercept: my - slope * mx,
+			r2: match syy {
+				s if s == 0. => 1.,
+				s => sxy * sxy / (sxx * s)
+			}
+		}
+	}
+}
+
+impl Display for Stats {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		writeln!(f, "Articles:   {}", self.articles)?;
+		writeln!(f, "Tokens:     {}", self.tokens)?;
+		writeln!(f, "Coverage:   {} of {} dictionary words ({:.2}%)",
+			self.coverage.seen, self.coverage.dict, self.coverage.ratio * 100.)?;
+		writeln!(f, "Zipf fit:   exponent {:.4}, intercept {:.4}, r^2 {:.4}",
+			self.zipf.exponent, self.zipf.intercept, self.zipf.r2)?;
+		writeln!(f)?;
+
+		writeln!(f, "Article length (dictionary words):")?;
+		writeln!(f, "  min {}, median {}, mean {:.1}, max {}",
+			self.lengths.min, self.lengths.median, self.lengths.mean, self.lengths.max)?;
+
+		let peak = self.lengths.histogram.iter().map(|e| e.1).max().unwrap_or(0).max(1);
+		for (lo, c) in self.lengths.histogram.iter() {
+			writeln!(f, "  >= {:<8} {:>10} {}", lo, c, "#".repeat((*c as u64 * 40 / peak as u64) as usize))?;
+		}
+		writeln!(f)?;
+
+		writeln!(f, "Top {} words:", self.top.min(self.words.len()))?;
+		writeln!(f, "  {:>6}  {:<20} {:>12} {:>10}", "rank", "word", "cf", "df")?;
+		for (i, w) in self.words.iter().take(self.top).enumerate() {
+			writeln!(f, "  {:>6}  {:<20} {:>12} {:>10}", i + 1, w.word, w.cf, w.df)?;
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,284 @@
+/// Module counting the articles behind word associations and estimating their significance
+use std::path::Path;
+use std::io::{BufWriter, Write};
+use std::fs::File;
+use std::cmp::Ordering;
+use serde::Serialize;
+use memmap2::Mmap;
+use super::build::Csr;
+use super::error::*;
+
+/// Bytes identifying a support file.
+pub const MAGIC: &[u8; 4] = b"WKDS";
+/// Current version of the support file header.
+pub const VERSION: u8 = 1;
+/// Size of the header in bytes.
+pub const HEADER: usize = 16;
+
+/// Two sided normal quantile of the reported confidence intervals.
+const Z95: f64 = 1.959963984540054;
+
+/// Evidence behind the association of a word pair.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Support {
+	/// Number of articles containing both words.
+	pub co: u32,
+	/// Probability of sharing at least `co` articles if the words occurred independently.
+	pub p: f64,
+	/// 95% confidence interval of a Pearson's r, only given for that measure.
+	pub interval: Option<(f64, f64)>,
+}
+
+/// Sorted articles of every word, from which the support of any pair is counted.
+///
+/// The file holds, after its header, the offset of every word's articles and the end
+/// of the last, as big endian `u64`, followed by the articles as big endian `u32`.
+pub struct Postings {
+	data: Mmap,
+	words: usize,
+	/// Number of articles.
+	len: usize,
+}
+
+impl Postings {
+	/// Write the articles of every row of `x` to `fname`.
+	pub fn write <P: AsRef<Path>> (x: &Csr, fname: P) -> Result<()> {
+		let n = x.len();
+		let mut w = BufWriter::new(File::create(fname).map_err(|_| ErrorKind::Io)?);
+
+		let mut buf: Vec<u8> = Vec::with_capacity(HEADER + (n + 1) * 8);
+
+		buf.extend(MAGIC);
+		buf.extend([VERSION, 0, 0, 0]);
+		buf.extend((n as u32).to_be_bytes());
+		buf.extend((x.width() as u32).to_be_bytes());
+
+		let mut end: u64 = 0;
+		buf.extend(end.to_be_bytes());
+
+		for i in 0..n {
+			end += x.row(i).0.len() as u64;
+			buf.extend(end.to_be_bytes());
+		}
+
+		w.write_all(&buf).map_err(|_| ErrorKind::Io)?;
+
+		for i in 0..n {
+			let mut row = x.row(i).0.to_vec();
+			row.sort_unstable();
+
+			buf.clear();
+			for a in row {
+				buf.extend(a.to_be_bytes());
+			}
+
+			w.write_all(&buf).map_err(|_| ErrorKind::Io)?;
+		}
+
+		w.flush().map_err(|_| ErrorKind::Io)?;
+
+		Ok(())
+	}
+
+	/// Read the postings of `words` words.
+	pub fn open <P: AsRef<Path>> (fname: P, words: usize) -> Result<Postings> {
+		let f = File::open(fname).map_err(|_| ErrorKind::Io)?;
+
+		// Safety: support files are written once and never modified while in use.
+		let data = unsafe { Mmap::map(&f) }.map_err(|_| ErrorKind::Io)?;
+
+		Postings::from_map(data, words)
+	}
+
+	/// Read the articles of `words` words from mapped support data, such as a section of a bundle.
+	pub fn from_map (data: Mmap, words: usize) -> Result<Postings> {
+		if data.len() < HEADER || &data[0..4] != MAGIC || data[4] != VERSION {
+			return Err(ErrorKind::Format.into());
+		}
+
+		let n = u32::from_be_bytes(data[8..12].try_into().unwrap()) as usize;
+		let len = u32::from_be_bytes(data[12..16].try_into().unwrap()) as usize;
+
+		let start = (n + 1).checked_mul(8).and_then(|s| s.checked_add(HEADER));
+
+		if n != words || start.map_or(true, |s| data.len() < s) {
+			return Err(ErrorKind::Format.into());
+		}
+
+		let p = Postings { data, words: n, len };
+
+		// offsets are trusted by every lookup, so they have to start at 0, never decrease
+		// and end with the articles
+		let ordered = p.offset(0) == 0 && (0..n).all(|i| p.offset(i) <= p.offset(i + 1));
+		let end = p.offset(n).checked_mul(4).and_then(|e| e.checked_add(p.start()));
+
+		if !ordered || end != Some(p.data.len()) {
+			return Err(ErrorKind::Format.into());
+		}
+
+		Ok(p)
+	}
+
+	/// Start of the articles in the file.
+	fn start (&self) -> usize {
+		HEADER + (self.words + 1) * 8
+	}
+
+	/// Position of the first article of word `i` among all articles.
+	fn offset (&self, i: usize) -> usize {
+		let s = HEADER + i * 8;
+
+		u64::from_be_bytes(self.data[s..s + 8].try_into().unwrap()) as usize
+	}
+
+	/// Articles of word `i`, in ascending order.
+	pub fn articles (&self, i: usize) -> impl Iterator<Item = u32> + '_ {
+		let (s, e) = (self.offset(i), self.offset(i + 1));
+
+		self.data[self.start() + s * 4..self.start() + e * 4]
+			.chunks_exact(4)
+			.map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+	}
+
+	/// Number of articles containing word `i`.
+	pub fn df (&self, i: usize) -> usize {
+		self.offset(i + 1) - self.offset(i)
+	}
+
+	/// Number of articles.
+	pub fn len (&self) -> usize {
+		self.len
+	}
+
+	/// Number of articles containing both words `i` and `j`.
+	pub fn co (&self, i: usize, j: usize) -> u32 {
+		let (mut a, mut b) = (self.articles(i).peekable(), self.articles(j).peekable());
+		let mut co = 0;
+
+		while let (Some(x), Some(y)) = (a.peek(), b.peek()) {
+			match x.cmp(y) {
+				Ordering::Less => { a.next(); },
+				Ordering::Greater => { b.next(); },
+				Ordering::Equal => {
+					co += 1;
+					a.next();
+					b.next();
+				}
+			}
+		}
+
+		co
+	}
+
+	/// Support of the pair of words `i` and `j`, whose association is `r`.
+	///
+	/// `pearson` gives the confidence interval of `r` as a Pearson's r.
+	pub fn support (&self, i: usize, j: usize, r: f64, pearson: bool) -> Support {
+		let co = self.co(i, j);
+
+		Support {
+			co,
+			p: tail(co as usize, self.df(i), self.df(j), self.len),
+			interval: match pearson {
+				true => Some(interval(r, self.len)),
+				false => None
+			},
+		}
+	}
+}
+
+/// Natural logarithm of the gamma function, by the Lanczos approximation.
+fn ln_gamma (x: f64) -> f64 {
+	const G: [f64; 9] = [
+		0.99999999999980993, 676.5203681218851, -1259.1392167224028,
+		771.32342877765313, -176.61502916214059, 12.507343278686905,
+		-0.13857109526572012, 9.9843695780195716e-6, 1.5056327351493116e-7,
+	];
+
+	if x < 0.5 {
+		return (std::f64::consts::PI / (std::f64::consts::PI * x).sin()).ln() - ln_gamma(1. - x);
+	}
+
+	let x = x - 1.;
+	let t = x + 7.5;
+	let s: f64 = G[1..].iter()
+		.enumerate()
+		.fold(G[0], |s, (i, g)| s + g / (x + i as f64 + 1.));
+
+	0.5 * (2. * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + s.ln()
+}
+
+/// Natural logarithm of `n` choose `k`.
+fn ln_choose (n: usize, k: usize) -> f64 {
+	ln_gamma(n as f64 + 1.) - ln_gamma(k as f64 + 1.) - ln_gamma((n - k) as f64 + 1.)
+}
+
+/// Probability of at least `co` shared articles between words in `a` and `b` of `len`
+/// articles, drawn independently, the upper tail of the hypergeometric distribution.
+pub fn tail (co: usize, a: usize, b: usize, len: usize) -> f64 {
+	let lo = (a + b).saturating_sub(len);
+	let hi = a.min(b);
+
+	if co <= lo {
+		return 1.;
+	}
+
+	if co > hi {
+		return 0.;
+	}
+
+	let pmf = |x: usize| (ln_choose(a, x) + ln_choose(len - a, b - x) - ln_choose(len, b)).exp();
+
+	// terms decrease away from the mode, so sums stop once they no longer matter
+	if co > (a + 1) * (b + 1) / (len + 2) {
+		let mut t = pmf(co);
+		let mut p = 0.;
+
+		for x in co..=hi {
+			p += t;
+
+			if t <= p * 1e-17 {
+				break;
+			}
+
+			t *= ((a - x) * (b - x)) as f64 / ((x + 1) * (len + x + 1 - a - b)) as f64;
+		}
+
+		p.min(1.)
+	} else {
+		let mut t = pmf(co - 1);
+		let mut q = 0.;
+
+		for x in (lo..co).rev() {
+			q += t;
+
+			if t <= q * 1e-17 || x == 0 {
+				break;
+			}
+
+			t *= (x * (len + x - a - b)) as f64 / ((a - x + 1) * (b - x + 1)) as f64;
+		}
+
+		(1. - q).max(0.)
+	}
+}
+
+/// 95% confidence interval of a Pearson's r by the Fisher transformation.
+///
+/// `n` is the number of articles `r` was computed over. Fewer than 4 give `(-1, 1)`.
+pub fn interval (r: f64, n: usize) -> (f64, f64) {
+	if n < 4 || !r.is_finite() {
+		return (-1., 1.);
+	}
+
+	let z = r.clamp(-1. + 1e-15, 1. - 1e-15).atanh();
+	let h = Z95 / (n as f64 - 3.).sqrt();
+
+	((z - h).tanh(), (z + h).tanh())
+}
+
+/// Shrink an association toward zero by a factor `co / (co + strength)`, so pairs sharing
+/// few articles cannot reach extreme scores.
+pub fn shrink (v: f64, co: u32, strength: f64) -> f64 {
+	v * co as f64 / (co as f64 + strength)
+}
@@ -0,0 +1,289 @@
+/// Module managing the nearest neighbors of every word
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::io::{BufWriter, Write};
+use std::fs::File;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use core::fmt::{Formatter, Debug};
+use memmap2::Mmap;
+use log::info;
+use super::read::Dict;
+use super::correlation::{Config, Pruned};
+use super::build::{self, Csr, Marginals};
+use super::similarity::{Similarity, Index};
+use super::error::*;
+
+/// Bytes identifying a neighbor file.
+pub const MAGIC: &[u8; 4] = b"WKDK";
+/// Current version of the neighbor file header.
+pub const VERSION: u8 = 1;
+/// Size of the header in bytes.
+pub const HEADER: usize = 16;
+
+/// Number of rows ranked at once when reading another source.
+const BLOCK_ROWS: usize = 1 << 10;
+
+/// Size of a stored neighbor, its index and score.
+const ENTRY: usize = 8;
+
+/// The highest scoring neighbors of every word, in place of a full matrix.
+///
+/// The file holds, after its header, `k` neighbors for every word ordered by index,
+/// each stored as a big endian `u32` index and `f32` score, highest score first.
+/// A word is usually its own first neighbor.
+pub struct TopK {
+	data: Mmap,
+	k: usize,
+	index: Index,
+}
+
+/// Header of a neighbor file with `k` neighbors for `n` words.
+fn header (k: usize, n: usize) -> Vec<u8> {
+	let mut buf: Vec<u8> = Vec::with_capacity(HEADER);
+
+	buf.extend(MAGIC);
+	buf.extend([VERSION, 0, 0, 0]);
+	buf.extend((k as u32).to_be_bytes());
+	buf.extend((n as u32).to_be_bytes());
+
+	buf
+}
+
+impl TopK {
+	/// Compute the `k` highest scoring neighbors of every word from raw exported frequency data.
+	///
+	/// Scores are given by `config.measure`, only one block of rows is kept in memory at once,
+	/// so no full matrix is ever written.
+	///
+	/// Returns
+	/// * `Err(ErrorKind::Format)` for a measure not computed from counts, see `Config::validate`.
+	pub fn new <P: AsRef<Path>> (dat: HashMap<u32, Vec<(u32, u16)>>, len: usize, fname: P, dict: &Dict, config: &Config, k: usize) -> Result<TopK> {
+		config.validate()?;
+
+		let assoc = config.measure.association();
+		let (nd, pruned) = config.prune(&dat, len, dict);
+
+		let x = Csr::new(&dat, &Csr::words(&nd, dict), len);
+		drop(dat);
+
+		let m = Marginals::new(&x);
+		let k = k.min(x.len());
+
+		let mut w = BufWriter::new(File::create(&fname).map_err(|_| ErrorKind::Io)?);
+		w.write_all(&header(k, x.len())).map_err(|_| ErrorKind::Io)?;
+
+		build::top(&x, k, config.threads, &mut w, |i, j, p| match i == j {
+			true => assoc.own(&m, i),
+			false => assoc.score(&m, i, j, p)
+		})?;
+
+		drop(w);
+
+		TopK::deserialize(fname, nd, pruned)
+	}
+
+	/// Keep the `k` highest scoring neighbors of every word of another source.
+	///
+	/// Rows are read from `sim` by `threads` workers, a block at a time.
+	pub fn from_similarity <P: AsRef<Path>> (sim: &dyn Similarity, fname: P, k: usize, threads: usize) -> Result<TopK> {
+		let words = sim.names();
+		let n = words.len();
+		let k = k.min(n);
+
+		let mut w = BufWriter::new(File::create(&fname).map_err(|_| ErrorKind::Io)?);
+		w.write_all(&header(k, n)).map_err(|_| ErrorKind::Io)?;
+
+		for r0 in (0..n).step_by(BLOCK_ROWS) {
+			let r1 = (r0 + BLOCK_ROWS).min(n);
+			let next = AtomicUsize::new(r0);
+
+			let work = || {
+				let mut out: Vec<(usize, Option<Vec<u8>>)> = Vec::new();
+
+				loop {
+					let i = next.fetch_add(1, Ordering::Relaxed);
+
+					if i >= r1 {
+						return out;
+					}
+
+					out.push((i, sim.top(words[i], k).map(|row| {
+						let mut buf: Vec<u8> = Vec::with_capacity(k * ENTRY);
+
+						for (j, v) in row {
+							buf.extend(j.to_be_bytes());
+							buf.extend((v as f32).to_be_bytes());
+						}
+
+						buf
+					})));
+				}
+			};
+
+			let mut rows: Vec<(usize, Option<Vec<u8>>)> = thread::scope(|s| {
+				let workers: Vec<_> = (0..threads.max(1))
+					.map(|_| s.spawn(&work))
+					.collect();
+
+				workers.into_iter()
+					.flat_map(|h| h.join().unwrap())
+					.collect()
+			});
+
+			rows.sort_unstable_by_key(|e| e.0);
+
+			for (_, row) in rows {
+				match row {
+					Some(r) if r.len() == k * ENTRY => w.write_all(&r).map_err(|_| ErrorKind::Io)?,
+					_ => return Err(ErrorKind::Format.into())
+				}
+			}
+
+			info!("Ranked {} of {} rows.", r1, n);
+		}
+
+		w.flush().map_err(|_| ErrorKind::Io)?;
+		drop(w);
+
+		TopK::deserialize(fname, sim.dict().clone(), sim.pruned().clone())
+	}
+
+	/// Used to load neighbors from an existing file
+	fn deserialize <P: AsRef<Path>> (fname: P, dict: Dict, pruned: HashMap<String, Pruned>) -> Result<TopK> {
+		let f = File::open(&fname).map_err(|_| ErrorKind::Io)?;
+
+		// Safety: neighbor files are written once and never modified while in use.
+		let data = unsafe { Mmap::map(&f) }.map_err(|_| ErrorKind::Io)?;
+
+		TopK::from_map(fname.as_ref().canonicalize().map_err(|_| ErrorKind::Io)?, data, dict, pruned)
+	}
+
+	/// Read neighbors already mapped to memory, such as a section of a bundle.
+	///
+	/// `fname` is only kept to be serialized.
+	pub fn from_map (fname: PathBuf, data: Mmap, dict: Dict, pruned: HashMap<String, Pruned>) -> Result<TopK> {
+		if data.len() < HEADER || &data[0..4] != MAGIC || data[4] != VERSION {
+			return Err(ErrorKind::Format.into());
+		}
+
+		let k = u32::from_be_bytes(data[8..12].try_into().unwrap()) as usize;
+		let n = u32::from_be_bytes(data[12..16].try_into().unwrap()) as usize;
+
+		if n != dict.len() || n.checked_mul(k).and_then(|c| c.checked_mul(ENTRY)).and_then(|c| c.checked_add(HEADER)) != Some(data.len()) {
+			return Err(ErrorKind::Format.into());
+		}
+
+		// neighbors are trusted as indices of the dictionary once loaded
+		if data[HEADER..].chunks_exact(ENTRY).any(|e| u32::from_be_bytes(e[0..4].try_into().unwrap()) as usize >= n) {
+			return Err(ErrorKind::Format.into());
+		}
+
+		Ok(TopK {
+			data,
+			k,
+			index: Index { fname, dict, pruned },
+		})
+	}
+
+	/// Number of neighbors kept for every word.
+	pub fn k (&self) -> usize {
+		self.k
+	}
+
+	/// Index of a word in the dictionary.
+	pub fn index (&self, a: &str) -> Option<u32> {
+		self.index.index(a)
+	}
+
+	/// Index of a word in the dictionary, see `Index::lookup`.
+	pub fn lookup (&self, a: &str) -> Result<u32> {
+		self.index.lookup(a)
+	}
+
+	/// Words left out of the data and why.
+	pub fn pruned<'a>(&'a self) -> &'a HashMap<String, Pruned> {
+		self.index.pruned()
+	}
+
+	/// Neighbors of the word with index `i`, highest score first.
+	fn row (&self, i: usize) -> impl Iterator<Item = (u32, f64)> + '_ {
+		let s = HEADER + i * self.k * ENTRY;
+
+		self.data[s..s + self.k * ENTRY]
+			.chunks_exact(ENTRY)
+			.map(|e| (
+				u32::from_be_bytes(e[0..4].try_into().unwrap()),
+				f32::from_be_bytes(e[4..8].try_into().unwrap()) as f64
+			))
+	}
+
+	/// Returns the score of `b` among the neighbors of `a`, `f64::NEG_INFINITY` if it is not one.
+	pub fn corr (&self, a: &str, b: &str) -> Option<f64> {
+		Some(self.score(self.index(a)?, self.index(b)?))
+	}
+
+	/// Returns the score of the word with index `b` among the neighbors of the word with index `a`.
+	pub fn score (&self, a: u32, b: u32) -> f64 {
+		self.row(a as usize)
+			.find(|(j, _)| *j == b)
+			.map(|(_, v)| v)
+			.unwrap_or(f64::NEG_INFINITY)
+	}
+
+	/// Returns the scores of the neighbors of a word ordered by index, `f64::NEG_INFINITY`
+	/// for every other word.
+	pub fn corrall (&self, a: &str) -> Option<Vec<f64>> {
+		let a = self.index(a)? as usize;
+		let mut ret: Vec<f64> = vec![f64::NEG_INFINITY; self.index.dict.len()];
+
+		for (j, v) in self.row(a) {
+			ret[j as usize] = v;
+		}
+
+		Some(ret)
+	}
+
+	/// Indices and scores of the first `k` neighbors of a word.
+	pub fn top (&self, a: &str, k: usize) -> Option<Vec<(u32, f64)>> {
+		Some(self.row(self.index(a)? as usize).take(k).collect())
+	}
+
+	pub fn dict<'a>(&'a self) -> &'a Dict {
+		&self.index.dict
+	}
+}
+
+impl PartialEq for TopK {
+	fn eq(&self, r: &TopK) -> bool {
+		self.index.fname == r.index.fname
+	}
+}
+
+impl Debug for TopK {
+	fn fmt(&self, f: &mut Formatter) -> std::result::Result<(), std::fmt::Error> {
+		f.debug_struct("TopK")
+			.field("fname", &self.index.fname)
+			.field("k", &self.k)
+			.finish()
+	}
+}
+
+impl Serialize for TopK {
+	fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+		where S: Serializer
+	{
+		self.index.serialize(serializer)
+	}
+}
+
+impl<'de> Deserialize<'de> for TopK {
+	fn deserialize<D>(deserializer: D) -> std::result::Result<TopK, D::Error>
+		where D: Deserializer<'de>,
+	{
+		Index::deserialize(deserializer)?
+			.open(|fname, dict, pruned| TopK::deserialize(fname, dict, pruned))
+	}
+}
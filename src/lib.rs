@@ -1,12 +1,11 @@
 #![feature(proc_macro_hygiene, decl_macro, try_trait_v2)]
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use serde::{Serialize, Deserialize};
-use std::sync::{RwLock,Arc};
+use std::sync::{Mutex,Arc};
 use std::borrow::BorrowMut;
 use std::path::{Path};
 use lru::LruCache;
-use std::fs::File;
 use std::thread;
 use server::{
 	Launch,
@@ -28,8 +27,17 @@ use server::{
 };
 
 use util::schedule::Schedule;
-use database::correlation::Correlation;
-use std::io::{BufRead,BufReader,Cursor};
+use database::{
+	correlation::Pruned,
+	read,
+	similarity::{self, Similarity, Source, Rank, Filter, Neighbor},
+	bundle::Bundle,
+	build,
+	support::Support,
+	query::Query,
+	error::{self, ErrorKind},
+};
+use std::io::Cursor;
 use lazy_static::lazy_static;
 use rand::{
 	SeedableRng,
@@ -49,12 +57,13 @@ use tokio::{
 		Duration as TDuration
 	}
 };
-use log::{debug, info};
+use log::{debug, info, error};
 
 pub mod database;
 pub mod util;
 
 const VALID_ANSWERS: &str = "data/answers"; // valid answer words
+const STOPWORDS: &str = "data/stopwords"; // words never guessed, whatever the data
 const CORRF: &str = "results/frequency/corrindex.dat";
 
 const RNG_SEED: <SmallRng as SeedableRng>::Seed = *b"MERLIN 2.0 fan v.s. HMM enjoyer\n";
@@ -63,30 +72,42 @@ lazy_static! {
 	static ref ROOT_DATE : NaiveDate = NaiveDate::from_ymd(2022, 5, 9);
 }
 
+/// Game state shared by all requests.
+///
+/// Only the caches are locked, so requests are served in parallel.
 struct CState {
-	cache: LruCache<u32, Vec<f64>>,
-	ranks: LruCache<u32, Vec<usize>>,
-	corr: Correlation,
+	cache: Mutex<LruCache<u32, Arc<Vec<f64>>>>,
+	ranks: Mutex<LruCache<u32, Arc<Vec<usize>>>>,
+	corr: Box<dyn Similarity>,
 	wordlist: Vec<String>,
 	revdict: Vec<String>,
+	stopwords: HashSet<String>,
 }
 
 impl CState {
-	pub fn new <P: AsRef<Path>> (root: P, sz: usize) -> std::io::Result<CState> {
-		let root = root.as_ref();
-
-		let f = BufReader::new(File::open(VALID_ANSWERS)?);
+	/// Game state over some similarity data, answers being drawn from `wordlist`.
+	///
+	/// Answers the data has no scores for are left out, as no guess could be ranked against them,
+	/// and so are `stopwords`, which are refused as guesses.
+	///
+	/// Returns
+	/// * `Err(InvalidData)` if none of the answers is scored, leaving no answer to draw.
+	pub fn new (corr: Box<dyn Similarity>, mut wordlist: Vec<String>, stopwords: HashSet<String>, sz: usize) -> std::io::Result<CState> {
 		let mut rng = SmallRng::from_seed(RNG_SEED);
+		let listed = wordlist.len();
 
-		let mut wordlist: Vec<String> = f
-			.lines()
-			.map(|e| e.unwrap())
-			.collect();
-		wordlist.shuffle(&mut rng);
+		wordlist.retain(|w| corr.index(w).is_some() && !stopwords.contains(w));
 
-		let corr: Correlation = bincode::deserialize_from(
-			File::open(root.join(CORRF))?
-		).map_err(|_| std::io::ErrorKind::InvalidData)?;
+		if wordlist.len() < listed {
+			info!("Left out {} of {} answers without scores.", listed - wordlist.len(), listed);
+		}
+
+		if wordlist.is_empty() {
+			error!("None of the {} answers is scored.", listed);
+			return Err(std::io::ErrorKind::InvalidData.into());
+		}
+
+		wordlist.shuffle(&mut rng);
 
 		let mut revdict: Vec<(String, u32)> = corr.dict()
 			.iter()
@@ -95,14 +116,15 @@ impl CState {
 
 		revdict.sort_unstable_by(|(_, a), (_, b)| a.cmp(b));
 
-		Ok (CState {
-			cache: LruCache::new(sz),
-			ranks: LruCache::new(10),
+		Ok(CState {
+			cache: Mutex::new(LruCache::new(sz)),
+			ranks: Mutex::new(LruCache::new(10)),
 			corr,
 			wordlist,
 			revdict: revdict.into_iter()
 				.map(|(k, _)| k)
-				.collect()
+				.collect(),
+			stopwords,
 		})
 	}
 
@@ -128,83 +150,139 @@ impl CState {
 	}
 
 	/// load correlation data for a word and cache it
-	fn corrs	(&mut self, w: &str) -> Option<Vec<f64>> {
+	fn corrs	(&self, w: &str) -> Option<Arc<Vec<f64>>> {
 		let wind = self.corr.index(w)?;
 
-		match self.cache.get(&wind) {
-			Some(_) => (),
-			None => {
-				debug!("Could not find corrs for {} in cache, loading...", w);
-				self.cache.push(wind, self.corr.corrall(w)?);
-			}
+		if let Some(c) = self.cache.lock().unwrap().get(&wind) {
+			return Some(Arc::clone(c));
 		}
 
-		Some(self.cache.peek(&wind)?.clone())
+		// load outside of the lock so other requests are not blocked
+		debug!("Could not find corrs for {} in cache, loading...", w);
+		let c = Arc::new(self.corr.corrall(w)?);
+
+		self.cache.lock().unwrap().push(wind, Arc::clone(&c));
+
+		Some(c)
 	}
 
 	/// get ranks for a word
-	fn ranks(&mut self, w: &str) -> Option<Vec<usize>>{
+	fn ranks(&self, w: &str) -> Option<Arc<Vec<usize>>>{
 		let wind = self.corr.index(w)?;
 
-		match self.ranks.get(&wind) {
-			Some(_) => (),
-			None => {
-				debug!("Could not find ranks for {} in cache, loading...", w);
-				let dat = self.corrs(w)?;
+		if let Some(r) = self.ranks.lock().unwrap().get(&wind) {
+			return Some(Arc::clone(r));
+		}
 
-				let mut dat: Vec<(usize,f64)> = dat.into_iter()
-					.enumerate()
-					.collect();
+		debug!("Could not find ranks for {} in cache, loading...", w);
+		let dat = self.corrs(w)?;
 
-				dat.sort_unstable_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap());
+		let mut dat: Vec<(u32,f64)> = dat.iter()
+			.enumerate()
+			.map(|(i, e)| (i as u32, *e))
+			.collect();
 
-				let dat: Vec<usize> = {
-					let mut dd : Vec<usize> = vec![0; dat.len()];
+		// scores may be NaN for words without variance
+		dat.sort_unstable_by(build::by_score);
 
-					for (i, (w, _)) in dat.into_iter().enumerate() {
-						dd[w] = i;
-					}
+		let dat: Vec<usize> = {
+			let mut dd : Vec<usize> = vec![0; dat.len()];
 
-					dd
-				};
-
-				self.ranks.push(wind, dat);
+			for (i, (w, _)) in dat.into_iter().enumerate() {
+				dd[w as usize] = i;
 			}
-		}
 
-		Some(self.ranks.peek(&wind)?.clone())
+			dd
+		};
+
+		let dat = Arc::new(dat);
+
+		self.ranks.lock().unwrap().push(wind, Arc::clone(&dat));
+
+		Some(dat)
 	}
 
 	/// correlation between words `a` and `b`
-	pub fn corr (&mut self, a: &str, b: &str) -> Option<f64> {
+	pub fn corr (&self, a: &str, b: &str) -> Option<f64> {
 		let a = self.corr.index(a)?;
 
-		Some(match self.cache.get(&a) {
-			Some(c) => c[self.corr.index(b)? as usize],
-			None => self.corrs(b)?[a as usize]
+		let cached = self.cache.lock().unwrap()
+			.get(&a)
+			.map(Arc::clone);
+
+		// only sources scoring every pair are symmetric
+		Some(match (cached, self.corr.limit()) {
+			(Some(c), _) => c[self.corr.index(b)? as usize],
+			(None, None) => self.corrs(b)?[a as usize],
+			(None, Some(_)) => self.corr.corr(self.of_index(a)?, b)?
 		})
 	}
 
 	/// get rank of word `b` in word `a`'s list
-	pub fn rank (&mut self, a: &str, b: &str) -> Option<usize> {
-		self.ranks(a)?
-			.get(self.corr.index(b)? as usize)
-			.map(|e| *e)
+	///
+	/// Words outside of the neighbors kept by the source only get a bound.
+	pub fn rank (&self, a: &str, b: &str) -> Option<Rank> {
+		let r = *self.ranks(a)?
+			.get(self.corr.index(b)? as usize)?;
+
+		Some(match self.corr.limit() {
+			Some(k) if r >= k => Rank::Beyond(k),
+			_ => Rank::Within(r)
+		})
+	}
+
+	/// Closest words to a word that pass `filter`, see `Similarity::neighbors`.
+	///
+	/// Filtered lists may reach far down the row, so they are read in the order of the
+	/// cached ranks rather than sorting the row again.
+	pub fn neighbors (&self, w: &str, k: usize, filter: &Filter) -> error::Result<Vec<Neighbor>> {
+		let i = self.corr.lookup(w)?;
+
+		if filter.is_empty() {
+			return self.corr.neighbors(w, k, filter);
+		}
+
+		let (scores, ranks) = match (self.corrs(w), self.ranks(w)) {
+			(Some(s), Some(r)) => (s, r),
+			_ => return Err(ErrorKind::InvalidWord.into())
+		};
+
+		let mut row: Vec<(u32, f64)> = vec![(0, 0.); ranks.len()];
+
+		for (j, r) in ranks.iter().enumerate() {
+			row[*r] = (j as u32, scores[j]);
+		}
+
+		// words beyond the neighbors kept by the source are not ordered
+		row.truncate(self.corr.limit().unwrap_or(usize::MAX));
+
+		similarity::list(&*self.corr, row, &[i], k, filter)
 	}
 
 	/// Make sure the word is in cache.
-	pub fn cache(&mut self, word: &str) {
+	pub fn cache(&self, word: &str) {
 		let _ = self.corrs(word);
 		let _ = self.ranks(word);
 	}
 
+	/// Index of a word, or the reason it cannot be guessed.
+	///
+	/// Stopwords, those of the served list as well as those the data was built without,
+	/// are reported as `Pruned::Stopword` whatever the source holds for them.
+	pub fn lookup(&self, w: &str) -> error::Result<u32> {
+		match read::strip(w) {
+			Some(s) if self.stopwords.contains(&s) => Err(ErrorKind::PrunedWord(Pruned::Stopword).into()),
+			_ => self.corr.lookup(w)
+		}
+	}
+
 	/// get word of index
 	pub fn of_index(&self, ind: u32) -> Option<&String> {
 		self.revdict.get(ind as usize)
 	}
 }
 
-type MState = Arc<RwLock<CState>>;
+type MState = Arc<CState>;
 
 pub struct Server {
 	data: MState,
@@ -220,6 +298,19 @@ fn reject (status: Status, msg: &str) -> Response<'static> {
 		).finalize()
 }
 
+/// Message explaining why a word cannot be used.
+fn invalid (word: &str, e: error::Error) -> String {
+	match *e {
+		ErrorKind::PrunedWord(Pruned::Stopword) => format!("{word} is a stopword and cannot be guessed."),
+		ErrorKind::PrunedWord(Pruned::Unseen) => format!("{word} never appears on Wikipedia."),
+		ErrorKind::PrunedWord(Pruned::Rare) => format!("{word} is too rare to be scored."),
+		ErrorKind::PrunedWord(Pruned::Common) => format!("{word} is too common to be scored."),
+		ErrorKind::PrunedWord(Pruned::Capped) => format!("{word} is not among the scored words."),
+		ErrorKind::PrunedWord(Pruned::Missing) => format!("{word} has no vector in the source."),
+		_ => format!("{word} was not a valid word.")
+	}
+}
+
 fn accept <T : Serialize> (data: T) -> Response<'static> {
 	Response::build()
 		.sized_body(
@@ -227,22 +318,26 @@ fn accept <T : Serialize> (data: T) -> Response<'static> {
 		).finalize()
 }
 
+/// Association of a word pair with the articles behind it
+#[derive(Serialize,Debug)]
+struct CorrData {
+	corr: f64,
+	support: Option<Support>, // only known for correlation data built with support
+}
+
 /// Get correlation data between two sets of words
 #[post("/corr", format = "json", data = "<data>")]
 fn corr (data: Json<(Vec<String>, Vec<String>)>, state: State<MState>) -> Response {
 	let (a, b) = data.into_inner();
-	let mut state = match state.write() {
-		Err(_) => return reject(Status::BadRequest, "Could not access internal state (Server error)."),
-		Ok(s) => s
-	};
 
 	match a.into_iter()
 		.map(|i|
 			b.iter()
-				.map(|j| {
-					state.corr(&i, j)
-				}).collect()
-		).collect::<Option<Vec<Vec<f64>>>>() {
+				.map(|j| Some(CorrData {
+					corr: state.corr(&i, j)?,
+					support: state.corr.support(&i, j),
+				})).collect()
+		).collect::<Option<Vec<Vec<CorrData>>>>() {
 		None => reject(Status::BadRequest, "Some words were invalid."),
 		Some (e) => accept(e)
 	}
@@ -252,51 +347,142 @@ fn corr (data: Json<(Vec<String>, Vec<String>)>, state: State<MState>) -> Respon
 #[get("/raw?<word>")]
 fn raw (word: String, state: State<MState>) -> Response {
 	let set = {
-		let mut state = match state.write() {
-			Err(_) => return reject(Status::BadRequest, "Could not access internal state (Server error)."),
-			Ok(s) => s
-		};
-
 		let set = match state.corrs(&word) {
 			None => return reject(Status::BadRequest, &format!("{word} was not a valid word.")),
 			Some(k) => k
 		};
 
-		let mut set: Vec<(usize,f64)> = set.into_iter()
+		let mut set: Vec<(u32,f64)> = set.iter()
 			.enumerate()
+			.map(|(i, e)| (i as u32, *e))
 			.collect();
 
-		set.sort_unstable_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap());
+		set.sort_unstable_by(build::by_score);
 
 		set.into_iter()
-			.map(|(k, v)| (state.of_index(k as u32).unwrap().clone(), v))
+			.take(state.corr.limit().unwrap_or(usize::MAX))
+			.map(|(k, v)| (state.of_index(k).unwrap().clone(), v))
 			.collect::<Vec<(String,f64)>>()
 	};
 
 	accept(set)
 }
 
+/// One page of a word's closest words
+#[derive(Serialize,Debug)]
+struct NeighborPage {
+	word: String,
+	page: usize,
+	per: usize,
+	neighbors: Vec<Neighbor>,
+	more: bool, // whether there is a next page
+}
+
+/// List the closest words to a word, a page at a time
+///
+/// Only answers are listed with `answers`, only words of at least `min_df` articles
+/// with `min_df`, and only the comma separated `words` with `words`. Pages hold
+/// `per` words, from 1 to 500.
+#[get("/neighbors?<word>&<page>&<per>&<answers>&<min_df>&<words>")]
+fn neighbors (word: String, page: Option<usize>, per: Option<usize>, answers: Option<bool>, min_df: Option<usize>, words: Option<String>, state: State<MState>) -> Response {
+	const MAX_PER: usize = 500;
+
+	let page = page.unwrap_or(0);
+	// empty pages would never end
+	let per = per.unwrap_or(50).clamp(1, MAX_PER);
+
+	// listed words are normalized like guesses, so they match the dictionary
+	let mut set: Option<HashSet<String>> = words.map(|w| w.split(',').filter_map(|e| read::strip(e.trim())).collect());
+
+	if answers.unwrap_or(false) {
+		set = Some(match set {
+			Some(s) => state.wordlist.iter().filter(|w| s.contains(*w)).cloned().collect(),
+			None => state.wordlist.iter().cloned().collect()
+		});
+	}
+
+	let filter = Filter { words: set, min_df };
+
+	// words up to the end of the page, and one more to tell whether there is a next page
+	let end = match page.checked_add(1).and_then(|p| p.checked_mul(per)).filter(|e| *e < usize::MAX) {
+		Some(e) => e,
+		None => return reject(Status::BadRequest, "The page is out of range.")
+	};
+
+	match state.neighbors(&word, end + 1, &filter) {
+		Ok(mut n) => {
+			let more = n.len() > end;
+
+			n.truncate(end);
+
+			accept(NeighborPage {
+				word,
+				page,
+				per,
+				neighbors: n.into_iter().skip(page * per).collect(),
+				more,
+			})
+		},
+		Err(e) if *e == ErrorKind::Unsupported => reject(Status::BadRequest, "Article counts were not kept for this data."),
+		Err(e) => reject(Status::BadRequest, &invalid(&word, e))
+	}
+}
+
+/// Query for words related to a weighted set of words
+#[derive(Deserialize,Debug)]
+struct QueryData {
+	#[serde(flatten)]
+	query: Query,
+	k: Option<usize>,
+	answers: Option<bool>,
+	min_df: Option<usize>,
+}
+
+/// Find the words best related to some words and least to others
+#[post("/query", format = "json", data = "<data>")]
+fn query (data: Json<QueryData>, state: State<MState>) -> Response {
+	const MAX_K: usize = 500;
+
+	let data = data.into_inner();
+
+	let filter = Filter {
+		words: match data.answers.unwrap_or(false) {
+			true => Some(state.wordlist.iter().cloned().collect()),
+			false => None
+		},
+		min_df: data.min_df,
+	};
+
+	match data.query.top(state.corr.as_ref(), data.k.unwrap_or(50).min(MAX_K), &filter) {
+		Ok(n) => accept(n),
+		Err(e) => match *e {
+			ErrorKind::Unsupported => reject(Status::BadRequest, "Article counts were not kept for this data."),
+			ErrorKind::Format => reject(Status::BadRequest, "The query has no words."),
+			_ => reject(Status::BadRequest, "Some words were invalid.")
+		}
+	}
+}
+
 /// Returned when a guess is made
 #[derive(Serialize,Debug)]
 struct GuessData {
-	rank: usize, // approximate rank
-	corr: f64,
+	rank: Rank, // approximate rank
+	corr: Option<f64>, // unknown outside of the kept neighbors
 	correct: bool // is the word the answer for today?
 }
 
 /// Guess a word.
 #[get("/guess?<word>")]
 fn guess(word: String, state: State<MState>) -> Response {
-	let mut state = match state.write() {
-		Err(_) => return reject(Status::BadRequest, "Could not access internal state (Server error)."),
-		Ok(s) => s
-	};
+	if let Err(e) = state.lookup(&word) {
+		return reject(Status::BadRequest, &invalid(&word, e));
+	}
 
 	let ans = state.answer().clone();
 
-	let mut guess = || -> Option<GuessData> {
+	let guess = || -> Option<GuessData> {
 		Some(GuessData {
-			corr: state.corr(&ans, &word)?,
+			corr: Some(state.corr(&ans, &word)?).filter(|c| c.is_finite()),
 			rank: state.rank(&ans, &word)?,
 			correct: word == ans
 		})
@@ -311,21 +497,77 @@ fn guess(word: String, state: State<MState>) -> Response {
 	}
 }
 
-impl Launch for Server {
-	fn new <P: AsRef<Path>> (root: P) -> std::io::Result<Server> {
+impl Server {
+	/// Serve a game from any source of similarity data.
+	///
+	/// Guesses are ranked by the scores of the source, such as any association measure
+	/// stored in correlation data or the cosine similarity of embeddings. Answers come
+	/// from a bundle, or from the `data` directory of `root` otherwise. Guesses of words
+	/// the data was built without are refused, and so are those of `data/stopwords` under
+	/// `root` if it exists, for every source.
+	pub fn open <P: AsRef<Path>> (root: P, source: Source) -> std::io::Result<Server> {
 		let root = root.as_ref();
 
 		const CACHE_LEN: usize = 1000;
 
+		info!("Serving similarity data from {:?}.", source);
+
+		let (corr, wordlist) = match &source {
+			Source::Bundle(p) => {
+				let b = Bundle::open(p).map_err(|_| std::io::ErrorKind::InvalidData)?;
+
+				info!("Bundle metadata {:?}.", b.metadata);
+				(b.sim, b.answers)
+			},
+			_ => (
+				source.load().map_err(|_| std::io::ErrorKind::InvalidData)?,
+				read::load_lines(root.join(VALID_ANSWERS)).map_err(|_| std::io::ErrorKind::NotFound)?,
+			)
+		};
+
+		let stopwords = match root.join(STOPWORDS).exists() {
+			true => read::load_words(root.join(STOPWORDS)).map_err(|_| std::io::ErrorKind::InvalidData)?,
+			false => HashSet::new()
+		};
+
+		info!("Refusing {} stopwords.", stopwords.len());
+
+		let data = CState::new(corr, wordlist, stopwords, CACHE_LEN)?;
+
 		Ok(Server {
-			data: Arc::new(RwLock::new(CState::new(
-				root,
-				CACHE_LEN 
-			)?)),
+			data: Arc::new(data),
 			static_f: StaticFiles::from(root.join("static"))
 		})
 	}
 
+	/// Index of a guess, or the reason it cannot be guessed.
+	pub fn lookup (&self, w: &str) -> error::Result<u32> {
+		self.data.lookup(w)
+	}
+
+	/// Closest words to a word that pass `filter`, as listed by `/api/neighbors`.
+	pub fn neighbors (&self, w: &str, k: usize, filter: &Filter) -> error::Result<Vec<Neighbor>> {
+		self.data.neighbors(w, k, filter)
+	}
+
+	/// Rank of word `b` among the neighbors of word `a`, as given for a guess.
+	pub fn rank (&self, a: &str, b: &str) -> Option<Rank> {
+		self.data.rank(a, b)
+	}
+
+	/// Words answers are drawn from, in the order of the days.
+	pub fn answers (&self) -> &[String] {
+		&self.data.wordlist
+	}
+}
+
+impl Launch for Server {
+	fn new <P: AsRef<Path>> (root: P) -> std::io::Result<Server> {
+		let root = root.as_ref();
+
+		Server::open(root, Source::Correlation(root.join(CORRF)))
+	}
+
 	fn mount <P: AsRef<Path>> (self, path: P, app: rocket::Rocket) -> rocket::Rocket {
 		let path = path.as_ref();
 
@@ -350,8 +592,7 @@ impl Launch for Server {
 				let cs: MState = s.state::<MState>().unwrap().clone();
 
 				let cache = move || {
-					info!("Awaiting write");
-					let mut csw = cs.write().unwrap();
+					let csw = &cs;
 
 					let today = Utc::today();
 					let yesterday = csw.word(today - Duration::days(1)).clone();
@@ -371,7 +612,7 @@ impl Launch for Server {
 					Schedule::new(cache, tokio::time::Duration::from_secs(60 * 60))
 				))
 			}))
-			.mount(path.join("api").to_str().unwrap_or("api/"), routes![guess])
+			.mount(path.join("api").to_str().unwrap_or("api/"), routes![guess, neighbors, query])
 			.mount(path.to_str().unwrap_or(""), self.static_f);
 
 		mountdev(app, routes![corr, raw])
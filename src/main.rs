@@ -3,7 +3,10 @@
 use core::time::Duration;
 use futures::{stream,StreamExt};
 use std::collections::HashSet;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use std::sync::{Arc, mpsc};
+use std::thread;
 use server::{
 	Launch,
 	rocket::{
@@ -16,9 +19,25 @@ use wikidle::{
 	Server,
 	database::{
 		self,
-		read::{Dict, load_dict, Database},
-		correlation::Correlation,
-		frequency::{Frequency}
+		read::{self, Dict, load_dict, load_words, Database, Page},
+		correlation::{self, Correlation},
+		embedding::{Embedding, Svd},
+		topk::TopK,
+		hnsw::{self, Hnsw},
+		similarity::{self, Source, Filter},
+		query::{Query, Aggregate},
+		export,
+		cluster,
+		drift,
+		benchmark,
+		bundle::Bundle,
+		inspect,
+		second,
+		matrix::{Precision, Layout},
+		measure::Measure,
+		frequency::{Frequency},
+		stats::Stats,
+		audit::Audit,
 	}
 };
 use std::io::{Write, BufWriter, BufReader, Seek, SeekFrom};
@@ -31,13 +50,16 @@ use serde::{
 use core::fmt::Formatter;
 use const_format::formatcp;
 use log::{info, error};
+use rand::{SeedableRng, prelude::SliceRandom, rngs::SmallRng};
 
 const DBNAME: &str = "enwiki-20220101-pages-articles-multistream";
 const DBDATA: &str = formatcp!("data/{}/{0}.xml", DBNAME);
 const DBINDEX: &str = formatcp!("data/{}/{0}-index.txt", DBNAME);
 const DBDICT: &str = "data/words";
+const DBSTOP: &str = "data/stopwords";
 const VALID_ANSWERS: &str = "data/answers"; // valid answer words
 const DICT_URI: &str = "https://api.dictionaryapi.dev/api/v2/entries/en/";
+const THREADS: usize = 4; // number of frequency shards counted in parallel
 
 /// enum representing a part of speech
 #[derive(Debug, Hash, PartialEq, Eq)]
@@ -200,12 +222,35 @@ async fn word_parts (word: &str) -> Option<HashSet<PartOfSpeech>> {
 	)
 }
 
-async fn gen_word_frequency<'a> (namespace: &str, dict: &'a Dict, start: u64) {
+/// Iterate over the parsed articles of the database dump, starting at byte `start`.
+///
+/// Articles that fail to parse are logged and skipped.
+fn articles (start: u64) -> impl Iterator<Item = Page> {
+	let mut db = File::open(format!("{}.bz2", DBDATA)).unwrap();
+
+	db.seek(SeekFrom::Start(start)).unwrap();
+
+	let db = BufReader::new(db);
+	let db = MultiBzDecoder::new(db);
+	// let db = File::open(DBDATA).unwrap();
+	let db = BufReader::new(db);
+	let db = Database::new(db);
+
+	db.into_iter()
+		.filter_map(|e| match e {
+			Ok(x) => Some(x),
+			Err(x) => {
+				error!("Failed to parse article with error {x:?}, skipped");
+				None
+			}
+		})
+}
+
+async fn gen_word_frequency (namespace: &str, name: Option<&str>, dict: &Arc<Dict>, start: u64, config: &correlation::Config) {
 	let path = Path::new("results").join(namespace);
 
 	let root = path.join("index.dat");
-	let cind = path.join("corrindex.dat");
-	let cpath = path.join("corr.dat");
+	let (cpath, cind) = files(namespace, "corr", name);
 
 	let valid = File::open(VALID_ANSWERS);
 	let cexist = File::open(&cind);
@@ -226,35 +271,28 @@ async fn gen_word_frequency<'a> (namespace: &str, dict: &'a Dict, start: u64) {
 		Err(_) => {
 			info!("Failed to read database, creating new instead.");
 
-			let mut db = File::open(format!("{}.bz2", DBDATA)).unwrap();
-
-			db.seek(SeekFrom::Start(start)).unwrap();
-
-			let db = BufReader::new(db);
-			let db = MultiBzDecoder::new(db);
-			// let db = File::open(DBDATA).unwrap();
-			let db = BufReader::new(db);
-			let db = Database::new(db);
-
-			let mut a = db.into_iter();
-
 			std::fs::create_dir_all(&path).unwrap();
 
-			let mut fa = Frequency::new(path.join("data.dat"), &dict).unwrap();
+			// stopwords are never counted
+			let dict = Arc::new(read::without(dict, &config.stopwords));
 
-			while let Some(e) = a.next() {
-				let page = match e {
-					Ok(x) => x,
-					Err(x) => {
-						error!("Failed to parse article with error {x:?}, skipped");
-						continue;
-					}
-				};
-				fa.insert(page.text).unwrap();
+			let shards: Vec<PathBuf> = (0..THREADS).map(|i| path.join(format!("data.{i}.dat"))).collect();
+			let fa = count_articles(path.join("data.dat"), &shards, &dict, start);
 
-				info!(target: "app::basic", "Parsed article {}: {}", page.id, page.title);
+			// shards are only read by the merge, whether it happened or not
+			for s in shards.iter() {
+				std::fs::remove_file(s).ok();
 			}
 
+			let fa = match fa {
+				Ok(fa) => fa,
+				Err(e) => {
+					std::fs::remove_file(path.join("data.dat")).ok();
+					error!("Failed to count articles: {}", e);
+					return;
+				}
+			};
+
 			let fw = BufWriter::new(File::create(&root).unwrap());
 
 			bincode::serialize_into(fw, &fa).unwrap();
@@ -269,6 +307,9 @@ async fn gen_word_frequency<'a> (namespace: &str, dict: &'a Dict, start: u64) {
 	if let Err(_) = valid {
 		let mut f = BufWriter::new(File::create(VALID_ANSWERS).unwrap());
 
+		// answers must be scored, so pruned words are left out
+		let (kept, _) = config.prune(&dat, fa.len(), dict);
+
 		let words: HashMap<u32, u32> = dat.iter()
 			.map(|(k, v)| 
 				(*k, v.into_iter().map(|(_, v)| *v as u32).sum())
@@ -284,7 +325,7 @@ async fn gen_word_frequency<'a> (namespace: &str, dict: &'a Dict, start: u64) {
 		let words = words
 			.into_iter()
 			.map(|(k, _)| k)
-			.filter (|k| k.len() >= 3)
+			.filter (|k| k.len() >= 3 && kept.contains_key(*k))
 			.take(3000);
 
 		let words: Vec<&String>  = stream::iter(words)
@@ -313,181 +354,3051 @@ async fn gen_word_frequency<'a> (namespace: &str, dict: &'a Dict, start: u64) {
 
 	if let Err(_) = cexist {
 		info!("Generating correlation data...");
-		let corr = Correlation::new(dat, fa.len(), &cpath, &dict).unwrap();
+		let corr = match Correlation::with_config(dat, fa.len(), &cpath, &dict, config) {
+			Ok(c) => c,
+			Err(e) if *e == database::error::ErrorKind::Unbounded => {
+				error!("{}", unfit(config.measure, config.precision));
+				return;
+			},
+			Err(e) => panic!("Failed to generate correlation data with error:\n{e}")
+		};
 
 		let fw = BufWriter::new(File::create(&cind).unwrap());
 		bincode::serialize_into(fw, &corr).unwrap();
 	}
 }
 
-#[tokio::main(flavor = "current_thread")]
-async fn main () {
-	log4rs::init_file("log/config.yaml", Default::default()).unwrap();
+/// Count the articles of the dump from `start` into a table at `fname`.
+///
+/// Articles are dealt in turn to worker threads, each filling its own shard of `shards`,
+/// which are interleaved back into dump order so document ids are dump positions.
+fn count_articles (fname: PathBuf, shards: &[PathBuf], dict: &Arc<Dict>, start: u64) -> database::error::Result<Frequency> {
+	let mut senders: Vec<mpsc::SyncSender<String>> = Vec::with_capacity(shards.len());
+	let mut workers: Vec<thread::JoinHandle<database::error::Result<Frequency>>> = Vec::with_capacity(shards.len());
+
+	for s in shards {
+		let (tx, rx) = mpsc::sync_channel::<String>(64);
+		let mut shard = Frequency::new(s, Arc::clone(dict))?;
+
+		senders.push(tx);
+		workers.push(thread::spawn(move || -> database::error::Result<Frequency> {
+			for text in rx {
+				shard.insert(text)?;
+			}
 
-	info!("Initiated Logger");
+			Ok(shard)
+		}));
+	}
 
-	let dict = load_dict(DBDICT).unwrap();
+	for (c, page) in articles(start).enumerate() {
+		// a worker only hangs up after failing, which its join reports
+		if senders[c % shards.len()].send(page.text).is_err() {
+			break;
+		}
 
-	// this will be discarded as it is already serialized
-	gen_word_frequency("frequency", &dict, 0).await;
+		info!(target: "app::basic", "Parsed article {}: {}", page.id, page.title);
+	}
 
-	let srv = Server::new("").unwrap();
-	let conf = Config::build(Environment::Development)
-		.address("127.0.0.1")
-    .port(8000)
-    .log_level(LoggingLevel::Normal)
-    .unwrap();
+	drop(senders);
 
-	let app = rocket::custom(conf);
+	let shards = workers.into_iter()
+		.map(|w| w.join().unwrap_or_else(|_| Err(database::error::ErrorKind::Io.into())))
+		.collect::<database::error::Result<Vec<Frequency>>>()?;
 
-	error!("Launch failed on {}", srv.mount("/", app).launch());
+	info!("Merging {} shards.", shards.len());
+
+	Frequency::interleave(fname, shards)
 }
 
+/// Why scores of `measure` cannot be stored with `precision`, and which precisions can store them.
+fn unfit (measure: Measure, precision: Precision) -> String {
+	let range = measure.association().range();
 
-#[cfg(test)]
-mod test {
-	use regex::Regex;
-	use bzip2::bufread::BzDecoder;
-	use std::io::{Read, BufRead};
-	use super::*;
+	let fit: Vec<String> = [Precision::F64, Precision::F32, Precision::F16, Precision::I16, Precision::I8].into_iter()
+		.filter(|p| p.fits(range))
+		.map(|p| format!("{p:?}").to_lowercase())
+		.collect();
 
-	const EPSILON: f64 = 1e-12;
-	
-	#[test]
-	/// Test database serialization and deserialization.
-	fn database_serialize_deserialize() {
-		let db = File::open(format!("{}.bz2", DBDATA)).unwrap();
+	format!("{measure:?} scores range from {} to {}, more than {precision:?} cells can store. Use --precision {}.",
+		range.0, range.1, fit.join(" or "))
+}
 
-		// db.seek(SeekFrom::Start(ind[ARTICLEID].0)).unwrap();
+/// Values of every occurrence of the command line option `name`, as in `--top 50`.
+///
+/// Returns a message naming the option if one of them has a missing or invalid value.
+fn parse<T: std::str::FromStr> (args: &[String], name: &str) -> Result<Vec<T>, String> {
+	args.iter()
+		.enumerate()
+		.filter(|(_, a)| *a == name)
+		.map(|(i, _)| match args.get(i + 1) {
+			Some(v) => v.parse().map_err(|_| format!("Invalid value {v} for {name}.")),
+			None => Err(format!("Missing value for {name}."))
+		})
+		.collect()
+}
 
-		// db.seek(SeekFrom::Start(2369839923)).unwrap();
+/// Values of every occurrence of the command line option `name`.
+///
+/// A missing or invalid value ends the program, rather than running with a default.
+fn opts<T: std::str::FromStr> (args: &[String], name: &str) -> Vec<T> {
+	match parse(args, name) {
+		Ok(v) => v,
+		Err(e) => {
+			error!("{e}");
+			std::process::exit(2)
+		}
+	}
+}
 
-		let db = BufReader::new(db);
-		let db = MultiBzDecoder::new(db);
-		// let db = File::open(DBDATA).unwrap();
-		let db = BufReader::new(db);
-		let db = Database::new(db);
+/// Value of the command line option `name`, `None` if it is absent.
+///
+/// A missing or invalid value ends the program, rather than running with a default.
+fn opt<T: std::str::FromStr> (args: &[String], name: &str) -> Option<T> {
+	opts(args, name).into_iter().next()
+}
 
-		let mut a = db.into_iter();
+/// Whether the command line flag `name` is present.
+fn flag (args: &[String], name: &str) -> bool {
+	args.iter().any(|a| a == name)
+}
 
-		let dict = load_dict(DBDICT).unwrap();
+/// Write a command's report to the file given by `--out`, or stdout.
+fn output (args: &[String], report: &str) {
+	match opt::<String>(args, "--out") {
+		Some(f) => std::fs::write(&f, report).unwrap(),
+		None => print!("{}", report)
+	}
+}
 
-		let mut fa = Frequency::new("results/frequency.dat", &dict).unwrap();
+/// Correlation build options given on the command line.
+///
+/// Options
+/// * `--min-df N`: drop words occurring in fewer than `N` articles.
+/// * `--max-df R`: drop words occurring in more than ratio `R` of all articles.
+/// * `--top-words N`: keep only the `N` words occurring in the most articles.
+/// * `--stopwords FILE`: words to exclude (default `data/stopwords`).
+/// * `--no-stopwords`: do not exclude any words.
+/// * `--precision P`: cell encoding, one of `f64` (default), `f32`, `f16`, `i16` or `i8`.
+/// * `--layout L`: cell arrangement, `triangular` (default) or `full`.
+/// * `--threads N`: number of threads computing the matrix (default: available cores).
+/// * `--measure M`: association measure, one of `pearson` (default), `ppmi`, `npmi`,
+///   `cosine`, `jaccard`, `dice` or `llr`.
+/// * `--no-support`: do not keep the articles of every word, which count the support of pairs.
+/// * `--shrink S`: shrink associations by `co / (co + S)`, `co` being the articles a pair shares.
+fn config (args: &[String]) -> correlation::Config {
+	let d = correlation::Config::default();
+
+	correlation::Config {
+		stopwords: match flag(args, "--no-stopwords") {
+			true => d.stopwords,
+			false => load_words(opt::<String>(args, "--stopwords").unwrap_or_else(|| DBSTOP.to_owned())).unwrap()
+		},
+		min_df: opt(args, "--min-df").unwrap_or(d.min_df),
+		max_df: opt(args, "--max-df").unwrap_or(d.max_df),
+		top: opt(args, "--top-words").or(d.top),
+		precision: opt(args, "--precision").unwrap_or(d.precision),
+		layout: opt(args, "--layout").unwrap_or(d.layout),
+		threads: opt(args, "--threads").unwrap_or(d.threads),
+		measure: opt(args, "--measure").unwrap_or(d.measure),
+		support: !flag(args, "--no-support"),
+		shrink: opt(args, "--shrink").or(d.shrink),
+	}
+}
 
-		let mut c = 0;
-		while let Some(e) = a.next() {
-			fa.insert(e.unwrap().text).unwrap();
+/// Paths of the data and index of an artifact of a namespace, such as `corr.dat` and `corrindex.dat`.
+///
+/// `name` selects an alternative copy, such as `corr.NAME.dat` and `corrindex.NAME.dat`.
+fn files (namespace: &str, kind: &str, name: Option<&str>) -> (PathBuf, PathBuf) {
+	let path = Path::new("results").join(namespace);
 
-			c += 1;
-			info!(target: "app::basic", "Parsed article {}", c);
+	match name {
+		Some(n) => (path.join(format!("{kind}.{n}.dat")), path.join(format!("{kind}index.{n}.dat"))),
+		None => (path.join(format!("{kind}.dat")), path.join(format!("{kind}index.dat")))
+	}
+}
 
-			if c % 1000 == 0 {
-				println!("Parsed article {}", c);
-			}
+/// Load the serialized correlation database of a namespace.
+fn load_correlation (namespace: &str, name: Option<&str>) -> Correlation {
+	let (_, cind) = files(namespace, "corr", name);
 
-			// if c > 100_000 { break; }
-		}
+	match similarity::read_index(&cind) {
+		Ok(d) => d,
+		Err(e) => panic!("Failed to read correlation data from {} with error:\n{e}", cind.display())
+	}
+}
 
-		let fon = "results";
-		std::fs::create_dir_all(fon).unwrap();
-		let fon = &format!("{}/frequency-index.dat", fon);
+/// Serialized similarity data of a namespace, `kind` being `corr`, `embedding`, `topk` or `bundle`.
+fn source (namespace: &str, kind: &str, name: Option<&str>) -> Option<Source> {
+	Some(match kind {
+		"corr" => Source::Correlation(files(namespace, "corr", name).1),
+		"embedding" => Source::Embedding(files(namespace, "emb", name).1),
+		"topk" => Source::TopK(files(namespace, "topk", name).1),
+		"bundle" => Source::Bundle(files(namespace, "bundle", name).0),
+		_ => return None
+	})
+}
 
-		let fw = BufWriter::new(File::create(fon).unwrap());
+/// Load the serialized frequency database of a namespace.
+fn load_frequency (namespace: &str) -> Frequency {
+	let root = Path::new("results").join(namespace).join("index.dat");
 
-		bincode::serialize_into(fw, &fa).unwrap();
+	match File::open(&root) {
+		Ok(f) => match bincode::deserialize_from(f) {
+			Ok(d) => d,
+			Err(e) => panic!("Failed to deserialize database with error:\n{e}")
+		},
+		Err(e) => panic!("Failed to open {} with error:\n{e}", root.display())
+	}
+}
 
-		let fr = File::open(fon).unwrap();
+/// Print statistics about the corpus of a namespace.
+///
+/// Options
+/// * `--top N`: number of words listed in the text report (default 50).
+/// * `--json`: print the full report as JSON.
+/// * `--out FILE`: write the report to a file.
+fn stats (namespace: &str, args: &[String]) {
+	let dict = load_dict(DBDICT).unwrap();
+	let mut fa = load_frequency(namespace);
 
-		// Deserialize from the file.
-		let mut fad = bincode::deserialize_from(fr).unwrap();
+	info!("Loading freq data to memory");
+	let dat = fa.load().unwrap();
 
-		// Assert that the data of both databases are equal.
-		assert_eq!(fa, fad);
-		// not writable
-		assert_eq!(
-			fad.insert(String::from("")),
-			Err(database::error::ErrorKind::MissingDict.into())
-		);
+	let s = Stats::new(&dat, fa.len(), &dict, opt(args, "--top").unwrap_or(50));
 
-		fad.set_dict(&dict);
-		// now it should be writable
-		assert_eq!(fad.insert(String::from("")), Ok( () ));
+	output(args, &if flag(args, "--json") {
+		serde_json::to_string_pretty(&s).unwrap()
+	} else {
+		s.to_string()
+	});
+}
+
+/// Audit the dictionary against the tokens of the database dump.
+///
+/// Options
+/// * `--top N`: number of missed tokens listed per cause (default 50).
+/// * `--limit N`: only audit the first `N` articles.
+/// * `--json`: print the report as JSON.
+/// * `--out FILE`: write the report to a file.
+fn audit (args: &[String]) {
+	let mut audit = Audit::new(Arc::new(load_dict(DBDICT).unwrap()));
+
+	for page in articles(0).take(opt(args, "--limit").unwrap_or(usize::MAX)) {
+		audit.insert(&page.text);
+
+		info!(target: "app::basic", "Audited article {}: {}", page.id, page.title);
 	}
 
-	#[test]
-	/// Load the index file into memory.
-	fn index_read () {
-		let indexmap: Regex = Regex::new(r"^(\d+):(\d+):(.+)$").unwrap();
+	let report = audit.report(opt(args, "--top").unwrap_or(50));
 
-		let ind = File::open(format!("{}.bz2", DBINDEX)).unwrap();
-		let ind = BufReader::new(ind);
-		let ind = BzDecoder::new(ind);
-		let ind = BufReader::new(ind);
+	output(args, &if flag(args, "--json") {
+		serde_json::to_string_pretty(&report).unwrap()
+	} else {
+		report.to_string()
+	});
+}
 
-		let ind: Vec<(u64, u32, String)> = ind.lines()
-			.map(|e| e.unwrap())
-			.map(|e| {
-				let s = indexmap.captures_iter(&e).next().unwrap();
+/// Rewrite the correlation data of a namespace with another cell encoding or layout.
+///
+/// Options
+/// * `--precision P`: target encoding, one of `f64`, `f32`, `f16`, `i16` or `i8`.
+/// * `--layout L`: target layout, `triangular` or `full`.
+/// * `--to NAME`: write `corr.NAME.dat` and `corrindex.NAME.dat` instead of replacing the data.
+fn convert (namespace: &str, args: &[String]) {
+	if opt::<Precision>(args, "--precision").is_none() && opt::<Layout>(args, "--layout").is_none() {
+		error!("Missing or invalid --precision and --layout.");
+		return;
+	}
 
-				(
-					s[1].parse::<u64>().unwrap(), 
-					s[2].parse::<u32>().unwrap(), 
-					s[3].to_owned()
-				)
+	let corr = load_correlation(namespace, None);
+
+	let precision = opt(args, "--precision").unwrap_or(corr.precision());
+	let layout = opt(args, "--layout").unwrap_or(corr.layout());
+
+	let (cpath, cind) = files(namespace, "corr", opt::<String>(args, "--to").as_deref());
+
+	info!("Converting to {} from {:?} {:?} to {:?} {:?}.", cpath.display(), corr.precision(), corr.layout(), precision, layout);
+
+	let corr = match corr.convert(&cpath, precision, layout) {
+		Ok(c) => c,
+		Err(e) if *e == database::error::ErrorKind::Unbounded => {
+			error!("{}", unfit(corr.measure(), precision));
+			return;
+		},
+		Err(e) => panic!("Failed to convert correlation data with error:\n{e}")
+	};
+
+	let fw = BufWriter::new(File::create(&cind).unwrap());
+	bincode::serialize_into(fw, &corr).unwrap();
+
+	info!("Converted, new size {} bytes.", std::fs::metadata(&cpath).unwrap().len());
+}
+
+/// Compare the latency of loading full rows from copies of the correlation data.
+///
+/// Every copy is timed on the same randomly chosen words, shared by all copies.
+///
+/// Options
+/// * `--with NAME`: also time `corrindex.NAME.dat`, may be repeated.
+/// * `--samples N`: number of rows loaded per copy (default 200).
+/// * `--seed S`: seed of the word choice (default 0).
+fn bench (namespace: &str, args: &[String]) {
+	let names: Vec<Option<String>> = std::iter::once(None)
+		.chain(opts::<String>(args, "--with").into_iter().map(Some))
+		.collect();
+
+	let corrs: Vec<(String, Correlation)> = names.iter()
+		.map(|n| (n.clone().unwrap_or_else(|| String::from("default")), load_correlation(namespace, n.as_deref())))
+		.collect();
+
+	let mut words: Vec<&String> = corrs[0].1.dict().keys()
+		.filter(|w| corrs.iter().all(|(_, c)| c.index(w).is_some()))
+		.collect();
+
+	words.sort_unstable();
+
+	let mut rng = SmallRng::seed_from_u64(opt(args, "--seed").unwrap_or(0));
+	let samples: Vec<&String> = (0..opt(args, "--samples").unwrap_or(200))
+		.filter_map(|_| words.choose(&mut rng).map(|w| *w))
+		.collect();
+
+	let mut report = format!("{:<16} {:<10} {:>9} {:>12} {:>12} {:>12}\n", "data", "layout", "precision", "mean (us)", "median (us)", "p95 (us)");
+
+	for (name, corr) in corrs.iter() {
+		let mut times: Vec<f64> = samples.iter()
+			.map(|w| {
+				let start = Instant::now();
+				let row = corr.corrall(w).unwrap();
+				let t = start.elapsed().as_secs_f64() * 1e6;
+
+				drop(row);
+				t
 			})
 			.collect();
 
-		const ARTICLEID: usize = 921235 + 70611;
+		times.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
 
-		println!("Parsing starting at article {} id {}, {:?}, byte {}", ARTICLEID, ind[ARTICLEID].1, ind[ARTICLEID].2, ind[ARTICLEID].0);
+		let at = |q: f64| times.get(((times.len() as f64 * q) as usize).min(times.len().saturating_sub(1))).map(|e| *e).unwrap_or(0.);
+
+		report.push_str(&format!("{:<16} {:<10} {:>9} {:>12.1} {:>12.1} {:>12.1}\n",
+			name,
+			format!("{:?}", corr.layout()),
+			format!("{:?}", corr.precision()),
+			times.iter().sum::<f64>() / times.len().max(1) as f64,
+			at(0.5),
+			at(0.95)));
 	}
 
-	#[test]
-	/// Test parse_wiki_text
-	fn wikitext () {
-		let mut tmp = File::open("tmp.log").unwrap();
-		let mut contents = String::new();
-		tmp.read_to_string(&mut contents).unwrap();
+	output(args, &report);
+}
 
-		println!("{:?}", database::read::CONFIG.parse(&contents));
-	}
+/// Compute word embeddings from the frequency data of a namespace.
+///
+/// Accepts the correlation build options, which select the embedded words.
+///
+/// Options
+/// * `--dim K`: dimension of the vectors (default 100).
+/// * `--oversample P`: extra random directions of the SVD (default 10).
+/// * `--power Q`: power iterations of the SVD (default 2).
+/// * `--seed S`: seed of the random directions (default 0).
+/// * `--to NAME`: write `emb.NAME.dat` and `embindex.NAME.dat` instead of `emb.dat` and `embindex.dat`.
+fn embed (namespace: &str, args: &[String]) {
+	let dict = load_dict(DBDICT).unwrap();
+	let mut fa = load_frequency(namespace);
 
-	#[test]
-	/// Correlation test
-	fn corr () {
-		let dict = load_dict("data/words").unwrap();
-		let dat: HashMap<u32,Vec<(u32,u16)>> = HashMap::from([
-			(0, vec![(0, 1), (1, 1), (9, 2)]),
-			(1, vec![(0, 1), (1, 1), (9, 2)]),
-			(2, vec![(9, 1)]),
-			(3, vec![(5, 1)])
-		]);
+	info!("Loading freq data to memory");
+	let dat = fa.load().unwrap();
 
-		let mut c = Correlation::new(dat, 10, "results/_test/corr.dat", &dict).unwrap();
+	let d = Svd::default();
+	let svd = Svd {
+		dim: opt(args, "--dim").unwrap_or(d.dim),
+		oversample: opt(args, "--oversample").unwrap_or(d.oversample),
+		power: opt(args, "--power").unwrap_or(d.power),
+		seed: opt(args, "--seed").unwrap_or(d.seed),
+	};
 
-		println!("{:?}", c.dict());
+	let (epath, eind) = files(namespace, "emb", opt::<String>(args, "--to").as_deref());
 
-		let acorn = c.corrall("a").unwrap();
+	info!("Computing {} dimensional embeddings into {}.", svd.dim, epath.display());
 
-		let corr = |c: &mut Correlation, a:&str, b:&str, exp: f64| {
-			let co = c.corr(a,b).unwrap_or(0.);
-			println!("{}/{}: {} ({})", a, b, co, exp);
+	let emb = Embedding::new(dat, fa.len(), &epath, &dict, &config(args), &svd).unwrap();
 
-			assert!((co - exp).abs() < EPSILON);
+	let fw = BufWriter::new(File::create(&eind).unwrap());
+	bincode::serialize_into(fw, &emb).unwrap();
+
+	info!("Embedded {} words.", emb.dict().len());
+}
+
+/// Import vectors trained elsewhere, from GloVe, word2vec or fastText, as embeddings of the namespace.
+///
+/// Only words of the dictionary are kept, a game is then served on them with `serve --source embedding`.
+///
+/// Options
+/// * `--binary`: read the binary word2vec format (default for `.bin` files) instead of text.
+/// * `--to NAME`: write `emb.NAME.dat` and `embindex.NAME.dat` instead of `emb.dat` and `embindex.dat`.
+fn import (namespace: &str, args: &[String]) {
+	let src = match args.first().filter(|a| !a.starts_with("--")) {
+		Some(f) => Path::new(f),
+		None => {
+			error!("Missing vector file.");
+			return;
+		}
+	};
+
+	let binary = flag(args, "--binary") || src.extension().map_or(false, |e| e == "bin");
+	let dict = load_dict(DBDICT).unwrap();
+
+	let (epath, eind) = files(namespace, "emb", opt::<String>(args, "--to").as_deref());
+	std::fs::create_dir_all(epath.parent().unwrap()).unwrap();
+
+	info!("Importing vectors from {} into {}.", src.display(), epath.display());
+
+	let emb = match Embedding::import(src, binary, &epath, &dict) {
+		Ok(e) => e,
+		Err(e) => {
+			error!("Could not import {}: {}", src.display(), e);
+			return;
+		}
+	};
+
+	let fw = BufWriter::new(File::create(&eind).unwrap());
+	bincode::serialize_into(fw, &emb).unwrap();
+
+	info!("Imported {} words of dimension {}, {} without a vector.", emb.dict().len(), emb.dim(), emb.pruned().len());
+}
+
+/// Keep only the nearest neighbors of every word, for servers without room for a full matrix.
+///
+/// Options
+/// * `--k K`: neighbors kept per word (default 1000).
+/// * `--from S`: `frequency` (default) scores the frequency data directly and accepts the
+///   correlation build options, `corr` or `embedding` ranks that data of the namespace.
+/// * `--name NAME`: copy of the data ranked with `--from`.
+/// * `--threads N`: number of threads ranking words (default: available cores).
+/// * `--to NAME`: write `topk.NAME.dat` and `topkindex.NAME.dat` instead of `topk.dat` and `topkindex.dat`.
+fn topk (namespace: &str, args: &[String]) {
+	let k = opt(args, "--k").unwrap_or(1000);
+	let name: Option<String> = opt(args, "--name");
+	let config = config(args);
+
+	let (tpath, tind) = files(namespace, "topk", opt::<String>(args, "--to").as_deref());
+
+	info!("Keeping {} neighbors per word in {}.", k, tpath.display());
+
+	let top = match opt::<String>(args, "--from").as_deref() {
+		Some("frequency") | None => {
+			let dict = load_dict(DBDICT).unwrap();
+			let mut fa = load_frequency(namespace);
+
+			info!("Loading freq data to memory");
+			let dat = fa.load().unwrap();
+
+			TopK::new(dat, fa.len(), &tpath, &dict, &config, k).unwrap()
+		},
+		Some(s @ ("corr" | "embedding")) => {
+			let sim = source(namespace, s, name.as_deref()).unwrap().load().unwrap();
+
+			TopK::from_similarity(sim.as_ref(), &tpath, k, config.threads).unwrap()
+		},
+		Some(s) => {
+			error!("Unknown source {}.", s);
+			return;
+		}
+	};
+
+	let fw = BufWriter::new(File::create(&tind).unwrap());
+	bincode::serialize_into(fw, &top).unwrap();
+
+	info!("Kept {} neighbors of {} words, {} bytes.", top.k(), top.dict().len(), std::fs::metadata(&tpath).unwrap().len());
+}
+
+/// Build an approximate nearest neighbor graph over similarity data of a namespace, and
+/// compare its search to exact search on random words.
+///
+/// Options
+/// * `--from S`: data the graph links, `embedding` (default), `corr` or `topk`.
+/// * `--name NAME`: copy of the data, the graph being `hnsw.NAME.dat` instead of `hnsw.dat`.
+/// * `--m M`: links per word and layer (default 16).
+/// * `--ef-construction N`: candidates considered when linking a word (default 200).
+/// * `--query WORD`: list the words closest to `WORD` with an existing graph instead.
+/// * `--recall`: only measure the recall of an existing graph.
+/// * `--k K`: number of words searched (default 10).
+/// * `--ef N`: candidates considered by a search (default 64).
+/// * `--samples N`: number of words searched when measuring recall (default 200).
+/// * `--seed S`: seed of the graph layers and of the word choice (default 0).
+/// * `--json`: print the recall report as JSON.
+/// * `--out FILE`: write the report to a file.
+fn hnsw (namespace: &str, args: &[String]) {
+	let kind: String = opt(args, "--from").unwrap_or_else(|| String::from("embedding"));
+	let name: Option<String> = opt(args, "--name");
+
+	let sim = match source(namespace, &kind, name.as_deref()) {
+		Some(s) => s.load().unwrap(),
+		None => {
+			error!("Unknown source {}.", kind);
+			return;
+		}
+	};
+
+	let gpath = files(namespace, "hnsw", name.as_deref()).0;
+	let k = opt(args, "--k").unwrap_or(10);
+	let ef = opt(args, "--ef").unwrap_or(64);
+	let seed = opt(args, "--seed").unwrap_or(0);
+
+	let g = match opt::<String>(args, "--query").is_some() || flag(args, "--recall") {
+		true => Hnsw::load(&gpath, sim.as_ref()).unwrap(),
+		false => {
+			let d = hnsw::Options::default();
+			let options = hnsw::Options {
+				m: opt(args, "--m").unwrap_or(d.m),
+				ef: opt(args, "--ef-construction").unwrap_or(d.ef),
+				seed,
+			};
+
+			info!("Linking {} words into {}.", sim.dict().len(), gpath.display());
+
+			let start = Instant::now();
+			let g = Hnsw::new(sim.as_ref(), &options);
+			g.save(&gpath).unwrap();
+
+			info!("Linked {} words in {:.1}s.", g.len(), start.elapsed().as_secs_f64());
+			g
+		}
+	};
+
+	if let Some(w) = opt::<String>(args, "--query") {
+		let start = Instant::now();
+
+		let found = match g.query(sim.as_ref(), &w, k, ef) {
+			Some(f) => f,
+			None => {
+				error!("{} is not a valid word.", w);
+				return;
+			}
 		};
 
-		corr(&mut c, "A","A's", 1.);
-		corr(&mut c, "AMD","A", 0.804030252207);
-		corr(&mut c, "AMD's","AMD", -0.111111111111);
+		let mut report = format!("{} nearest words to {} in {:.3} ms\n", found.len(), w, start.elapsed().as_secs_f64() * 1e3);
 
-		let i = c.index("A's").unwrap() as usize;
-		corr(&mut c, "A", "A's", acorn[i]);
-		let i = c.index("AMD").unwrap() as usize;
-		corr(&mut c, "A", "AMD", acorn[i]);
-		let i = c.index("Amd's").unwrap() as usize;
-		corr(&mut c, "A", "AMD's", acorn[i]);
-		let i = c.index("A").unwrap() as usize;
-		corr(&mut c, "A", "A", acorn[i]);
+		let names = sim.names();
+
+		for (i, v) in found {
+			report.push_str(&format!("{:<24} {:.5}\n", names[i as usize], v));
+		}
+
+		return output(args, &report);
+	}
+
+	let mut words: Vec<&str> = sim.dict().keys().map(|w| w.as_str()).collect();
+	words.sort_unstable();
+
+	let mut rng = SmallRng::seed_from_u64(seed);
+	let samples: Vec<&str> = (0..opt(args, "--samples").unwrap_or(200))
+		.filter_map(|_| words.choose(&mut rng).map(|w| *w))
+		.collect();
+
+	let r = g.recall(sim.as_ref(), &samples, k, ef);
+
+	output(args, &if flag(args, "--json") {
+		serde_json::to_string_pretty(&r).unwrap()
+	} else {
+		r.to_string()
+	});
+}
+
+/// List the closest words to a word.
+///
+/// Options
+/// * `--from S`: data the scores come from, `corr` (default), `embedding` or `topk`.
+/// * `--name NAME`: copy of the data.
+/// * `--k K`: number of words listed (default 20).
+/// * `--answers`: only list words of the answer list.
+/// * `--words FILE`: only list words of a file, one per line.
+/// * `--min-df N`: only list words occurring in at least `N` articles.
+/// * `--json`: print the list as JSON.
+/// * `--out FILE`: write the list to a file.
+fn neighbors (namespace: &str, args: &[String]) {
+	let word = match args.first().filter(|a| !a.starts_with("--")) {
+		Some(w) => w,
+		None => {
+			error!("Missing word.");
+			return;
+		}
+	};
+
+	let kind: String = opt(args, "--from").unwrap_or_else(|| String::from("corr"));
+
+	let sim = match source(namespace, &kind, opt::<String>(args, "--name").as_deref()) {
+		Some(s) => s.load().unwrap(),
+		None => {
+			error!("Unknown source {}.", kind);
+			return;
+		}
+	};
+
+	let mut words: Option<HashSet<String>> = opt::<String>(args, "--words").map(|f| load_words(f).unwrap());
+
+	if flag(args, "--answers") {
+		let answers = load_words(VALID_ANSWERS).unwrap();
+
+		words = Some(match words {
+			Some(w) => w.intersection(&answers).cloned().collect(),
+			None => answers
+		});
+	}
+
+	let filter = Filter { words, min_df: opt(args, "--min-df") };
+
+	let found = match sim.neighbors(word, opt(args, "--k").unwrap_or(20), &filter) {
+		Ok(f) => f,
+		Err(e) => {
+			error!("Could not list neighbors of {}: {}", word, e);
+			return;
+		}
+	};
+
+	output(args, &if flag(args, "--json") {
+		serde_json::to_string_pretty(&found).unwrap()
+	} else {
+		found.iter()
+			.map(|n| format!("{:>7} {:<24} {:.5}\n", n.rank, n.word, n.score))
+			.collect()
+	});
+}
+
+/// List the words best related to some words and least to others.
+///
+/// Words are given as `WORD` or `WORD:WEIGHT`, the weight defaulting to 1.
+///
+/// Options
+/// * `--pos WORD`: word results should relate to, may be repeated.
+/// * `--neg WORD`: word results should not relate to, may be repeated.
+/// * `--aggregate A`: `mean` (default), `min` or `centroid`.
+/// * `--from S`, `--name NAME`, `--k K`, `--answers`, `--min-df N`, `--json`, `--out FILE`: as for `neighbors`.
+fn query (namespace: &str, args: &[String]) {
+	let weighted = |name: &str| -> Vec<(String, f64)> {
+		opts::<String>(args, name).into_iter()
+			.map(|w| match w.split_once(':') {
+				Some((t, x)) => match x.parse() {
+					Ok(x) => (t.to_owned(), x),
+					Err(_) => {
+						error!("Invalid weight in {} {}.", name, w);
+						std::process::exit(2)
+					}
+				},
+				None => (w, 1.)
+			})
+			.collect()
+	};
+
+	let q = Query {
+		positive: weighted("--pos"),
+		negative: weighted("--neg"),
+		aggregate: opt(args, "--aggregate").unwrap_or(Aggregate::Mean),
+	};
+
+	let kind: String = opt(args, "--from").unwrap_or_else(|| String::from("corr"));
+
+	let sim = match source(namespace, &kind, opt::<String>(args, "--name").as_deref()) {
+		Some(s) => s.load().unwrap(),
+		None => {
+			error!("Unknown source {}.", kind);
+			return;
+		}
+	};
+
+	let filter = Filter {
+		words: flag(args, "--answers").then(|| load_words(VALID_ANSWERS).unwrap()),
+		min_df: opt(args, "--min-df"),
+	};
+
+	let found = match q.top(sim.as_ref(), opt(args, "--k").unwrap_or(20), &filter) {
+		Ok(f) => f,
+		Err(e) => {
+			error!("Could not answer the query: {}", e);
+			return;
+		}
+	};
+
+	output(args, &if flag(args, "--json") {
+		serde_json::to_string_pretty(&found).unwrap()
+	} else {
+		found.iter()
+			.map(|n| format!("{:>7} {:<24} {:.5}\n", n.rank, n.word, n.score))
+			.collect()
+	});
+}
+
+/// Group words into themes, naming every theme by its most central words.
+///
+/// Options
+/// * `--method M`: `kmeans` (default) or `agglomerative`.
+/// * `--k K`: number of clusters (default 20).
+/// * `--labels N`: words naming every cluster (default 5).
+/// * `--iterations N`: maximum number of k-means iterations (default 100).
+/// * `--seed N`: seed of the first k-means centroids.
+/// * `--from S`: data the words are compared on, `corr` (default), `embedding` or `topk`,
+///   rows of scores being restricted to the clustered words.
+/// * `--name NAME`: copy of the data.
+/// * `--answers`, `--words FILE`, `--min-df N`: only cluster these words, as for `neighbors`,
+///   the answer list being clustered when neither `--words` nor `--all` is given.
+/// * `--all`: cluster every word of the data, within `--limit`.
+/// * `--limit N`: most words clustered (default 10000), as memory grows with its square.
+/// * `--show N`: only print the words of cluster `N`, one per line, as read by `--words`.
+/// * `--json`: print the clusters as JSON.
+/// * `--out FILE`: write the clusters to a file.
+fn cluster (namespace: &str, args: &[String]) {
+	let kind: String = opt(args, "--from").unwrap_or_else(|| String::from("corr"));
+
+	let sim = match source(namespace, &kind, opt::<String>(args, "--name").as_deref()) {
+		Some(s) => s.load().unwrap(),
+		None => {
+			error!("Unknown source {}.", kind);
+			return;
+		}
+	};
+
+	let mut words: Option<HashSet<String>> = opt::<String>(args, "--words").map(|f| load_words(f).unwrap());
+
+	if flag(args, "--answers") || (words.is_none() && !flag(args, "--all")) {
+		let answers = load_words(VALID_ANSWERS).unwrap();
+
+		words = Some(match words {
+			Some(w) => w.intersection(&answers).cloned().collect(),
+			None => answers
+		});
+	}
+
+	let filter = Filter { words, min_df: opt(args, "--min-df") };
+
+	let mut kept: Vec<(&str, u32)> = Vec::new();
+
+	for (w, i) in sim.dict().iter() {
+		match filter.accepts(sim.as_ref(), *i, w) {
+			Ok(true) => kept.push((w, *i)),
+			Ok(false) => (),
+			Err(e) => {
+				error!("Could not select words: {}", e);
+				return;
+			}
+		}
+	}
+
+	kept.sort_unstable_by_key(|e| e.1);
+
+	let limit: usize = opt(args, "--limit").unwrap_or(cluster::Options::default().limit);
+
+	if kept.len() > limit {
+		error!("Refusing to cluster {} words, more than --limit {}. Select fewer with --words or --min-df.", kept.len(), limit);
+		return;
+	}
+
+	let words: Vec<&str> = kept.into_iter().map(|e| e.0).collect();
+
+	let d = cluster::Options::default();
+	let options = cluster::Options {
+		method: opt(args, "--method").unwrap_or(d.method),
+		k: opt(args, "--k").unwrap_or(d.k),
+		labels: opt(args, "--labels").unwrap_or(d.labels),
+		iterations: opt(args, "--iterations").unwrap_or(d.iterations),
+		seed: opt(args, "--seed").unwrap_or(d.seed),
+		restrict: kind != "embedding",
+		limit,
+	};
+
+	info!("Clustering {} words into {} groups.", words.len(), options.k);
+
+	let start = Instant::now();
+
+	let c = match cluster::cluster(sim.as_ref(), &words, &options) {
+		Ok(c) => c,
+		Err(e) => {
+			error!("Could not cluster words: {}", e);
+			return;
+		}
+	};
+
+	info!("Clustered in {:.1}s.", start.elapsed().as_secs_f64());
+
+	if let Some(n) = opt::<usize>(args, "--show") {
+		return match c.clusters.get(n) {
+			Some(c) => output(args, &c.words.iter().map(|w| format!("{w}\n")).collect::<String>()),
+			None => error!("There are only {} clusters.", c.clusters.len())
+		};
+	}
+
+	output(args, &if flag(args, "--json") {
+		serde_json::to_string_pretty(&c).unwrap()
+	} else {
+		c.clusters.iter()
+			.enumerate()
+			.map(|(i, c)| format!("{:>3} {:>6} {:.3}  {}\n", i, c.words.len(), c.cohesion, c.labels.join(", ")))
+			.collect()
+	});
+}
+
+/// Report how the neighborhoods of words changed between two builds.
+///
+/// Builds are given as `NAMESPACE` or `NAMESPACE:NAME`, defaulting to the data of the namespace.
+///
+/// Options
+/// * `--old B`, `--new B`: builds compared.
+/// * `--from S`: data compared, `corr` (default), `embedding` or `topk`.
+/// * `--k K`: neighbors compared per word (default 20).
+/// * `--changed N`: most changed words reported (default 50).
+/// * `--step N`: only compare every `N`-th shared word (default 1).
+/// * `--threads N`: number of threads comparing words (default: available cores).
+/// * `--json`: print the report as JSON.
+/// * `--out FILE`: write the report to a file.
+fn drift (namespace: &str, args: &[String]) {
+	let kind: String = opt(args, "--from").unwrap_or_else(|| String::from("corr"));
+
+	let load = |name: &str| {
+		let build: String = opt(args, name).unwrap_or_else(|| namespace.to_owned());
+
+		let (ns, copy) = match build.split_once(':') {
+			Some((ns, copy)) => (ns.to_owned(), Some(copy.to_owned())),
+			None => (build, None)
+		};
+
+		source(&ns, &kind, copy.as_deref()).map(|s| s.load().unwrap())
+	};
+
+	let (old, new) = match (load("--old"), load("--new")) {
+		(Some(o), Some(n)) => (o, n),
+		_ => {
+			error!("Unknown source {}.", kind);
+			return;
+		}
+	};
+
+	let d = drift::Options::default();
+	let options = drift::Options {
+		k: opt(args, "--k").unwrap_or(d.k),
+		changed: opt(args, "--changed").unwrap_or(d.changed),
+		step: opt(args, "--step").unwrap_or(d.step),
+		threads: opt(args, "--threads").unwrap_or(d.threads),
+	};
+
+	let start = Instant::now();
+	let report = drift::compare(old.as_ref(), new.as_ref(), &options);
+
+	info!("Compared {} words in {:.1}s.", report.compared, start.elapsed().as_secs_f64());
+
+	output(args, &if flag(args, "--json") {
+		serde_json::to_string_pretty(&report).unwrap()
+	} else {
+		report.to_string()
+	});
+}
+
+/// Score similarity data against word similarity benchmarks, such as `benchmark data/simlex.txt data/men.txt`.
+///
+/// Options
+/// * `--format F`: `wordsim`, `simlex` or `men`, guessed from every file name by default.
+/// * `--from S`: data evaluated, `corr` (default), `embedding` or `topk`.
+/// * `--name NAME`: copy of the data.
+/// * `--json`: print the results as JSON.
+/// * `--out FILE`: write the results to a file.
+fn benchmark (namespace: &str, args: &[String]) {
+	let files: Vec<&String> = args.iter().take_while(|a| !a.starts_with("--")).collect();
+
+	if files.is_empty() {
+		error!("Missing benchmark file.");
+		return;
+	}
+
+	let kind: String = opt(args, "--from").unwrap_or_else(|| String::from("corr"));
+	let name: Option<String> = opt(args, "--name");
+
+	let sim = match source(namespace, &kind, name.as_deref()) {
+		Some(s) => s.load().unwrap(),
+		None => {
+			error!("Unknown source {}.", kind);
+			return;
+		}
+	};
+
+	let mut report = benchmark::Report {
+		source: match name {
+			Some(n) => format!("{namespace}/{kind}.{n}"),
+			None => format!("{namespace}/{kind}")
+		},
+		benchmarks: Vec::new(),
+	};
+
+	for f in files {
+		let format = opt(args, "--format").unwrap_or_else(|| benchmark::Format::guess(f));
+
+		let pairs = match benchmark::read(f, format) {
+			Ok(p) => p,
+			Err(e) => {
+				error!("Could not read {}: {}", f, e);
+				return;
+			}
+		};
+
+		let stem = Path::new(f).file_stem().map_or_else(|| f.clone(), |s| s.to_string_lossy().into_owned());
+
+		report.benchmarks.push(benchmark::evaluate(sim.as_ref(), &stem, format, &pairs));
+	}
+
+	output(args, &if flag(args, "--json") {
+		serde_json::to_string_pretty(&report).unwrap()
+	} else {
+		report.to_string()
+	});
+}
+
+/// Pack similarity data with the answer list into a single file, which
+/// `serve --bundle FILE` runs a game on wherever it is copied.
+///
+/// Options
+/// * `--from S`: data bundled, `corr` (default), `embedding` or `topk`.
+/// * `--name NAME`: copy of the data.
+/// * `--answers FILE`: answer list (default `data/answers`).
+/// * `--to NAME`: write `bundle.NAME.dat` instead of `bundle.dat`.
+/// * `--out FILE`: write the bundle to a file.
+fn bundle (namespace: &str, args: &[String]) {
+	let kind: String = opt(args, "--from").unwrap_or_else(|| String::from("corr"));
+
+	let src = match source(namespace, &kind, opt::<String>(args, "--name").as_deref()) {
+		Some(s) => s,
+		None => {
+			error!("Unknown source {}.", kind);
+			return;
+		}
+	};
+
+	let fanswers: String = opt(args, "--answers").unwrap_or_else(|| VALID_ANSWERS.to_owned());
+
+	// kept in file order, which the game shuffles into the answer of every day
+	let answers: Vec<String> = match read::load_lines(&fanswers) {
+		Ok(w) => w,
+		Err(e) => {
+			error!("Could not read {}: {}", fanswers, e);
+			return;
+		}
+	};
+
+	let out: PathBuf = opt(args, "--out")
+		.unwrap_or_else(|| files(namespace, "bundle", opt::<String>(args, "--to").as_deref()).0);
+
+	info!("Bundling {:?} into {}.", src, out.display());
+
+	match Bundle::write(&src, &answers, &out) {
+		Ok(m) => info!("Bundled {} words and {} answers.", m.words, m.answers),
+		Err(e) => error!("Could not bundle {:?}: {}", src, e)
+	}
+}
+
+/// Check the correlation data of a namespace for damage, and repair it if asked.
+///
+/// Options
+/// * `--name NAME`: copy of the data.
+/// * `--data FILE`: data file read instead of the one named by the index.
+/// * `--sample WORD`: word whose neighbors are listed, may be repeated.
+/// * `--samples N`: number of words listed without `--sample`, spread over the dictionary (default 3).
+/// * `--k K`: neighbors listed per word (default 10).
+/// * `--examples N`: number of damaged cells and words listed (default 20).
+/// * `--repair P`: rewrite damaged cells with `zero`, `lowest` or `clamp`.
+/// * `--json`: print the report as JSON.
+/// * `--out FILE`: write the report to a file.
+fn inspect (namespace: &str, args: &[String]) {
+	let (_, cind) = files(namespace, "corr", opt::<String>(args, "--name").as_deref());
+	let d = inspect::Options::default();
+
+	let repair: Option<inspect::Policy> = match opt::<String>(args, "--repair") {
+		Some(p) => match p.parse() {
+			Ok(p) => Some(p),
+			Err(_) => {
+				error!("Unknown repair policy {}.", p);
+				return;
+			}
+		},
+		None => None
+	};
+
+	let options = inspect::Options {
+		data: opt(args, "--data"),
+		examples: opt(args, "--examples").unwrap_or(d.examples),
+		samples: opts(args, "--sample"),
+		spread: opt(args, "--samples").unwrap_or(d.spread),
+		k: opt(args, "--k").unwrap_or(d.k),
+		repair,
+	};
+
+	let report = match inspect::inspect(&cind, &options) {
+		Ok(r) => r,
+		Err(e) => {
+			error!("Could not inspect {}: {}", cind.display(), e);
+			return;
+		}
+	};
+
+	if report.repaired > 0 {
+		info!("Repaired {} cells of {}.", report.repaired, report.data.display());
+	}
+
+	output(args, &if flag(args, "--json") {
+		serde_json::to_string_pretty(&report).unwrap()
+	} else {
+		report.to_string()
+	});
+}
+
+/// Derive second-order similarity from the correlation data of a namespace, words being
+/// alike when their rows are, which `serve --name NAME` runs a game on.
+///
+/// Options
+/// * `--name NAME`: copy of the correlation data compared.
+/// * `--to NAME`: write `corr.NAME.dat` and `corrindex.NAME.dat` (default `second`).
+/// * `--precision P`: cell encoding, one of `f64`, `f32` (default), `f16`, `i16` or `i8`.
+/// * `--layout L`: cell arrangement, `triangular` (default) or `full`.
+/// * `--threads N`: number of threads computing the matrix (default: available cores).
+/// * `--block N`: rows computed before they are written out, bounding memory (default 1024).
+/// * `--no-center`: compare rows by cosine rather than correlation.
+fn second (namespace: &str, args: &[String]) {
+	let corr = load_correlation(namespace, opt::<String>(args, "--name").as_deref());
+	let d = second::Options::default();
+
+	let options = second::Options {
+		precision: opt(args, "--precision").unwrap_or(d.precision),
+		layout: opt(args, "--layout").unwrap_or(d.layout),
+		threads: opt(args, "--threads").unwrap_or(d.threads),
+		block: opt(args, "--block").unwrap_or(d.block),
+		center: !flag(args, "--no-center"),
+	};
+
+	let to: String = opt(args, "--to").unwrap_or_else(|| String::from("second"));
+	let (cpath, cind) = files(namespace, "corr", Some(&to));
+
+	info!("Building second-order similarity of {} words into {}.", corr.dict().len(), cpath.display());
+
+	let start = Instant::now();
+
+	let second = match second::build(&corr, &cpath, &options) {
+		Ok(s) => s,
+		Err(e) => {
+			error!("Could not build {}: {}", cpath.display(), e);
+			return;
+		}
+	};
+
+	let fw = BufWriter::new(File::create(&cind).unwrap());
+	bincode::serialize_into(fw, &second).unwrap();
+
+	info!("Built in {:.1}s, serve it with --name {}.", start.elapsed().as_secs_f64(), to);
+}
+
+/// Write similarity data in a format read by other tools.
+///
+/// Options
+/// * `--format F`: `word2vec` (default), `word2vec-bin`, `glove`, `csv`, `npy` or `npz`.
+/// * `--from S`: data exported, `corr`, `embedding` (default) or `topk`.
+/// * `--name NAME`: copy of the data.
+/// * `--k K`: neighbors listed per word with `csv` (default 100).
+/// * `--out FILE`: destination (default `results/NAMESPACE/KIND.EXT`).
+fn export (namespace: &str, args: &[String]) {
+	let format: export::Format = match opt::<String>(args, "--format") {
+		Some(f) => match f.parse() {
+			Ok(f) => f,
+			Err(_) => {
+				error!("Unknown format {}.", f);
+				return;
+			}
+		},
+		None => export::Format::Word2Vec
+	};
+
+	let kind: String = opt(args, "--from").unwrap_or_else(|| String::from("embedding"));
+
+	let sim = match source(namespace, &kind, opt::<String>(args, "--name").as_deref()) {
+		Some(s) => s.load().unwrap(),
+		None => {
+			error!("Unknown source {}.", kind);
+			return;
+		}
+	};
+
+	let out: PathBuf = opt(args, "--out")
+		.unwrap_or_else(|| Path::new("results").join(namespace).join(format!("{kind}.{}", format.extension())));
+
+	info!("Exporting {} words to {}.", sim.dict().len(), out.display());
+
+	let start = Instant::now();
+
+	match export::export(sim.as_ref(), format, &out, opt(args, "--k").unwrap_or(100)) {
+		Ok(()) => info!("Exported in {:.1}s.", start.elapsed().as_secs_f64()),
+		Err(e) => error!("Could not export to {}: {}", out.display(), e)
+	}
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main () {
+	log4rs::init_file("log/config.yaml", Default::default()).unwrap();
+
+	info!("Initiated Logger");
+
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	let namespace: String = opt(&args, "--namespace").unwrap_or_else(|| String::from("frequency"));
+
+	match args.first().map(|e| e.as_str()) {
+		Some("stats") => return stats(&namespace, &args[1..]),
+		Some("audit") => return audit(&args[1..]),
+		Some("convert") => return convert(&namespace, &args[1..]),
+		Some("bench") => return bench(&namespace, &args[1..]),
+		Some("embed") => return embed(&namespace, &args[1..]),
+		Some("import") => return import(&namespace, &args[1..]),
+		Some("topk") => return topk(&namespace, &args[1..]),
+		Some("hnsw") => return hnsw(&namespace, &args[1..]),
+		Some("neighbors") => return neighbors(&namespace, &args[1..]),
+		Some("query") => return query(&namespace, &args[1..]),
+		Some("export") => return export(&namespace, &args[1..]),
+		Some("cluster") => return cluster(&namespace, &args[1..]),
+		Some("drift") => return drift(&namespace, &args[1..]),
+		Some("benchmark") => return benchmark(&namespace, &args[1..]),
+		Some("bundle") => return bundle(&namespace, &args[1..]),
+		Some("inspect") => return inspect(&namespace, &args[1..]),
+		Some("second") => return second(&namespace, &args[1..]),
+		Some("serve") | None => (),
+		Some(c) => {
+			error!("Unknown command {}.", c);
+			return;
+		}
+	}
+
+	// a game can be served from another copy of the data, such as correlations built
+	// with another measure, given by `--name NAME`, or from a bundle given by `--bundle FILE`
+	let name: Option<String> = opt(&args, "--name");
+
+	let source = match opt::<String>(&args, "--source").as_deref() {
+		_ if flag(&args, "--bundle") => match opt::<PathBuf>(&args, "--bundle") {
+			Some(p) => Source::Bundle(p),
+			None => {
+				error!("Missing bundle file after --bundle.");
+				return;
+			}
+		},
+		Some("corr") | None => {
+			let dict = Arc::new(load_dict(DBDICT).unwrap());
+
+			// this will be discarded as it is already serialized
+			gen_word_frequency(&namespace, name.as_deref(), &dict, 0, &config(&args)).await;
+
+			Source::Correlation(files(&namespace, "corr", name.as_deref()).1)
+		},
+		Some(s) => match source(&namespace, s, name.as_deref()) {
+			Some(s) => s,
+			None => {
+				error!("Unknown source {}.", s);
+				return;
+			}
+		}
+	};
+
+	let srv = Server::open("", source).unwrap();
+	let conf = Config::build(Environment::Development)
+		.address("127.0.0.1")
+    .port(8000)
+    .log_level(LoggingLevel::Normal)
+    .unwrap();
+
+	let app = rocket::custom(conf);
+
+	error!("Launch failed on {}", srv.mount("/", app).launch());
+}
+
+
+#[cfg(test)]
+mod test {
+	use regex::Regex;
+	use bzip2::bufread::BzDecoder;
+	use std::io::{Read, BufRead};
+	use super::*;
+
+	const EPSILON: f64 = 1e-12;
+
+	/// Dictionary of `words` indexed in order.
+	fn dict (words: &[&str]) -> Dict {
+		words.iter()
+			.enumerate()
+			.map(|(i, w)| (String::from(*w), i as u32))
+			.collect()
+	}
+
+	/// Six words counted over 8 articles, shared by the tests of similarity sources.
+	fn farm () -> (Dict, HashMap<u32, Vec<(u32, u16)>>) {
+		let dict = dict(&["cow", "moo", "pig", "oink", "farm", "barn"]);
+		let dat = HashMap::from([
+			(0, vec![(0, 3), (1, 1), (4, 2), (7, 1)]),
+			(1, vec![(0, 1), (1, 2), (3, 1)]),
+			(2, vec![(2, 4), (4, 1), (5, 1)]),
+			(3, vec![(2, 2), (3, 1), (4, 5), (5, 3), (6, 1)]),
+			(4, vec![(1, 1), (5, 2)]),
+			(5, vec![(0, 1), (1, 1), (2, 1), (3, 1), (4, 1), (5, 1), (6, 1), (7, 2)]),
+		]);
+
+		(dict, dat)
+	}
+	
+	#[test]
+	/// Test database serialization and deserialization.
+	fn database_serialize_deserialize() {
+		let db = File::open(format!("{}.bz2", DBDATA)).unwrap();
+
+		// db.seek(SeekFrom::Start(ind[ARTICLEID].0)).unwrap();
+
+		// db.seek(SeekFrom::Start(2369839923)).unwrap();
+
+		let db = BufReader::new(db);
+		let db = MultiBzDecoder::new(db);
+		// let db = File::open(DBDATA).unwrap();
+		let db = BufReader::new(db);
+		let db = Database::new(db);
+
+		let mut a = db.into_iter();
+
+		let dict = Arc::new(load_dict(DBDICT).unwrap());
+
+		let mut fa = Frequency::new("results/frequency.dat", Arc::clone(&dict)).unwrap();
+
+		let mut c = 0;
+		while let Some(e) = a.next() {
+			fa.insert(e.unwrap().text).unwrap();
+
+			c += 1;
+			info!(target: "app::basic", "Parsed article {}", c);
+
+			if c % 1000 == 0 {
+				println!("Parsed article {}", c);
+			}
+
+			// if c > 100_000 { break; }
+		}
+
+		let fon = "results";
+		std::fs::create_dir_all(fon).unwrap();
+		let fon = &format!("{}/frequency-index.dat", fon);
+
+		let fw = BufWriter::new(File::create(fon).unwrap());
+
+		bincode::serialize_into(fw, &fa).unwrap();
+
+		let fr = File::open(fon).unwrap();
+
+		// Deserialize from the file.
+		let mut fad = bincode::deserialize_from(fr).unwrap();
+
+		// Assert that the data of both databases are equal.
+		assert_eq!(fa, fad);
+		// not writable
+		assert_eq!(
+			fad.insert(String::from("")),
+			Err(database::error::ErrorKind::MissingDict.into())
+		);
+
+		fad.set_dict(dict);
+		// now it should be writable
+		assert_eq!(fad.insert(String::from("")), Ok( () ));
+	}
+
+	#[test]
+	/// Command line options default only when absent, a typo being an error naming the option
+	fn options () {
+		let args: Vec<String> = ["--precision", "f16", "--layout", "ful", "--top", "3", "--top", "4", "--measure"]
+			.iter()
+			.map(|a| a.to_string())
+			.collect();
+
+		assert_eq!(parse::<Precision>(&args, "--precision"), Ok(vec![Precision::F16]));
+		assert_eq!(parse::<Layout>(&args, "--layout"), Err(String::from("Invalid value ful for --layout.")));
+		assert_eq!(parse::<usize>(&args, "--top"), Ok(vec![3, 4]));
+		assert_eq!(parse::<Measure>(&args, "--measure"), Err(String::from("Missing value for --measure.")));
+		assert_eq!(parse::<usize>(&args, "--threads"), Ok(vec![]));
+		assert_eq!(opt::<Precision>(&args, "--threads"), None);
+	}
+
+	#[test]
+	/// Merge shards filled on separate threads.
+	fn merge () {
+		let dict: Arc<Dict> = Arc::new(HashMap::from([
+			(String::from("cow"), 0),
+			(String::from("moo"), 1),
+			(String::from("pig"), 2),
+		]));
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let fa = Frequency::new("results/_test/data.0.dat", Arc::clone(&dict)).unwrap();
+		let fb = fa.shard("results/_test/data.1.dat").unwrap();
+
+		let insert = |mut f: Frequency, articles: Vec<&'static str>| thread::spawn(move || {
+			for a in articles {
+				f.insert(a.to_owned()).unwrap();
+			}
+
+			f
+		});
+
+		let fa = insert(fa, vec!["cow moo moo", "pig"]);
+		let fb = insert(fb, vec!["moo", "pig pig cow"]);
+
+		let mut fm = Frequency::merge(
+			"results/_test/data.dat",
+			vec![fa.join().unwrap(), fb.join().unwrap()]
+		).unwrap();
+
+		assert_eq!(fm.len(), 4);
+
+		let mut dat = fm.load().unwrap();
+		for v in dat.values_mut() {
+			v.sort();
+		}
+
+		assert_eq!(dat[&0], vec![(0, 1), (3, 1)]);
+		assert_eq!(dat[&1], vec![(0, 2), (2, 1)]);
+		assert_eq!(dat[&2], vec![(1, 1), (3, 2)]);
+
+		// still writable after the merge
+		assert_eq!(fm.insert(String::from("cow")), Ok( () ));
+
+		// shards of another dictionary would mix up word ids
+		let other: Arc<Dict> = Arc::new(HashMap::from([(String::from("pig"), 0)]));
+		let fc = Frequency::new("results/_test/data.2.dat", Arc::new((*dict).clone())).unwrap();
+		let fd = Frequency::new("results/_test/data.3.dat", other).unwrap();
+		let fm = Frequency::merge("results/_test/data.4.dat", vec![fm, fc]).unwrap();
+
+		assert_eq!(
+			Frequency::merge("results/_test/data.5.dat", vec![fm, fd]).err().map(|e| *e),
+			Some(database::error::ErrorKind::Format)
+		);
+
+		// merging into a shard, however it is named, would truncate it first
+		let mut fe = Frequency::new("results/_test/data.6.dat", Arc::clone(&dict)).unwrap();
+		fe.insert(String::from("cow moo")).unwrap();
+		fe.flush().unwrap();
+		let len = std::fs::metadata("results/_test/data.6.dat").unwrap().len();
+
+		assert_eq!(
+			Frequency::merge("results/_test/../_test/data.6.dat", vec![fe]).err().map(|e| *e),
+			Some(database::error::ErrorKind::Io)
+		);
+		assert_eq!(std::fs::metadata("results/_test/data.6.dat").unwrap().len(), len);
+
+		// a table read back from disk has no dictionary to check against the others
+		let mut fg = Frequency::new("results/_test/data.7.dat", Arc::clone(&dict)).unwrap();
+		fg.insert(String::from("pig")).unwrap();
+		fg.flush().unwrap();
+		let read: Frequency = bincode::deserialize(&bincode::serialize(&fg).unwrap()).unwrap();
+		let fh = Frequency::new("results/_test/data.8.dat", Arc::clone(&dict)).unwrap();
+
+		assert_eq!(
+			Frequency::merge("results/_test/data.9.dat", vec![fh, read]).err().map(|e| *e),
+			Some(database::error::ErrorKind::Format)
+		);
+	}
+
+	#[test]
+	/// Shards dealt articles in turn are interleaved back into the order they were dealt in.
+	fn interleave () {
+		let dict: Arc<Dict> = Arc::new(HashMap::from([
+			(String::from("cow"), 0),
+			(String::from("moo"), 1),
+			(String::from("pig"), 2),
+		]));
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let articles = ["cow", "moo", "pig", "cow cow", "moo moo"];
+		let mut shards: Vec<Frequency> = (0..2)
+			.map(|i| Frequency::new(format!("results/_test/data.interleave.{i}.dat"), Arc::clone(&dict)).unwrap())
+			.collect();
+
+		for (c, a) in articles.iter().enumerate() {
+			shards[c % 2].insert(a.to_string()).unwrap();
+		}
+
+		let mut fm = Frequency::interleave("results/_test/data.interleave.dat", shards).unwrap();
+		let dat = fm.load().unwrap();
+
+		assert_eq!(dat[&0], vec![(0, 1), (3, 2)]);
+		assert_eq!(dat[&1], vec![(1, 1), (4, 2)]);
+		assert_eq!(dat[&2], vec![(2, 1)]);
+
+		// a later shard holding more articles than an earlier one was not dealt in turn
+		let fa = Frequency::new("results/_test/data.interleave.2.dat", Arc::clone(&dict)).unwrap();
+		let mut fb = fa.shard("results/_test/data.interleave.3.dat").unwrap();
+		fb.insert(String::from("cow")).unwrap();
+
+		assert_eq!(
+			Frequency::interleave("results/_test/data.interleave.4.dat", vec![fa, fb]).err().map(|e| *e),
+			Some(database::error::ErrorKind::Format)
+		);
+	}
+
+	#[test]
+	/// Corpus statistics of a small table, whose counts follow Zipf's law exactly
+	fn stats () {
+		use wikidle::database::stats::{WordStats, Lengths, Coverage};
+
+		let dict: Dict = HashMap::from([
+			(String::from("cow"), 0),
+			(String::from("moo"), 1),
+			(String::from("pig"), 2),
+			(String::from("farm"), 3),
+		]);
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let mut fa = Frequency::new("results/_test/stats.dat", Arc::new(dict.clone())).unwrap();
+
+		for a in ["cow moo moo", "pig", "moo moo moo", "pig pig cow", "moo the", "the"] {
+			fa.insert(a.to_owned()).unwrap();
+		}
+
+		let dat = fa.load().unwrap();
+		let s = Stats::new(&dat, fa.len(), &dict, 2);
+
+		assert_eq!(s.articles, 6);
+		assert_eq!(s.tokens, 11);
+		assert_eq!(s.coverage, Coverage { dict: 4, seen: 3, ratio: 0.75 });
+		assert_eq!(s.words, vec![
+			WordStats { word: String::from("moo"), cf: 6, df: 3 },
+			WordStats { word: String::from("pig"), cf: 3, df: 2 },
+			WordStats { word: String::from("cow"), cf: 2, df: 2 },
+		]);
+
+		// lengths 3, 1, 3, 3, 1 and 0 in buckets [0], [1, 2) and [2, 4)
+		assert_eq!(s.lengths, Lengths { min: 0, max: 3, mean: 11. / 6., median: 3, histogram: vec![(0, 1), (1, 2), (2, 3)] });
+
+		// cf = 6 / rank
+		assert!((s.zipf.exponent - 1.).abs() < 1e-9);
+		assert!((s.zipf.intercept - 6f64.ln()).abs() < 1e-9);
+		assert!((s.zipf.r2 - 1.).abs() < 1e-9);
+
+		let report = s.to_string();
+
+		assert!(report.contains("Coverage:   3 of 4 dictionary words (75.00%)"));
+		assert!(report.contains("Top 2 words:"));
+		assert!(!report.contains(" cow "));
+	}
+
+	#[test]
+	/// Missed tokens are classified by cause, and unused dictionary words are reported
+	fn audit () {
+		use std::collections::BTreeMap;
+		use wikidle::database::audit::Cause;
+
+		let dict: Arc<Dict> = Arc::new(HashMap::from([
+			(String::from("cow"), 0),
+			(String::from("moo"), 1),
+			(String::from("pig"), 2),
+			(String::from("farm"), 3),
+		]));
+
+		let cases = [
+			("café moo", Some((Cause::NonAscii, "café"))),
+			("MP3 moo", Some((Cause::Alphanumeric, "mp3"))),
+			("cow-goat", Some((Cause::Hyphenated, "goat"))),
+			("geese' moo", Some((Cause::Possessive, "geese"))),
+			("Goat moo", Some((Cause::Unknown, "goat"))),
+			("Cow's 42 moo.", None),
+		];
+
+		let mut all = Audit::new(Arc::clone(&dict));
+
+		for (article, cause) in cases {
+			let mut audit = Audit::new(Arc::clone(&dict));
+
+			audit.insert(article);
+			all.insert(article);
+
+			let r = audit.report(10);
+			let missed: BTreeMap<Cause, Vec<(String, u64)>> = cause.into_iter()
+				.map(|(c, w)| (c, vec![(w.to_owned(), 1)]))
+				.collect();
+
+			assert_eq!(r.missed, missed, "{article}");
+			assert_eq!(r.misses, missed.len() as u64, "{article}");
+			assert_eq!(r.hits, 1 + article.starts_with("Cow") as u64, "{article}");
+		}
+
+		let r = all.report(10);
+
+		assert_eq!(r.articles, 6);
+		assert_eq!(r.hits, 7);
+		assert_eq!(r.misses, 5);
+		assert_eq!(r.unused, vec!["farm", "pig"]);
+		assert_eq!(r.causes.values().collect::<Vec<_>>(), vec![&1; 5]);
+		assert_eq!(r.missed[&Cause::Unknown], vec![(String::from("goat"), 1)]);
+	}
+
+	#[test]
+	/// Load the index file into memory.
+	fn index_read () {
+		let indexmap: Regex = Regex::new(r"^(\d+):(\d+):(.+)$").unwrap();
+
+		let ind = File::open(format!("{}.bz2", DBINDEX)).unwrap();
+		let ind = BufReader::new(ind);
+		let ind = BzDecoder::new(ind);
+		let ind = BufReader::new(ind);
+
+		let ind: Vec<(u64, u32, String)> = ind.lines()
+			.map(|e| e.unwrap())
+			.map(|e| {
+				let s = indexmap.captures_iter(&e).next().unwrap();
+
+				(
+					s[1].parse::<u64>().unwrap(), 
+					s[2].parse::<u32>().unwrap(), 
+					s[3].to_owned()
+				)
+			})
+			.collect();
+
+		const ARTICLEID: usize = 921235 + 70611;
+
+		println!("Parsing starting at article {} id {}, {:?}, byte {}", ARTICLEID, ind[ARTICLEID].1, ind[ARTICLEID].2, ind[ARTICLEID].0);
+	}
+
+	#[test]
+	/// Test parse_wiki_text
+	fn wikitext () {
+		let mut tmp = File::open("tmp.log").unwrap();
+		let mut contents = String::new();
+		tmp.read_to_string(&mut contents).unwrap();
+
+		println!("{:?}", database::read::CONFIG.parse(&contents));
+	}
+
+	#[test]
+	/// Correlation test
+	fn corr () {
+		let dict = load_dict("data/words").unwrap();
+		let dat: HashMap<u32,Vec<(u32,u16)>> = HashMap::from([
+			(0, vec![(0, 1), (1, 1), (9, 2)]),
+			(1, vec![(0, 1), (1, 1), (9, 2)]),
+			(2, vec![(9, 1)]),
+			(3, vec![(5, 1)])
+		]);
+
+		let mut c = Correlation::new(dat, 10, "results/_test/corr.dat", &dict).unwrap();
+
+		println!("{:?}", c.dict());
+
+		let acorn = c.corrall("a").unwrap();
+
+		let corr = |c: &mut Correlation, a:&str, b:&str, exp: f64| {
+			let co = c.corr(a,b).unwrap_or(0.);
+			println!("{}/{}: {} ({})", a, b, co, exp);
+
+			assert!((co - exp).abs() < EPSILON);
+		};
+
+		corr(&mut c, "A","A's", 1.);
+		corr(&mut c, "AMD","A", 0.804030252207);
+		corr(&mut c, "AMD's","AMD", -0.111111111111);
+
+		let i = c.index("A's").unwrap() as usize;
+		corr(&mut c, "A", "A's", acorn[i]);
+		let i = c.index("AMD").unwrap() as usize;
+		corr(&mut c, "A", "AMD", acorn[i]);
+		let i = c.index("Amd's").unwrap() as usize;
+		corr(&mut c, "A", "AMD's", acorn[i]);
+		let i = c.index("A").unwrap() as usize;
+		corr(&mut c, "A", "A", acorn[i]);
+	}
+
+	#[test]
+	/// Reduced precision storage and conversion
+	fn precision () {
+		let dict: Dict = HashMap::from([
+			(String::from("cow"), 0),
+			(String::from("moo"), 1),
+			(String::from("pig"), 2),
+			(String::from("oink"), 3),
+		]);
+		let dat = || HashMap::from([
+			(0, vec![(0, 3), (1, 1), (4, 2)]),
+			(1, vec![(0, 1), (1, 2), (3, 1)]),
+			(2, vec![(2, 4), (4, 1)]),
+			(3, vec![(2, 2), (3, 1), (4, 5)]),
+		]);
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let c = Correlation::new(dat(), 6, "results/_test/corr.precision.dat", &dict).unwrap();
+		let words = ["cow", "moo", "pig", "oink"];
+
+		let exp: HashMap<(&str, &str), f64> = words.iter()
+			.flat_map(|a| words.iter().map(move |b| (*a, *b)))
+			.map(|(a, b)| ((a, b), c.corr(a, b).unwrap()))
+			.collect();
+
+		let check = |c: &Correlation, eps: f64| {
+			for a in words.iter() {
+				let row = c.corrall(a).unwrap();
+
+				for b in words.iter() {
+					let e = exp[&(*a, *b)];
+
+					assert!((row[c.index(b).unwrap() as usize] - e).abs() <= eps, "{a}/{b}");
+					assert!((c.corr(a, b).unwrap() - e).abs() <= eps, "{a}/{b}");
+				}
+			}
+		};
+
+		for (p, eps) in [
+			(Precision::F32, 1e-7),
+			(Precision::F16, 1e-3),
+			(Precision::I16, 1. / 32767.),
+			(Precision::I8, 1. / 127.),
+		] {
+			let config = correlation::Config { precision: p, ..Default::default() };
+			let c = Correlation::with_config(dat(), 6, format!("results/_test/corr.precision.{p:?}.dat"), &dict, &config).unwrap();
+
+			assert_eq!(c.precision(), p);
+			check(&c, eps);
+		}
+
+		let config = correlation::Config { layout: Layout::Full, ..Default::default() };
+		let full = Correlation::with_config(dat(), 6, "results/_test/corr.precision.full.dat", &dict, &config).unwrap();
+
+		assert_eq!(full.layout(), Layout::Full);
+		check(&full, EPSILON);
+
+		let c = c.convert("results/_test/corr.precision.dat", Precision::I16, Layout::Full).unwrap();
+		check(&c, 1. / 32767.);
+
+		let c = c.convert("results/_test/corr.precision.dat", Precision::I16, Layout::Triangular).unwrap();
+		check(&c, 1. / 32767.);
+	}
+
+	#[test]
+	/// Every layout, converted or not, and headerless files written before layouts read the same scores
+	fn layout () {
+		let (dict, dat) = farm();
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let tri = Correlation::new(dat.clone(), 8, "results/_test/corr.layout.tri.dat", &dict).unwrap();
+		let config = correlation::Config { layout: Layout::Full, ..Default::default() };
+		let full = Correlation::with_config(dat, 8, "results/_test/corr.layout.full.dat", &dict, &config).unwrap();
+
+		let words: Vec<&String> = tri.dict().keys().collect();
+		let n = words.len() as u64;
+
+		// scores of words without variance are NaN
+		let same = |x: f64, y: f64| x == y || (x.is_nan() && y.is_nan());
+
+		let check = |c: &Correlation| {
+			for a in words.iter() {
+				let row = c.corrall(a).unwrap();
+				let exp = tri.corrall(a).unwrap();
+
+				for b in words.iter() {
+					let e = exp[tri.index(b).unwrap() as usize];
+
+					assert!(same(row[c.index(b).unwrap() as usize], e), "{:?} {a}/{b}", c.layout());
+					assert!(same(c.corr(a, b).unwrap(), e), "{:?} {a}/{b}", c.layout());
+				}
+			}
+		};
+
+		assert_eq!((tri.layout(), full.layout()), (Layout::Triangular, Layout::Full));
+		check(&full);
+
+		let c = tri.convert("results/_test/corr.layout.tri2full.dat", Precision::F64, Layout::Full).unwrap();
+		assert_eq!(c.layout(), Layout::Full);
+		check(&c);
+
+		let c = full.convert("results/_test/corr.layout.full2tri.dat", Precision::F64, Layout::Triangular).unwrap();
+		assert_eq!(c.layout(), Layout::Triangular);
+		check(&c);
+
+		// f64 cells of the lower triangle, without a header nor a diagonal
+		let mut cells: Vec<f64> = vec![0.; Layout::Triangular.cells(n) as usize];
+
+		for a in words.iter() {
+			for b in words.iter() {
+				let (i, j) = (tri.index(a).unwrap() as u64, tri.index(b).unwrap() as u64);
+
+				if i > j {
+					cells[Layout::Triangular.find(i, j, n).unwrap() as usize] = tri.corr(a, b).unwrap();
+				}
+			}
+		}
+
+		let mut bytes: Vec<u8> = Vec::new();
+
+		for v in cells {
+			Precision::F64.encode(v, &mut bytes);
+		}
+
+		std::fs::write("results/_test/corr.layout.legacy.dat", bytes).unwrap();
+
+		let index = bincode::serialize(&(Path::new("results/_test/corr.layout.legacy.dat"), tri.dict(), tri.pruned())).unwrap();
+		let legacy: Correlation = bincode::deserialize(&index).unwrap();
+
+		assert_eq!((legacy.layout(), legacy.precision()), (Layout::Triangular, Precision::F64));
+
+		for a in words.iter() {
+			let row = legacy.corrall(a).unwrap();
+
+			for b in words.iter() {
+				let (got, e) = (row[legacy.index(b).unwrap() as usize], tri.corr(a, b).unwrap());
+
+				// older files have no diagonal, a word scoring 1 with itself
+				match a == b {
+					true => assert_eq!(got, 1.),
+					false => assert!(same(got, e), "legacy {a}/{b}"),
+				}
+
+				assert!(same(legacy.corr(a, b).unwrap(), got), "legacy {a}/{b}");
+			}
+		}
+	}
+
+	#[test]
+	/// Matrices of another dictionary, cut short, or headerless of the wrong size are refused
+	fn mismatched () {
+		let (dict, dat) = farm();
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let tri = Correlation::new(dat, 8, "results/_test/corr.mismatched.dat", &dict).unwrap();
+		let load = |fname: &str, dict: &Dict| {
+			let index = bincode::serialize(&(Path::new(fname), dict, tri.pruned())).unwrap();
+			bincode::deserialize::<Correlation>(&index)
+		};
+
+		assert!(load("results/_test/corr.mismatched.dat", tri.dict()).is_ok());
+
+		let mut fewer = tri.dict().clone();
+		let last = fewer.iter().max_by_key(|e| *e.1).map(|e| e.0.clone()).unwrap();
+		fewer.remove(&last);
+		assert!(load("results/_test/corr.mismatched.dat", &fewer).is_err());
+
+		let data = std::fs::read("results/_test/corr.mismatched.dat").unwrap();
+		std::fs::write("results/_test/corr.mismatched.cut.dat", &data[..data.len() - 8]).unwrap();
+		assert!(load("results/_test/corr.mismatched.cut.dat", tri.dict()).is_err());
+
+		// neither a header nor the size of a headerless matrix
+		let n = tri.dict().len() as u64;
+		std::fs::write("results/_test/corr.mismatched.legacy.dat", vec![0u8; (Layout::Triangular.cells(n) * 8 - 8) as usize]).unwrap();
+		assert!(load("results/_test/corr.mismatched.legacy.dat", tri.dict()).is_err());
+	}
+
+	#[test]
+	/// Words are pruned by document frequency and count, and lookups tell pruned from unknown words
+	fn prune () {
+		use wikidle::database::{correlation::Pruned, error::ErrorKind};
+
+		let dict = dict(&["cow", "moo", "pig", "oink", "farm", "barn", "the"]);
+		// document frequencies 1, 2, 3, 4, 5, none and 9 out of 10 articles
+		let dat: HashMap<u32, Vec<(u32, u16)>> = [1, 2, 3, 4, 5, 0, 9].iter()
+			.enumerate()
+			.filter(|(_, df)| **df > 0)
+			.map(|(i, df)| (i as u32, (0..*df).map(|a| (a, 1)).collect()))
+			.collect();
+
+		let pruned = |config: &correlation::Config| -> Vec<(&str, Option<Pruned>)> {
+			let (kept, pruned) = config.prune(&dat, 10, &dict);
+			// kept words are renumbered from 0
+			assert_eq!(kept.values().map(|i| *i as usize).max().map_or(0, |m| m + 1), kept.len());
+
+			["cow", "moo", "pig", "oink", "farm", "barn", "the"].into_iter()
+				.map(|w| (w, pruned.get(w).copied()))
+				.collect()
+		};
+
+		let d = correlation::Config::default();
+
+		assert_eq!(pruned(&d), vec![
+			("cow", None), ("moo", None), ("pig", None), ("oink", None), ("farm", None),
+			("barn", Some(Pruned::Unseen)), ("the", None),
+		]);
+
+		let config = correlation::Config {
+			stopwords: HashSet::from(["the".into()]),
+			min_df: 2,
+			max_df: 0.45,
+			..Default::default()
+		};
+
+		assert_eq!(pruned(&config), vec![
+			("cow", Some(Pruned::Rare)), ("moo", None), ("pig", None), ("oink", None), ("farm", Some(Pruned::Common)),
+			("barn", Some(Pruned::Unseen)), ("the", Some(Pruned::Stopword)),
+		]);
+
+		// the most frequent words are kept
+		let config = correlation::Config { top: Some(2), ..Default::default() };
+
+		assert_eq!(pruned(&config), vec![
+			("cow", Some(Pruned::Capped)), ("moo", Some(Pruned::Capped)), ("pig", Some(Pruned::Capped)), ("oink", Some(Pruned::Capped)),
+			("farm", None), ("barn", Some(Pruned::Unseen)), ("the", None),
+		]);
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let config = correlation::Config { min_df: 2, ..Default::default() };
+		let c = Correlation::with_config(dat.clone(), 10, "results/_test/corr.prune.dat", &dict, &config).unwrap();
+
+		assert!(c.lookup("Moo").is_ok());
+		assert_eq!(c.lookup("cow").map_err(|e| *e), Err(ErrorKind::PrunedWord(Pruned::Rare)));
+		assert_eq!(c.lookup("barn").map_err(|e| *e), Err(ErrorKind::PrunedWord(Pruned::Unseen)));
+		assert_eq!(c.lookup("horse").map_err(|e| *e), Err(ErrorKind::InvalidWord));
+		assert_eq!(c.lookup("co-w").map_err(|e| *e), Err(ErrorKind::InvalidWord));
+
+		// indices written before words were pruned hold only the file name and dictionary
+		let legacy = bincode::serialize(&(Path::new("results/_test/corr.prune.dat"), c.dict())).unwrap();
+		std::fs::write("results/_test/corrindex.prune.old.dat", legacy).unwrap();
+		let old: Correlation = similarity::read_index("results/_test/corrindex.prune.old.dat").unwrap();
+
+		assert_eq!(old.dict(), c.dict());
+		assert!(old.pruned().is_empty());
+
+		// a damaged list of pruned words is an error rather than an empty list
+		let damaged = bincode::serialize(&(Path::new("results/_test/corr.prune.dat"), c.dict(), HashMap::from([("cow", 99u32)]))).unwrap();
+		std::fs::write("results/_test/corrindex.prune.bad.dat", damaged).unwrap();
+		assert!(similarity::read_index::<Correlation, _>("results/_test/corrindex.prune.bad.dat").is_err());
+
+		// so is an index cut short among its pruned words, rather than an index without any
+		let index = bincode::serialize(&c).unwrap();
+		std::fs::write("results/_test/corrindex.prune.cut.dat", &index[..index.len() - 3]).unwrap();
+		assert_eq!(
+			similarity::read_index::<Correlation, _>("results/_test/corrindex.prune.cut.dat").err().map(|e| *e),
+			Some(ErrorKind::Serialization)
+		);
+		assert_eq!(old.corrall("moo"), c.corrall("moo"));
+		assert_eq!(old.lookup("cow").map_err(|e| *e), Err(ErrorKind::InvalidWord));
+	}
+
+	#[test]
+	/// Association measures against dense computations over the same counts
+	fn measures () {
+		use wikidle::database::error::ErrorKind;
+
+		let len = 8;
+		let (dict, dat) = farm();
+
+		let counts: HashMap<&str, Vec<f64>> = dict.iter()
+			.map(|(w, i)| {
+				let mut v = vec![0.; len];
+				for (a, c) in dat[i].iter() {
+					v[*a as usize] = *c as f64;
+				}
+				(w.as_str(), v)
+			})
+			.collect();
+
+		// dense formulas, occurrence measures using the 2x2 table of articles
+		let expect = |m: Measure, a: &[f64], b: &[f64]| -> f64 {
+			let n = len as f64;
+			let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+			let na = a.iter().filter(|x| **x > 0.).count() as f64;
+			let nb = b.iter().filter(|y| **y > 0.).count() as f64;
+			let k = a.iter().zip(b).filter(|(x, y)| **x > 0. && **y > 0.).count() as f64;
+			let pmi = (k * n / (na * nb)).ln();
+
+			match m {
+				Measure::Pearson => {
+					let (ma, mb) = (a.iter().sum::<f64>() / n, b.iter().sum::<f64>() / n);
+					let num: f64 = a.iter().zip(b).map(|(x, y)| (x - ma) * (y - mb)).sum();
+					let da: f64 = a.iter().map(|x| (x - ma) * (x - ma)).sum();
+					let db: f64 = b.iter().map(|y| (y - mb) * (y - mb)).sum();
+					num / (da * db).sqrt()
+				},
+				Measure::Cosine => dot / (a.iter().map(|x| x * x).sum::<f64>() * b.iter().map(|y| y * y).sum::<f64>()).sqrt(),
+				Measure::Jaccard => k / (na + nb - k),
+				Measure::Dice => 2. * k / (na + nb),
+				Measure::Ppmi => if k == 0. { 0. } else { pmi.max(0.) },
+				Measure::Npmi => if k == 0. { -1. } else if k == n { 1. } else { pmi / -(k / n).ln() },
+				Measure::Llr => {
+					let cells = [(k, na, nb), (na - k, na, n - nb), (nb - k, n - na, nb), (n - na - nb + k, n - na, n - nb)];
+					let g2: f64 = 2. * cells.iter()
+						.filter(|(k, _, _)| *k > 0.)
+						.map(|(k, r, c)| k * (k * n / (r * c)).ln())
+						.sum::<f64>();
+					if k * n < na * nb { -g2 } else { g2 }
+				},
+				Measure::SecondOrder => unreachable!(),
+			}
+		};
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		for m in [Measure::Pearson, Measure::Ppmi, Measure::Npmi, Measure::Cosine, Measure::Jaccard, Measure::Dice, Measure::Llr] {
+			let config = correlation::Config { measure: m, ..Default::default() };
+			let c = Correlation::with_config(dat.clone(), len, format!("results/_test/corr.{m:?}.dat"), &dict, &config).unwrap();
+
+			assert_eq!(c.measure(), m);
+
+			if m == Measure::Ppmi || m == Measure::Llr {
+				for p in [Precision::F16, Precision::I8] {
+					let q = c.convert(format!("results/_test/corr.{m:?}.{p:?}.dat"), p, Layout::Triangular);
+					assert_eq!(q.map_err(|e| *e).err(), Some(ErrorKind::Unbounded), "{m:?} {p:?}");
+				}
+			}
+
+			for a in dict.keys() {
+				let row = c.corrall(a).unwrap();
+				let own = c.corr(a, a).unwrap();
+
+				assert!(row.iter().all(|v| *v <= own + EPSILON), "{m:?} {a}");
+
+				for b in dict.keys().filter(|b| *b != a) {
+					let exp = expect(m, &counts[a.as_str()], &counts[b.as_str()]);
+
+					assert!((row[c.index(b).unwrap() as usize] - exp).abs() < 1e-9, "{m:?} {a}/{b}");
+				}
+			}
+		}
+
+		// G² grows with the corpus beyond the largest f16
+		for (m, p) in [(Measure::Ppmi, Precision::I16), (Measure::Llr, Precision::F16)] {
+			let config = correlation::Config { measure: m, precision: p, ..Default::default() };
+			assert_eq!(
+				Correlation::with_config(dat.clone(), len, "results/_test/corr.quantized.dat", &dict, &config).err().map(|e| *e),
+				Some(ErrorKind::Unbounded)
+			);
+		}
+
+		let config = correlation::Config { measure: Measure::Npmi, precision: Precision::I8, ..Default::default() };
+		assert!(Correlation::with_config(dat, len, "results/_test/corr.Npmi.I8.dat", &dict, &config).is_ok());
+	}
+
+	#[test]
+	/// Resuming an interrupted correlation build
+	fn resume () {
+		use wikidle::database::{build::{Checkpoint, Csr, Marginals}, matrix::{Header, Layout}, measure::Measure};
+
+		let dict = dict(&["cow", "moo", "pig", "oink", "farm"]);
+		let dat = || HashMap::from([
+			(0, vec![(0, 3), (1, 1), (4, 2)]),
+			(1, vec![(0, 1), (1, 2), (3, 1)]),
+			(2, vec![(2, 4), (4, 1)]),
+			(3, vec![(2, 2), (3, 1), (4, 5)]),
+			(4, vec![(1, 1), (5, 2)]),
+		]);
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let fname = "results/_test/corr.resume.dat";
+		let c = Correlation::new(dat(), 6, fname, &dict).unwrap();
+		let exp: Vec<Vec<f64>> = dict.keys().map(|w| c.corrall(w).unwrap()).collect();
+		let index = c.dict().clone();
+		drop(c);
+
+		// cut the file after 3 complete rows, leaving part of the next one
+		let header = Header::new(Precision::F64, Layout::Triangular, Measure::Pearson, 5);
+		let f = std::fs::OpenOptions::new().write(true).open(fname).unwrap();
+		f.set_len(header.pos(Layout::Triangular.cells(3)) + 5).unwrap();
+		drop(f);
+
+		let mut words: Vec<(&String, &u32)> = index.iter().collect();
+		words.sort_by_key(|e| *e.1);
+		let words: Vec<u32> = words.into_iter().map(|(k, _)| dict[k]).collect();
+
+		let checkpoint = Checkpoint {
+			len: 6,
+			precision: Precision::F64,
+			measure: Measure::Pearson,
+			shrink: None,
+			marginals: Marginals::new(&Csr::new(&dat(), &words, 6)),
+			dict: index,
+			pruned: HashMap::new(),
+			rows: 3,
+		};
+		checkpoint.save(Path::new(fname).with_extension("ckpt")).unwrap();
+
+		let c = Correlation::new(dat(), 6, fname, &dict).unwrap();
+
+		for (w, e) in dict.keys().zip(exp.iter()) {
+			assert_eq!(&c.corrall(w).unwrap(), e, "{w}");
+		}
+
+		assert!(!Path::new(fname).with_extension("ckpt").exists());
+		drop(c);
+
+		// a file shorter than its checkpoint is rebuilt rather than padded
+		let f = std::fs::OpenOptions::new().write(true).open(fname).unwrap();
+		f.set_len(header.pos(Layout::Triangular.cells(2))).unwrap();
+		drop(f);
+
+		checkpoint.save(Path::new(fname).with_extension("ckpt")).unwrap();
+
+		let c = Correlation::new(dat(), 6, fname, &dict).unwrap();
+
+		for (w, e) in dict.keys().zip(exp.iter()) {
+			assert_eq!(&c.corrall(w).unwrap(), e, "{w}");
+		}
+	}
+
+	#[test]
+	/// Support counts, significance and shrinkage of low support pairs
+	fn support () {
+		use wikidle::database::support;
+
+		let len = 12;
+		let dict = dict(&["cow", "moo", "pig", "oink", "farm"]);
+		let dat = HashMap::from([
+			(0, vec![(0, 3), (1, 1), (4, 2), (7, 1), (9, 2)]),
+			(1, vec![(0, 1), (1, 2), (3, 1), (9, 1), (11, 3)]),
+			(2, vec![(2, 4), (4, 1), (5, 1)]),
+			(3, vec![(2, 2), (5, 1), (6, 1), (8, 2)]),
+			(4, vec![(10, 1)]),
+		]);
+
+		// upper tail of the hypergeometric distribution, summed exactly
+		let choose = |n: usize, k: usize| (0..k).fold(1., |c, i| c * (n - i) as f64 / (i + 1) as f64);
+		let tail = |co: usize, a: usize, b: usize| (co..=a.min(b))
+			.filter(|x| b - x <= len - a)
+			.map(|x| choose(a, x) * choose(len - a, b - x) / choose(len, b))
+			.sum::<f64>();
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let c = Correlation::new(dat.clone(), len, "results/_test/corr.support.dat", &dict).unwrap();
+
+		for (a, i) in dict.iter() {
+			for (b, j) in dict.iter() {
+				let (x, y) = (&dat[i], &dat[j]);
+				let co = x.iter().filter(|(p, _)| y.iter().any(|(q, _)| p == q)).count();
+				let s = c.support(a, b).unwrap();
+				let r = c.corr(a, b).unwrap();
+
+				assert_eq!(s.co as usize, co, "{a}/{b}");
+				assert!((s.p - tail(co, x.len(), y.len()).min(1.)).abs() < 1e-9, "{a}/{b}: {}", s.p);
+
+				let (lo, hi) = s.interval.unwrap();
+				assert!(lo <= r + EPSILON && r <= hi + EPSILON, "{a}/{b}");
+			}
+		}
+
+		// the interval is as wide as the number of articles allows
+		let (lo, hi) = support::interval(0., len);
+		assert!((hi - (1.96 / (len as f64 - 3.).sqrt()).tanh()).abs() < 1e-4 && (lo + hi).abs() < EPSILON);
+		assert_eq!(support::interval(0.5, 3), (-1., 1.));
+
+		// two words seen once, together, out of a million articles
+		assert!((support::tail(1, 1, 1, 1_000_000) - 1e-6).abs() < 1e-15);
+		assert!((support::tail(200, 500, 500, 1000) - (1. - 7.309255102802427e-11)).abs() < 1e-15);
+
+		// offsets that decrease or point past the articles are refused rather than sliced
+		let data = std::fs::read("results/_test/corr.support.support").unwrap();
+		let n = c.dict().len();
+		let offset = |i: usize| 16 + i * 8;
+		assert!(support::Postings::open("results/_test/corr.support.support", n).is_ok());
+
+		let mut decreasing = data.clone();
+		decreasing[offset(1)..offset(1) + 8].copy_from_slice(&u64::MAX.to_be_bytes());
+		let mut past = data.clone();
+		past[offset(n)..offset(n) + 8].copy_from_slice(&(u64::MAX / 2).to_be_bytes());
+
+		for d in [decreasing, past] {
+			std::fs::write("results/_test/corr.support.damaged.support", d).unwrap();
+			assert!(support::Postings::open("results/_test/corr.support.damaged.support", n).is_err());
+		}
+
+		let config = correlation::Config { shrink: Some(2.), support: false, ..Default::default() };
+		let s = Correlation::with_config(dat, len, "results/_test/corr.shrink.dat", &dict, &config).unwrap();
+
+		assert!(s.support("cow", "moo").is_none());
+
+		for a in dict.keys() {
+			for b in dict.keys().filter(|b| *b != a) {
+				let co = c.support(a, b).unwrap().co as f64;
+
+				assert!((s.corr(a, b).unwrap() - c.corr(a, b).unwrap() * co / (co + 2.)).abs() < EPSILON, "{a}/{b}");
+			}
+
+			assert_eq!(s.corr(a, a), Some(1.));
+		}
+	}
+
+	#[test]
+	/// Filtered neighbors keep their rank among all words
+	fn neighbors () {
+		use wikidle::database::{error::ErrorKind, similarity::Similarity};
+
+		let (dict, dat) = farm();
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let c = Correlation::new(dat, 8, "results/_test/corr.neighbors.dat", &dict).unwrap();
+
+		let mut exp: Vec<(String, f64)> = dict.keys()
+			.map(|w| (w.clone(), c.corr("cow", w).unwrap()))
+			.collect();
+		exp.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+		let all = c.neighbors("cow", 10, &Filter::default()).unwrap();
+
+		assert_eq!(all.len(), 5);
+		for (n, (w, v)) in all.iter().zip(exp.iter().skip(1)) {
+			assert_eq!((&n.word, n.score), (w, *v));
+		}
+		assert!(all.iter().enumerate().all(|(i, n)| n.rank == i + 1));
+
+		let two = c.neighbors("cow", 2, &Filter::default()).unwrap();
+		assert_eq!(two[..], all[..2]);
+
+		// ranks stay those among all words
+		let only = Filter { words: Some(HashSet::from([all[3].word.clone(), String::from("cow")])), min_df: None };
+		assert_eq!(c.neighbors("cow", 10, &only).unwrap(), vec![all[3].clone()]);
+
+		let common = Filter { words: None, min_df: Some(3) };
+		let found = c.neighbors("cow", 10, &common).unwrap();
+		assert_eq!(found, all.iter().filter(|n| ["moo", "pig", "oink", "barn"].contains(&n.word.as_str())).cloned().collect::<Vec<_>>());
+
+		// the server lists filtered words from its cached ranks
+		bincode::serialize_into(BufWriter::new(File::create("results/_test/corrindex.neighbors.dat").unwrap()), &c).unwrap();
+		Bundle::write(&Source::Correlation("results/_test/corrindex.neighbors.dat".into()), &["pig".into()], "results/_test/bundle.neighbors.dat").unwrap();
+		let srv = Server::open("", Source::Bundle("results/_test/bundle.neighbors.dat".into())).unwrap();
+
+		assert_eq!(srv.neighbors("cow", 10, &Filter::default()).unwrap(), all);
+		assert_eq!(srv.neighbors("cow", 10, &only).unwrap(), vec![all[3].clone()]);
+		assert_eq!(srv.neighbors("cow", 10, &common).unwrap(), found);
+		assert_eq!(srv.neighbors("cow", 1, &common).unwrap(), found[..1]);
+
+		let e = Embedding::from_vectors("results/_test/emb.neighbors.dat", dict, HashMap::new(), 1, vec![1.; 6]).unwrap();
+		assert_eq!(e.neighbors("cow", 10, &common).map_err(|e| *e), Err(ErrorKind::Unsupported));
+	}
+
+	#[test]
+	/// Scores and ranks read from many threads at once match those read from one
+	fn parallel () {
+		use wikidle::database::similarity::Rank;
+
+		type Read = Vec<(Option<f64>, Option<Vec<f64>>, Option<Rank>)>;
+
+		fn read (c: &Correlation, srv: &Server, words: &[String]) -> Read {
+			words.iter()
+				.flat_map(|a| words.iter().map(move |b| (c.corr(a, b), c.corrall(a), srv.rank(a, b))))
+				.collect()
+		}
+
+		let (dict, dat) = farm();
+		let words: Arc<Vec<String>> = Arc::new(dict.keys().cloned().collect());
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let c = Arc::new(Correlation::new(dat, 8, "results/_test/corr.parallel.dat", &dict).unwrap());
+		bincode::serialize_into(BufWriter::new(File::create("results/_test/corrindex.parallel.dat").unwrap()), &*c).unwrap();
+		Bundle::write(&Source::Correlation("results/_test/corrindex.parallel.dat".into()), &["cow".into()], "results/_test/bundle.parallel.dat").unwrap();
+
+		let open = || Arc::new(Server::open("", Source::Bundle("results/_test/bundle.parallel.dat".into())).unwrap());
+		let exp = read(&c, &open(), &words);
+
+		// a fresh server, so its caches are filled by every thread at once
+		let srv = open();
+		let handles: Vec<thread::JoinHandle<Read>> = (0..8)
+			.map(|_| {
+				let (c, srv, words) = (Arc::clone(&c), Arc::clone(&srv), Arc::clone(&words));
+				thread::spawn(move || read(&c, &srv, &words))
+			})
+			.collect();
+
+		for h in handles {
+			assert_eq!(h.join().unwrap(), exp);
+		}
+	}
+
+	#[test]
+	/// Scoring words against weighted sets of words
+	fn query () {
+		use wikidle::database::error::ErrorKind;
+
+		let (dict, dat) = farm();
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let c = Correlation::new(dat, 8, "results/_test/corr.query.dat", &dict).unwrap();
+		let r = |a: &str, b: &str| c.corr(a, b).unwrap();
+
+		let q = |aggregate| Query {
+			positive: vec![(String::from("cow"), 1.), (String::from("pig"), 2.)],
+			negative: vec![(String::from("farm"), 1.)],
+			aggregate,
+		};
+
+		let mean = q(Aggregate::Mean).scores(&c).unwrap();
+		let min = q(Aggregate::Min).scores(&c).unwrap();
+
+		for w in dict.keys() {
+			let i = c.index(w).unwrap() as usize;
+
+			let exp = (r("cow", w) + 2. * r("pig", w) - r("farm", w)) / 4.;
+			assert!((mean[i] - exp).abs() < EPSILON, "{w}");
+
+			let exp = r("cow", w).min(2. * r("pig", w)) - r("farm", w);
+			assert!((min[i] - exp).abs() < EPSILON, "{w}");
+		}
+
+		let top = q(Aggregate::Mean).top(&c, 10, &Filter::default()).unwrap();
+		let words: Vec<&str> = top.iter().map(|n| n.word.as_str()).collect();
+
+		assert_eq!(words.len(), 3);
+		assert!(!words.contains(&"cow") && !words.contains(&"pig") && !words.contains(&"farm"));
+		assert!(top.windows(2).all(|w| w[0].score >= w[1].score && w[0].rank < w[1].rank));
+
+		// pairs left out of neighbor lists count as 0 rather than -inf
+		let t = TopK::from_similarity(&c, "results/_test/topk.query.dat", 2, 1).unwrap();
+		let r = |a: &str, b: &str| t.corr(a, b).filter(|v| v.is_finite()).unwrap_or(0.);
+
+		let q = |aggregate| Query {
+			positive: vec![(String::from("cow"), 1.)],
+			negative: vec![(String::from("farm"), 1.)],
+			aggregate,
+		};
+
+		let mean = q(Aggregate::Mean).scores(&t).unwrap();
+		let min = q(Aggregate::Min).scores(&t).unwrap();
+
+		for w in dict.keys() {
+			let i = t.index(w).unwrap() as usize;
+
+			assert!((mean[i] - (r("cow", w) - r("farm", w)) / 2.).abs() < EPSILON, "{w}");
+			assert!((min[i] - (r("cow", w) - r("farm", w))).abs() < EPSILON, "{w}");
+		}
+
+		// centroid of orthogonal directions against vectors in between
+		let e = Embedding::from_vectors("results/_test/emb.query.dat", dict, HashMap::new(), 2, vec![
+			1., 0.,
+			0., 1.,
+			1., 1.,
+			1., -1.,
+			2., 0.,
+			0., 0.,
+		]).unwrap();
+
+		let q = Query {
+			positive: vec![(String::from("cow"), 1.), (String::from("moo"), 1.)],
+			negative: vec![],
+			aggregate: Aggregate::Centroid,
+		};
+
+		let top = q.top(&e, 2, &Filter::default()).unwrap();
+
+		assert_eq!(top[0].word, "pig");
+		assert!((top[0].score - 1.).abs() < 1e-6);
+		assert!((q.scores(&e).unwrap()[e.index("oink").unwrap() as usize]).abs() < 1e-6);
+
+		let empty = Query { positive: vec![], negative: vec![], aggregate: Aggregate::Min };
+		assert_eq!(empty.scores(&c).map_err(|e| *e), Err(ErrorKind::Format));
+	}
+
+	#[test]
+	/// Sparse builder against a dense pearson's r computation
+	fn sparse () {
+		use rand::Rng;
+
+		let words = 26;
+		let len = 60;
+
+		let name = |i: usize| format!("w{}", (b'a' + i as u8) as char);
+
+		let dict: Dict = (0..words)
+			.map(|i| (name(i), i as u32))
+			.collect();
+
+		let mut rng = SmallRng::seed_from_u64(7);
+		let dense: Vec<Vec<f64>> = (0..words)
+			.map(|_| (0..len)
+				.map(|_| match rng.gen_range(0..4) {
+					0 => rng.gen_range(1..20) as f64,
+					_ => 0.
+				})
+				.collect())
+			.collect();
+
+		let dat: HashMap<u32, Vec<(u32, u16)>> = dense.iter()
+			.enumerate()
+			.map(|(i, r)| (i as u32, r.iter()
+				.enumerate()
+				.filter(|(_, c)| **c > 0.)
+				.map(|(a, c)| (a as u32, *c as u16))
+				.collect()))
+			.collect();
+
+		let pearson = |a: &[f64], b: &[f64]| {
+			let (ma, mb) = (a.iter().sum::<f64>() / len as f64, b.iter().sum::<f64>() / len as f64);
+			let num: f64 = a.iter().zip(b).map(|(x, y)| (x - ma) * (y - mb)).sum();
+			let da: f64 = a.iter().map(|x| (x - ma) * (x - ma)).sum();
+			let db: f64 = b.iter().map(|y| (y - mb) * (y - mb)).sum();
+
+			num / (da * db).sqrt()
+		};
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		for threads in [1, 3] {
+			let config = correlation::Config { threads, ..Default::default() };
+			let c = Correlation::with_config(dat.clone(), len, format!("results/_test/corr.sparse{threads}.dat"), &dict, &config).unwrap();
+
+			for (a, ra) in dense.iter().enumerate() {
+				let row = c.corrall(&name(a)).unwrap();
+
+				for (b, rb) in dense.iter().enumerate() {
+					let exp = if a == b { 1. } else { pearson(ra, rb) };
+					let got = row[c.index(&name(b)).unwrap() as usize];
+
+					assert!((got - exp).abs() < EPSILON, "{a}/{b}: {got} ({exp})");
+				}
+			}
+		}
+	}
+
+	#[test]
+	/// Embedding neighbors stay within a topic and survive a reload
+	fn embedding () {
+		use rand::Rng;
+		use wikidle::database::similarity::{Similarity, Source};
+
+		let name = |i: usize| format!("w{}", (b'a' + i as u8) as char);
+		let dict: Dict = (0..12)
+			.map(|i| (name(i), i as u32))
+			.collect();
+
+		// words 0..6 only in the first 30 articles, words 6..12 only in the last 30
+		let mut rng = SmallRng::seed_from_u64(11);
+		let dat: HashMap<u32, Vec<(u32, u16)>> = (0..12u32)
+			.map(|w| {
+				let base = if w < 6 { 0 } else { 30 };
+				(w, (base..base + 30)
+					.filter_map(|a| match rng.gen_range(0..3) {
+						0 => None,
+						_ => Some((a, rng.gen_range(1..5)))
+					})
+					.collect())
+			})
+			.collect();
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let svd = Svd { dim: 4, ..Default::default() };
+		let e = Embedding::new(dat, 60, "results/_test/emb.dat", &dict, &Default::default(), &svd).unwrap();
+
+		assert_eq!(e.dim(), 4);
+
+		for i in 0..12 {
+			let a = name(i);
+			let n = e.neighbors(&a, 5, &Filter::default()).unwrap();
+
+			assert!(n.iter().all(|b| b.word != a && (dict[&b.word] < 6) == (i < 6)), "{a}: {n:?}");
+			assert!(n.windows(2).all(|w| w[0].score >= w[1].score));
+			assert!((e.corr(&a, &a).unwrap() - 1.).abs() < EPSILON);
+		}
+
+		let fname = "results/_test/embindex.dat";
+		bincode::serialize_into(BufWriter::new(File::create(fname).unwrap()), &e).unwrap();
+		let l = Source::Embedding(fname.into()).load().unwrap();
+
+		for i in 0..12 {
+			assert_eq!(l.corrall(&name(i)), e.corrall(&name(i)));
+		}
+	}
+
+	#[test]
+	/// Neighbors scored directly match those ranked from full correlation data
+	fn topk () {
+		use wikidle::database::similarity::{Rank, Similarity};
+
+		let (dict, dat) = farm();
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let c = Correlation::new(dat.clone(), 8, "results/_test/corr.topk.dat", &dict).unwrap();
+		let direct = TopK::new(dat, 8, "results/_test/topk.direct.dat", &dict, &Default::default(), 3).unwrap();
+		let ranked = TopK::from_similarity(&c, "results/_test/topk.ranked.dat", 3, 2).unwrap();
+
+		assert_eq!(direct.limit(), Some(3));
+
+		for w in dict.keys() {
+			let exp = Similarity::top(&c, w, 3).unwrap();
+			let row = direct.corrall(w).unwrap();
+
+			assert_eq!(exp[0].0, c.index(w).unwrap());
+
+			for t in [&direct, &ranked] {
+				let got = t.top(w, 5).unwrap();
+
+				assert_eq!(got.len(), 3);
+
+				for ((i, x), (j, y)) in exp.iter().zip(got) {
+					assert_eq!(*i, j, "{w}");
+					assert!((x - y).abs() < 1e-6, "{w}");
+				}
+			}
+
+			assert_eq!(row.iter().filter(|v| v.is_finite()).count(), 3);
+			assert!(direct.features(w).unwrap().iter().all(|v| v.is_finite()));
+		}
+
+		let back: TopK = bincode::deserialize(&bincode::serialize(&direct).unwrap()).unwrap();
+
+		assert_eq!(back, direct);
+		assert_eq!(back.pruned(), direct.pruned());
+
+		// indices written before words were pruned
+		let legacy = bincode::serialize(&(Path::new("results/_test/topk.direct.dat"), direct.dict())).unwrap();
+		std::fs::write("results/_test/topkindex.old.dat", legacy).unwrap();
+		let old: TopK = similarity::read_index("results/_test/topkindex.old.dat").unwrap();
+
+		assert!(old.pruned().is_empty());
+		assert_eq!(old.top("pig", 3), direct.top("pig", 3));
+
+		// neighbors outside of the dictionary are refused rather than ranked
+		let mut data = std::fs::read("results/_test/topk.direct.dat").unwrap();
+		data[16..20].copy_from_slice(&(dict.len() as u32).to_be_bytes());
+		std::fs::write("results/_test/topk.damaged.dat", data).unwrap();
+
+		let damaged = bincode::serialize(&(Path::new("results/_test/topk.damaged.dat"), direct.dict(), direct.pruned())).unwrap();
+		std::fs::write("results/_test/topkindex.damaged.dat", damaged).unwrap();
+		assert!(similarity::read_index::<TopK, _>("results/_test/topkindex.damaged.dat").is_err());
+
+		assert_eq!(serde_json::to_string(&[Rank::Within(4), Rank::Beyond(3)]).unwrap(), r#"[4,">3"]"#);
+	}
+
+	#[test]
+	/// Approximate search finds most of the exact nearest words
+	fn hnsw () {
+		use rand::Rng;
+		use wikidle::database::similarity::Similarity;
+
+		let (n, dim) = (600, 8);
+		let name = |i: usize| format!("w{}{}", (b'a' + (i / 26) as u8) as char, (b'a' + (i % 26) as u8) as char);
+
+		let dict: Dict = (0..n)
+			.map(|i| (name(i), i as u32))
+			.collect();
+
+		let mut rng = SmallRng::seed_from_u64(5);
+		let vectors: Vec<f32> = (0..n * dim)
+			.map(|_| rng.gen_range(-1.0..1.0))
+			.collect();
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let e = Embedding::from_vectors("results/_test/emb.hnsw.dat", dict, HashMap::new(), dim, vectors).unwrap();
+		let g = Hnsw::new(&e, &Default::default());
+
+		let words: Vec<String> = (0..n).step_by(7).map(name).collect();
+		let words: Vec<&str> = words.iter().map(|w| w.as_str()).collect();
+
+		let r = g.recall(&e, &words, 10, 64);
+
+		assert!(r.recall > 0.95, "{r}");
+
+		let found = g.query(&e, "wab", 10, 64).unwrap();
+
+		assert_eq!(found[0].0, e.index("wab").unwrap());
+		assert!(found.windows(2).all(|w| w[0].1 >= w[1].1));
+
+		g.save("results/_test/hnsw.dat").unwrap();
+		assert_eq!(Hnsw::load("results/_test/hnsw.dat", &e).unwrap(), g);
+
+		// graphs linking words or layers they do not hold are refused rather than searched
+		type Raw = (usize, u32, usize, Vec<Vec<Vec<u32>>>);
+		let raw: Raw = bincode::deserialize(&std::fs::read("results/_test/hnsw.dat").unwrap()).unwrap();
+		let damaged = |f: &dyn Fn(&mut Raw)| {
+			let mut r = raw.clone();
+			f(&mut r);
+			std::fs::write("results/_test/hnsw.damaged.dat", bincode::serialize(&r).unwrap()).unwrap();
+			Hnsw::load("results/_test/hnsw.damaged.dat", &e).err().map(|k| *k)
+		};
+
+		assert_eq!(damaged(&|r| r.3[0][0][0] = n as u32), Some(database::error::ErrorKind::Format));
+		assert_eq!(damaged(&|r| r.1 = n as u32), Some(database::error::ErrorKind::Format));
+		assert_eq!(damaged(&|r| r.2 += 1), Some(database::error::ErrorKind::Format));
+
+		// words without variance score NaN, stored as a positive NaN by quantized cells, yet rank last
+		let dict = self::dict(&["cow", "moo", "pig", "oink", "flat"]);
+		let dat = HashMap::from([
+			(0, vec![(0, 3), (1, 1), (3, 2)]),
+			(1, vec![(0, 1), (1, 2), (2, 1)]),
+			(2, vec![(1, 4), (2, 1), (3, 1)]),
+			(3, vec![(0, 2), (2, 1), (3, 5)]),
+			(4, vec![(0, 1), (1, 1), (2, 1), (3, 1)]),
+		]);
+
+		let config = correlation::Config { precision: Precision::I16, ..Default::default() };
+		let c = Correlation::with_config(dat, 4, "results/_test/corr.hnsw.dat", &dict, &config).unwrap();
+
+		assert!(c.corr("cow", "flat").unwrap().is_nan());
+
+		let g = Hnsw::new(&c, &hnsw::Options { m: 2, ..Default::default() });
+
+		for w in ["cow", "moo", "pig", "oink"] {
+			let found = g.query(&c, w, 5, 16).unwrap();
+			let exact = c.top(w, 5).unwrap();
+
+			assert_eq!(found.iter().map(|e| e.0).collect::<Vec<u32>>(), exact.iter().map(|e| e.0).collect::<Vec<u32>>(), "{w}");
+			assert_eq!(found.last().map(|e| e.0), c.index("flat"));
+		}
+	}
+
+	#[test]
+	/// Bundles load in one call from anywhere and refuse damaged data
+	fn bundle () {
+		use wikidle::database::{bundle::Kind, correlation::Pruned, error::ErrorKind};
+
+		let (dict, dat) = farm();
+
+		std::fs::create_dir_all("results/_test/moved").unwrap();
+
+		let config = correlation::Config { stopwords: HashSet::from(["barn".into()]), ..Default::default() };
+		let c = Correlation::with_config(dat, 8, "results/_test/corr.bundle.dat", &dict, &config).unwrap();
+		bincode::serialize_into(BufWriter::new(File::create("results/_test/corrindex.bundle.dat").unwrap()), &c).unwrap();
+
+		let answers: Vec<String> = vec!["pig".into(), "barn".into()];
+
+		let m = Bundle::write(&Source::Correlation("results/_test/corrindex.bundle.dat".into()), &answers, "results/_test/bundle.dat").unwrap();
+
+		assert_eq!((m.kind, m.words, m.answers), (Kind::Correlation, 5, 2));
+
+		// the bundle no longer needs the files it was made from
+		std::fs::rename("results/_test/bundle.dat", "results/_test/moved/bundle.dat").unwrap();
+		std::fs::remove_file("results/_test/corr.bundle.dat").unwrap();
+		std::fs::remove_file("results/_test/corr.bundle.support").unwrap();
+
+		let b = Bundle::open("results/_test/moved/bundle.dat").unwrap();
+
+		assert_eq!(b.metadata, m);
+		assert_eq!(b.answers, answers);
+		assert_eq!(b.sim.lookup("barn").err().map(|e| *e), Some(ErrorKind::PrunedWord(Pruned::Stopword)));
+
+		for w in dict.keys() {
+			assert_eq!(b.sim.corrall(w), c.corrall(w));
+			assert_eq!(b.sim.support(w, "farm"), c.support(w, "farm"));
+		}
+
+		let mut data = std::fs::read("results/_test/moved/bundle.dat").unwrap();
+		let last = data.len() - 1;
+		data[last] ^= 1;
+		std::fs::write("results/_test/moved/bundle.dat", data).unwrap();
+
+		assert_eq!(Bundle::open("results/_test/moved/bundle.dat").err().map(|e| *e), Some(ErrorKind::Checksum));
+	}
+
+	#[test]
+	/// The server refuses the stopwords the data was built without, and those of `data/stopwords` whatever the source
+	fn stopwords () {
+		use wikidle::database::{correlation::Pruned, error::ErrorKind};
+
+		let dict = dict(&["cow", "moo", "pig", "oink"]);
+		let dat = HashMap::from([
+			(0, vec![(0, 3), (1, 1), (2, 2)]),
+			(1, vec![(0, 1), (1, 2), (3, 1)]),
+			(2, vec![(2, 4), (3, 1)]),
+			(3, vec![(1, 2), (2, 1), (3, 3)]),
+		]);
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let config = correlation::Config { stopwords: HashSet::from(["oink".into()]), ..Default::default() };
+		let c = Correlation::with_config(dat, 4, "results/_test/corr.stopwords.dat", &dict, &config).unwrap();
+		bincode::serialize_into(BufWriter::new(File::create("results/_test/corrindex.stopwords.dat").unwrap()), &c).unwrap();
+
+		Bundle::write(&Source::Correlation("results/_test/corrindex.stopwords.dat".into()), &["cow".into(), "oink".into(), "barn".into()], "results/_test/bundle.stopwords.dat").unwrap();
+
+		let srv = Server::open("", Source::Bundle("results/_test/bundle.stopwords.dat".into())).unwrap();
+
+		assert_eq!(srv.lookup("oink").map_err(|e| *e), Err(ErrorKind::PrunedWord(Pruned::Stopword)));
+		assert_eq!(srv.lookup("Cow").ok(), c.index("cow"));
+
+		// listed in data/stopwords, but never part of this data
+		assert_eq!(srv.lookup("the").map_err(|e| *e), Err(ErrorKind::PrunedWord(Pruned::Stopword)));
+
+		// answers without scores would reject every guess of their day
+		assert_eq!(srv.answers(), ["cow"]);
+
+		// imported vectors know nothing of stopwords, the served list still applies
+		let e = Embedding::from_vectors("results/_test/emb.stopwords.dat", self::dict(&["cow", "moo", "the"]), HashMap::new(), 2, vec![1., 0., 0., 1., 1., 1.]).unwrap();
+		bincode::serialize_into(BufWriter::new(File::create("results/_test/embindex.stopwords.dat").unwrap()), &e).unwrap();
+		Bundle::write(&Source::Embedding("results/_test/embindex.stopwords.dat".into()), &["the".into(), "moo".into()], "results/_test/bundle.emb.stopwords.dat").unwrap();
+
+		let srv = Server::open("", Source::Bundle("results/_test/bundle.emb.stopwords.dat".into())).unwrap();
+
+		assert_eq!(srv.lookup("The").map_err(|e| *e), Err(ErrorKind::PrunedWord(Pruned::Stopword)));
+		assert_eq!(srv.lookup("moo").ok(), e.index("moo"));
+		assert_eq!(srv.answers(), ["moo"]);
+
+		// the shipped list keeps words that also name things
+		let shipped = load_words(DBSTOP).unwrap();
+
+		assert!(["the", "and", "of", "they", "were"].iter().all(|w| shipped.contains(*w)));
+		assert!(["may", "mine", "one", "well", "whole", "will", "can"].iter().all(|w| !shipped.contains(*w)));
+	}
+
+	#[test]
+	/// A bundle serves the answers of every day in the order the answer file gives them
+	fn bundle_answers () {
+		let (dict, dat) = farm();
+
+		std::fs::create_dir_all("results/_test/answers/data").unwrap();
+
+		let c = Correlation::new(dat, 8, "results/_test/corr.answers.dat", &dict).unwrap();
+		bincode::serialize_into(BufWriter::new(File::create("results/_test/corrindex.answers.dat").unwrap()), &c).unwrap();
+		drop(c);
+
+		// neither alphabetical nor free of lines that are not answers
+		std::fs::write("results/_test/answers/data/answers", "pig\nmoo\n\nfarm\ncow\nbarn\noink\n").unwrap();
+
+		let args: Vec<String> = ["--name", "answers", "--answers", "results/_test/answers/data/answers", "--to", "answers"]
+			.iter()
+			.map(|a| a.to_string())
+			.collect();
+		super::bundle("_test", &args);
+
+		let served = Server::open("results/_test/answers", Source::Correlation("results/_test/corrindex.answers.dat".into())).unwrap();
+		let bundled = Server::open("", Source::Bundle("results/_test/bundle.answers.dat".into())).unwrap();
+
+		assert_eq!(served.answers().len(), 6);
+		assert_eq!(bundled.answers(), served.answers());
+	}
+
+	#[test]
+	/// A game none of whose answers is scored is refused rather than failing on its first guess
+	fn unscored_answers () {
+		let (dict, dat) = farm();
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let c = Correlation::new(dat, 8, "results/_test/corr.unscored.dat", &dict).unwrap();
+		bincode::serialize_into(BufWriter::new(File::create("results/_test/corrindex.unscored.dat").unwrap()), &c).unwrap();
+
+		let source = Source::Correlation("results/_test/corrindex.unscored.dat".into());
+
+		Bundle::write(&source, &["horse".into(), "".into()], "results/_test/bundle.unscored.dat").unwrap();
+		Bundle::write(&source, &[], "results/_test/bundle.unanswered.dat").unwrap();
+
+		for b in ["results/_test/bundle.unscored.dat", "results/_test/bundle.unanswered.dat"] {
+			let e = Server::open("", Source::Bundle(b.into())).err().map(|e| e.kind());
+			assert_eq!(e, Some(std::io::ErrorKind::InvalidData), "{b}");
+		}
+	}
+
+	#[test]
+	/// Damaged cells are counted, listed and repaired, and rank last meanwhile
+	fn inspect () {
+		use wikidle::database::{matrix::Header, build::by_score};
+
+		let (dict, dat) = farm();
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let c = Correlation::new(dat, 8, "results/_test/corr.inspect.dat", &dict).unwrap();
+		bincode::serialize_into(BufWriter::new(File::create("results/_test/corrindex.inspect.dat").unwrap()), &c).unwrap();
+		let index = |w: &str| c.index(w).unwrap() as u64;
+		let cells = [(index("cow"), index("moo"), f64::NAN), (index("moo"), index("pig"), f64::INFINITY), (index("oink"), index("farm"), 5.)];
+		drop(c);
+
+		let options = inspect::Options { samples: vec!["cow".into()], k: 2, ..Default::default() };
+		let r = inspect::inspect("results/_test/corrindex.inspect.dat", &options).unwrap();
+
+		assert!(r.healthy(), "{r}");
+		assert_eq!((r.words, r.expected), (6, r.actual));
+		assert_eq!(r.samples[0].neighbors.len(), 2);
+
+		let mut data = std::fs::read("results/_test/corr.inspect.dat").unwrap();
+		let h = Header::read(&mut &data[..], dict.len() as u64, data.len() as u64).unwrap();
+
+		for (a, b, v) in cells {
+			let p = h.pos(h.find(a, b).unwrap()) as usize;
+			data[p..p + 8].copy_from_slice(&v.to_be_bytes());
+		}
+
+		std::fs::write("results/_test/corr.inspect.dat", &data).unwrap();
+
+		let c = load_correlation("_test", Some("inspect"));
+		let mut row: Vec<(u32, f64)> = c.corrall("cow").unwrap().into_iter().enumerate().map(|(i, v)| (i as u32, v)).collect();
+		row.sort_unstable_by(by_score);
+
+		assert_eq!(row.last().map(|e| e.0), c.index("moo"));
+
+		let options = inspect::Options { repair: Some(inspect::Policy::Lowest), ..Default::default() };
+		let r = inspect::inspect("results/_test/corrindex.inspect.dat", &options).unwrap();
+
+		// data already mapped keeps reading the file it was opened on
+		assert!(c.corr("cow", "moo").unwrap().is_nan());
+		drop(c);
+
+		assert_eq!((r.nan, r.infinite, r.out_of_range, r.repaired), (1, 1, 1, 3));
+		assert_eq!(r.nan_words, vec![("cow".to_owned(), 1), ("moo".to_owned(), 1)]);
+		assert_eq!(r.damaged.len(), 3);
+
+		let r = inspect::inspect("results/_test/corrindex.inspect.dat", &Default::default()).unwrap();
+		let c = load_correlation("_test", Some("inspect"));
+
+		assert!(r.healthy(), "{r}");
+		assert_eq!(c.corr("cow", "moo"), Some(-1.));
+		assert_eq!(c.corr("pig", "moo"), Some(-1.));
+		drop(c);
+
+		// a truncated file is reported without reading past its end
+		std::fs::write("results/_test/corr.inspect.dat", &data[..data.len() - 8]).unwrap();
+		let r = inspect::inspect("results/_test/corrindex.inspect.dat", &Default::default()).unwrap();
+
+		assert_eq!(r.actual + 8, r.expected);
+		assert!(!r.mismatches.is_empty() && r.samples.is_empty());
+	}
+
+	#[test]
+	/// Second-order scores match the correlations of rows whatever the blocks, threads and layout
+	fn second () {
+		use wikidle::database::measure::Measure;
+
+		let (dict, dat) = farm();
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let config = correlation::Config { measure: Measure::SecondOrder, ..Default::default() };
+		assert!(Correlation::with_config(dat.clone(), 8, "results/_test/corr.refused.dat", &dict, &config).is_err());
+		assert_eq!(
+			TopK::new(dat.clone(), 8, "results/_test/topk.refused.dat", &dict, &config, 3).err().map(|e| *e),
+			Some(database::error::ErrorKind::Format)
+		);
+
+		let c = Correlation::new(dat, 8, "results/_test/corr.first.dat", &dict).unwrap();
+
+		// rows without their own cell, centered
+		let row = |w: &str| -> Vec<f64> {
+			let mut r = c.corrall(w).unwrap();
+			r[c.index(w).unwrap() as usize] = 0.;
+			let mean = r.iter().sum::<f64>() / r.len() as f64;
+			r.into_iter().map(|v| v - mean).collect()
+		};
+
+		let options = second::Options { precision: Precision::F64, block: 4, threads: 3, ..Default::default() };
+		let s = second::build(&c, "results/_test/corr.second.dat", &options).unwrap();
+
+		assert_eq!(s.measure(), Measure::SecondOrder);
+
+		// building over the data being read would truncate it first
+		let len = std::fs::metadata("results/_test/corr.first.dat").unwrap().len();
+		assert_eq!(
+			second::build(&c, "results/_test/../_test/corr.first.dat", &options).err().map(|e| *e),
+			Some(database::error::ErrorKind::Io)
+		);
+		assert_eq!(std::fs::metadata("results/_test/corr.first.dat").unwrap().len(), len);
+		assert!(s.support("cow", "moo").is_none());
+
+		for a in dict.keys() {
+			let ra = row(a);
+
+			for b in dict.keys().filter(|b| *b != a) {
+				let rb = row(b);
+				let dot: f64 = ra.iter().zip(&rb).map(|(x, y)| x * y).sum();
+				let norm = (ra.iter().map(|x| x * x).sum::<f64>() * rb.iter().map(|y| y * y).sum::<f64>()).sqrt();
+
+				assert!((s.corr(a, b).unwrap() - dot / norm).abs() < 1e-6, "{a} {b}");
+			}
+
+			assert_eq!(s.corr(a, a), Some(1.));
+		}
+
+		let options = second::Options { layout: Layout::Full, block: 1, threads: 1, ..options };
+		let full = second::build(&c, "results/_test/corr.second-full.dat", &options).unwrap();
+
+		assert_eq!(full.layout(), Layout::Full);
+		assert!(!Path::new("results/_test/corr.second-full.tri").exists() && !Path::new("results/_test/corr.second-full.rows").exists());
+
+		for a in dict.keys() {
+			assert_eq!(full.corrall(a), s.corrall(a));
+		}
+
+		// the artifact is served like any other copy of the correlation data
+		bincode::serialize_into(BufWriter::new(File::create("results/_test/corrindex.second.dat").unwrap()), &s).unwrap();
+		let sim = Source::Correlation("results/_test/corrindex.second.dat".into()).load().unwrap();
+
+		assert_eq!(sim.neighbors("cow", 3, &Filter::default()).unwrap().len(), 3);
+		assert_eq!(sim.corrall("pig"), s.corrall("pig"));
+	}
+
+	#[test]
+	/// Benchmark files of every format are read and scored with their coverage
+	fn benchmark () {
+		use benchmark::Format;
+
+		let dict = dict(&["cow", "moo", "pig", "barn"]);
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let e = Embedding::from_vectors("results/_test/emb.benchmark.dat", dict, HashMap::new(), 2, vec![
+			1., 0.,  1., 0.2,  0.5, 1.,  0., 1.,
+		]).unwrap();
+
+		let files = [
+			("combined.csv", "Word 1,Word 2,Human (mean)\ncow,moo,9\ncow,pig,5\nCow,barn,1\ncow,hay,3\n"),
+			("SimLex-999.txt", "word1\tword2\tPOS\tSimLex999\tconc(w1)\ncow\tmoo\tN\t9.1\tx\ncow\tpig\tN\t5\tx\ncow\tbarn\tN\t1\tx\ncow\thay\tN\t3\tx\n"),
+			("MEN_dataset_lemma_form_full", "cow-n moo-v 50.0\ncow-n pig-n 25.0\ncow-n barn-n 5.0\nhay-n cow-n 15.0\n"),
+		];
+
+		for (name, text) in files {
+			let fname = format!("results/_test/{name}");
+			std::fs::write(&fname, text).unwrap();
+
+			let format = Format::guess(&fname);
+			let pairs = benchmark::read(&fname, format).unwrap();
+
+			assert_eq!(pairs.len(), 4, "{format:?}");
+			assert_eq!((pairs[0].a.as_str(), pairs[0].b.as_str()), ("cow", "moo"), "{format:?}");
+
+			let r = benchmark::evaluate(&e, name, format, &pairs);
+
+			assert_eq!((r.pairs, r.covered, r.coverage), (4, 3, 0.75), "{format:?}");
+			assert!((r.spearman - 1.).abs() < EPSILON, "{format:?}");
+			assert_eq!(r.missing, ["hay"]);
+		}
+
+		assert_eq!([Format::guess("ws353.tab"), Format::guess("a/SimLex-999.txt"), Format::guess("MEN_dataset_natural_form_full")], [Format::WordSim, Format::SimLex, Format::Men]);
+	}
+
+	#[test]
+	/// Drift between builds aligns words by name and finds the word that moved
+	fn drift () {
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		// the new build reorders words, drops "hay", adds "oink" and moves "pig" from cows to barns
+		let old = Embedding::from_vectors("results/_test/emb.old.dat", dict(&["cow", "moo", "pig", "barn", "shed", "hay"]), HashMap::new(), 2, vec![
+			1., 0.,  1., 0.1,  1., 0.2,  0., 1.,  0.1, 1.,  0.5, 0.5,
+		]).unwrap();
+		let new = Embedding::from_vectors("results/_test/emb.new.dat", dict(&["shed", "barn", "pig", "moo", "cow", "oink"]), HashMap::new(), 2, vec![
+			0.1, 1.,  0., 1.,  0.2, 1.,  1., 0.1,  1., 0.,  -1., 0.,
+		]).unwrap();
+
+		let d = drift::compare(&old, &new, &drift::Options { k: 1, threads: 2, ..Default::default() });
+
+		assert_eq!((d.shared, d.compared), (5, 5));
+		assert_eq!(d.added, ["oink"]);
+		assert_eq!(d.removed, ["hay"]);
+		assert_eq!(d.changed[0].word, "pig");
+		assert_eq!(d.changed[0].overlap, 0.);
+		assert_eq!((d.changed[0].lost.as_slice(), d.changed[0].gained.as_slice()), (&["moo".to_owned()][..], &["shed".to_owned()][..]));
+		assert!(d.changed.iter().filter(|w| w.word == "cow" || w.word == "barn").all(|w| w.overlap == 1.));
+
+		let same = drift::compare(&old, &old, &Default::default());
+
+		assert!((same.spearman - 1.).abs() < EPSILON && same.overlap == 1.);
+		assert!((drift::spearman(&[1., 2., 2., 3.], &[3., 2., 2., 1.]) + 1.).abs() < EPSILON);
+	}
+
+	#[test]
+	/// Both clusterings separate words drawn around distinct directions
+	fn cluster () {
+		use rand::Rng;
+		use cluster::{Method, Options};
+		use wikidle::database::error::ErrorKind;
+
+		let (n, dim) = (60, 6);
+		let name = |i: usize| format!("w{}{}", (b'a' + (i / 26) as u8) as char, (b'a' + (i % 26) as u8) as char);
+
+		let dict: Dict = (0..n)
+			.map(|i| (name(i), i as u32))
+			.collect();
+
+		// word i lies close to axis i % 3
+		let mut rng = SmallRng::seed_from_u64(3);
+		let vectors: Vec<f32> = (0..n * dim)
+			.map(|x| if x % dim == (x / dim) % 3 { 1. } else { 0. } + rng.gen_range(-0.2..0.2))
+			.collect();
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let e = Embedding::from_vectors("results/_test/emb.cluster.dat", dict, HashMap::new(), dim, vectors).unwrap();
+		let words: Vec<String> = (0..n).map(name).collect();
+		let words: Vec<&str> = words.iter().map(|w| w.as_str()).collect();
+
+		for method in [Method::KMeans, Method::Agglomerative] {
+			let c = cluster::cluster(&e, &words, &Options { method, k: 3, labels: 2, ..Default::default() }).unwrap();
+
+			assert_eq!(c.clusters.len(), 3);
+
+			for c in c.clusters.iter() {
+				let axis = words.iter().position(|w| *w == c.words[0]).unwrap() % 3;
+
+				assert_eq!(c.words.len(), n / 3, "{method:?}");
+				assert!(c.words.iter().all(|w| words.iter().position(|x| x == w).unwrap() % 3 == axis), "{method:?}");
+				assert_eq!(c.labels, c.words[..2]);
+				assert!(c.cohesion > 0.9);
+			}
+		}
+
+		// more clusters than directions, some emptied along the way and reseeded
+		for seed in 0..10 {
+			let c = cluster::cluster(&e, &words, &Options { k: 7, seed, ..Default::default() }).unwrap();
+
+			assert_eq!(c.clusters.len(), 7, "seed {seed}");
+			assert!(c.clusters.iter().all(|c| !c.words.is_empty()), "seed {seed}");
+		}
+
+		let c = cluster::cluster(&e, &words[..2], &Default::default()).unwrap();
+
+		assert_eq!(c.k, 2);
+		assert!(cluster::cluster(&e, &["nope"], &Default::default()).is_err());
+		assert_eq!(cluster::cluster(&e, &words, &Options { limit: 2, ..Default::default() }).map_err(|e| *e).err(), Some(ErrorKind::TooLarge));
+	}
+
+	#[test]
+	/// Vectors imported from every format give the same scores, keeping dictionary words only
+	fn import () {
+		use wikidle::database::{correlation::Pruned, export::Format, similarity::Source};
+
+		let dict = dict(&["cow", "moo", "pig", "barn"]);
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		// capitalized spellings only count when no exact one exists
+		let text = "4 3\nMoo 9 9 9\npig 0.5 -1 2\ncow 1 0 0\nmoo 0.5 0.5 0\nhorse 1 1 1\n";
+		std::fs::write("results/_test/import.vec", text).unwrap();
+
+		let w2v = Embedding::import("results/_test/import.vec", false, "results/_test/emb.import.dat", &dict).unwrap();
+
+		assert_eq!(w2v.dict().len(), 3);
+		assert_eq!(w2v.pruned().get("barn"), Some(&Pruned::Missing));
+		assert!((w2v.corr("cow", "moo").unwrap() - 0.5f64.sqrt()).abs() < 1e-6);
+
+		export::export(&w2v, Format::Glove, "results/_test/import.txt", 0).unwrap();
+		export::export(&w2v, Format::Word2VecBinary, "results/_test/import.bin", 0).unwrap();
+
+		let glove = Embedding::import("results/_test/import.txt", false, "results/_test/emb.glove.dat", &dict).unwrap();
+		let bin = Embedding::import("results/_test/import.bin", true, "results/_test/emb.bin.dat", &dict).unwrap();
+
+		bincode::serialize_into(BufWriter::new(File::create("results/_test/embindex.bin.dat").unwrap()), &bin).unwrap();
+		let served = Source::Embedding("results/_test/embindex.bin.dat".into()).load().unwrap();
+
+		for w in ["cow", "moo", "pig"] {
+			assert_eq!(glove.corrall(w), w2v.corrall(w));
+			assert_eq!(served.corrall(w), w2v.corrall(w));
+		}
+
+		let cut = std::fs::read("results/_test/import.bin").unwrap();
+		std::fs::write("results/_test/import.cut.bin", &cut[..cut.len() - 6]).unwrap();
+
+		assert!(Embedding::import("results/_test/import.cut.bin", true, "results/_test/emb.cut.dat", &dict).is_err());
+	}
+
+	#[test]
+	/// Exported vectors read back the same in every format
+	fn export () {
+		use export::Format;
+
+		let words = ["cow", "moo", "pig", "oink", "barn"];
+		let dict = dict(&words);
+		let vectors: Vec<f32> = (0..15).map(|i| i as f32 / 4. - 1.).collect();
+
+		std::fs::create_dir_all("results/_test").unwrap();
+
+		let e = Embedding::from_vectors("results/_test/emb.export.dat", dict, HashMap::new(), 3, vectors.clone()).unwrap();
+
+		let read = |f: Format| {
+			let fname = format!("results/_test/export.{}", f.extension());
+			export::export(&e, f, &fname, 2).unwrap();
+			std::fs::read(fname).unwrap()
+		};
+
+		let text = String::from_utf8(read(Format::Word2Vec)).unwrap();
+		let mut lines = text.lines();
+
+		assert_eq!(lines.next(), Some("5 3"));
+
+		for (i, l) in lines.enumerate() {
+			let mut l = l.split(' ');
+			assert_eq!(l.next(), Some(words[i]));
+			assert_eq!(l.map(|v| v.parse().unwrap()).collect::<Vec<f32>>(), vectors[i * 3..i * 3 + 3]);
+		}
+
+		assert_eq!(String::from_utf8(read(Format::Glove)).unwrap(), text.split_once('\n').unwrap().1);
+
+		let bin = read(Format::Word2VecBinary);
+		let mut at = 4;
+
+		for (i, w) in words.iter().enumerate() {
+			assert_eq!(&bin[at..at + w.len() + 1], format!("{w} ").as_bytes());
+			at += w.len() + 1;
+
+			for j in 0..3 {
+				assert_eq!(f32::from_le_bytes(bin[at..at + 4].try_into().unwrap()), vectors[i * 3 + j]);
+				at += 4;
+			}
+
+			assert_eq!(bin[at], b'\n');
+			at += 1;
+		}
+
+		let npy = read(Format::Npy);
+		let len = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+
+		assert_eq!((10 + len) % 64, 0);
+		assert!(String::from_utf8_lossy(&npy[10..10 + len]).contains("'shape': (5, 3)"));
+
+		let got: Vec<f32> = npy[10 + len..].chunks(4)
+			.map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+			.collect();
+
+		assert_eq!(got, vectors);
+
+		let vocab = std::fs::read("results/_test/export.vocab.npy").unwrap();
+		let len = u16::from_le_bytes([vocab[8], vocab[9]]) as usize;
+
+		assert!(String::from_utf8_lossy(&vocab[10..10 + len]).contains("'descr': '<U4'"));
+		assert_eq!(vocab.len(), 10 + len + 5 * 16);
+
+		// the archive holds both files as they are, followed by the end of its directory
+		let npz = read(Format::Npz);
+
+		assert_eq!(npz.windows(npy.len()).filter(|w| *w == npy.as_slice()).count(), 1);
+		assert_eq!(npz.windows(vocab.len()).filter(|w| *w == vocab.as_slice()).count(), 1);
+		assert_eq!(npz[npz.len() - 22..npz.len() - 18], [0x50, 0x4b, 0x05, 0x06]);
+
+		// zip64 sizes and offsets lead from the end of the directory to the data of both files
+		let u16_at = |i: usize| u16::from_le_bytes(npz[i..i + 2].try_into().unwrap()) as usize;
+		let u32_at = |i: usize| u32::from_le_bytes(npz[i..i + 4].try_into().unwrap());
+		let u64_at = |i: usize| u64::from_le_bytes(npz[i..i + 8].try_into().unwrap()) as usize;
+
+		let locator = npz.len() - 22 - 20;
+		assert_eq!(u32_at(locator), 0x07064b50);
+
+		let record = u64_at(locator + 8);
+		assert_eq!(u32_at(record), 0x06064b50);
+		assert_eq!(u64_at(record + 32), 2);
+		assert_eq!(u64_at(record + 40), record - u64_at(record + 48));
+
+		let mut at = u64_at(record + 48);
+
+		for (name, file) in [("matrix.npy", &npy), ("vocab.npy", &vocab)] {
+			let n = u16_at(at + 28);
+
+			assert_eq!(u32_at(at), 0x02014b50);
+			assert_eq!(&npz[at + 46..at + 46 + n], name.as_bytes());
+			assert_eq!((u32_at(at + 20), u32_at(at + 24), u32_at(at + 42)), (u32::MAX, u32::MAX, u32::MAX));
+			assert_eq!((u16_at(at + 46 + n), u16_at(at + 48 + n)), (1, 24));
+			assert_eq!((u64_at(at + 50 + n), u64_at(at + 58 + n)), (file.len(), file.len()));
+
+			let local = u64_at(at + 66 + n);
+
+			assert_eq!(u32_at(local), 0x04034b50);
+			assert_eq!(u16_at(local + 26), n);
+			assert_eq!(u32_at(local + 14), u32_at(at + 16));
+			assert_eq!((u16_at(local + 30 + n), u16_at(local + 32 + n)), (1, 16));
+			assert_eq!((u64_at(local + 34 + n), u64_at(local + 42 + n)), (file.len(), file.len()));
+
+			let data = local + 50 + n;
+
+			assert_eq!(&npz[data..data + file.len()], file.as_slice());
+			assert_eq!(u32_at(at + 16), crc32fast::hash(file));
+
+			at += 46 + n + u16_at(at + 30);
+		}
+
+		assert_eq!(at, record);
+
+		let csv = String::from_utf8(read(Format::Csv)).unwrap();
+
+		assert_eq!(csv.lines().count(), 1 + 5 * 2);
+		assert!(csv.lines().skip(1).all(|l| l.split(',').nth(2) != Some("0")));
 	}
 
 	#[test]
@@ -13,7 +13,7 @@
 	update(guesses, document.getElementById("guesses"));
 
 	function update (gs, t) {
-		gs.sort((a, b) => b.corr-a.corr); // sort by corr value
+		gs.sort((a, b) => (b.corr ?? -Infinity)-(a.corr ?? -Infinity)); // sort by corr value, unknown last
 
 		// clear table
 		t.innerHTML = "";
@@ -35,7 +35,7 @@
 			let row = t.tBodies[0].insertRow(-1);
 			row.insertCell(-1).innerHTML = e.id;
 			row.insertCell(-1).innerHTML = e.guess;
-			row.insertCell(-1).innerHTML = e.corr.toFixed(5);
+			row.insertCell(-1).innerHTML = e.corr === null ? "-" : e.corr.toFixed(5);
 			row.insertCell(-1).innerHTML = e.rank;
 		})
 	}